@@ -1,77 +1,475 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::io::Write;
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
 use anyhow::{anyhow, Context, Result};
+use archrypto_core::{format_iso8601, key_bits, ArchryptoError, KeyType, load_private_key, load_public_key};
+
+use crate::keystore::{FileBackedKeyStore, KeyInfo, KeyStore};
+
+/// 現在の設定ファイルのスキーマのバージョン。
+const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// 設定ファイルの統合を有効にする環境変数。
+const MERGE_CONFIGS_ENV: &str = "ARCHRYPT_MERGE_CONFIGS";
 
 /// Config は archrypt アプリケーションの設定情報を保持します。
-/// 公開鍵および秘密鍵のパスのリストと、各リストにおけるデフォルトのインデックスを管理します。
-#[derive(Serialize, Deserialize, Debug)]
+/// 公開鍵および秘密鍵は、それぞれの `KeyStore` で管理します。
+#[derive(Debug)]
 pub struct Config {
-    /// 登録されている公開鍵のパスのリスト
-    pub public_keys: Vec<PathBuf>,
-    /// public_keys 内でのデフォルト公開鍵のインデックス
-    pub default_public_key_index: Option<usize>,
-    /// 登録されている秘密鍵のパスのリスト
-    pub private_keys: Vec<PathBuf>,
-    /// private_keys 内でのデフォルト秘密鍵のインデックス
-    pub default_private_key_index: Option<usize>,
+    /// 設定ファイルのスキーマのバージョン。`load_from` で古いバージョンの設定を読み込んだ場合は現在のバージョンに移行します。
+    pub schema_version: u32,
+    /// 登録されている公開鍵とデフォルトの公開鍵
+    pub public_keystore: FileBackedKeyStore,
+    /// 登録されている秘密鍵とデフォルトの秘密鍵
+    pub private_keystore: FileBackedKeyStore,
+    /// 進捗バーの表示形式（`indicatif` のテンプレート）。None の場合はデフォルトの表示形式を使用します。
+    pub progress_template: Option<String>,
+    /// 進捗バーの塗りつぶしに使用する文字。None の場合はデフォルトの文字を使用します。
+    pub progress_chars: Option<String>,
+    /// この設定の読み込み元であり、`save()` の書き出し先となるパス。
+    /// None の場合はファイルと紐付かないインメモリの設定で、`save()` は何もしません。
+    path: Option<PathBuf>,
+}
+
+/// 設定ファイルに保存する形式。
+///
+/// `Config` の鍵ストアは、従来の `public_keys` / `default_public_key_index` などのフィールドとして保存します。
+#[derive(Serialize, Deserialize)]
+struct ConfigFile {
+    schema_version: u32,
+    public_keys: Vec<PathBuf>,
+    default_public_key_index: Option<usize>,
+    private_keys: Vec<PathBuf>,
+    default_private_key_index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    progress_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    progress_chars: Option<String>,
+}
+
+impl From<ConfigFile> for Config {
+    fn from(file: ConfigFile) -> Self {
+        Config {
+            schema_version: file.schema_version,
+            public_keystore: FileBackedKeyStore::new(KeyType::Public, file.public_keys, file.default_public_key_index),
+            private_keystore: FileBackedKeyStore::new(KeyType::Private, file.private_keys, file.default_private_key_index),
+            progress_template: file.progress_template,
+            progress_chars: file.progress_chars,
+            path: None,
+        }
+    }
+}
+
+impl From<&Config> for ConfigFile {
+    fn from(config: &Config) -> Self {
+        let (public_keys, default_public_key_index) = config.public_keystore.clone().into_parts();
+        let (private_keys, default_private_key_index) = config.private_keystore.clone().into_parts();
+        ConfigFile {
+            schema_version: config.schema_version,
+            public_keys,
+            default_public_key_index,
+            private_keys,
+            default_private_key_index,
+            progress_template: config.progress_template.clone(),
+            progress_chars: config.progress_chars.clone(),
+        }
+    }
 }
 
 impl Config {
+    /// ファイルと紐付かないインメモリの設定を作成します。
+    ///
+    /// 鍵のリストが空でない場合は、先頭の鍵をデフォルトに設定します。
+    /// この設定に対する `save()` は何もしません。
+    ///
+    /// # Parameters
+    ///
+    /// - `public_keys`: 公開鍵のパスのリスト
+    /// - `private_keys`: 秘密鍵のパスのリスト
+    pub fn new(public_keys: Vec<PathBuf>, private_keys: Vec<PathBuf>) -> Self {
+        Config {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            public_keystore: FileBackedKeyStore::new(
+                KeyType::Public,
+                public_keys.clone(),
+                (!public_keys.is_empty()).then_some(0),
+            ),
+            private_keystore: FileBackedKeyStore::new(
+                KeyType::Private,
+                private_keys.clone(),
+                (!private_keys.is_empty()).then_some(0),
+            ),
+            progress_template: None,
+            progress_chars: None,
+            path: None,
+        }
+    }
+
+    /// 鍵が登録されていない空のインメモリの設定を作成します。
+    ///
+    /// この設定に対する `save()` は何もしません。
+    pub fn in_memory() -> Self {
+        Self::new(Vec::new(), Vec::new())
+    }
+
+    /// 指定した公開鍵と秘密鍵をデフォルトとして登録したインメモリの設定を作成します。
+    ///
+    /// テストやライブラリへの組み込みなど、設定ファイルに依存せずに鍵を指定したい場合に使用します。
+    ///
+    /// # Parameters
+    ///
+    /// - `pub_key`: デフォルトの公開鍵のパス
+    /// - `priv_key`: デフォルトの秘密鍵のパス
+    #[allow(dead_code)]
+    pub fn with_defaults(pub_key: PathBuf, priv_key: PathBuf) -> Self {
+        Self::new(vec![pub_key], vec![priv_key])
+    }
+
     /// 設定ファイルのパスを返します。
     ///
-    /// 設定ファイルはホームディレクトリの `.archrypt/config.json` にあります。
+    /// `XDG_CONFIG_HOME` が設定されている場合は `$XDG_CONFIG_HOME/archrypt/config.json` を使用します。
+    /// 未設定の場合、Linux/BSD では `~/.config/archrypt/config.json`、
+    /// Windows では `%APPDATA%\archrypt\config.json`、macOS では従来通り `~/.archrypt/config.json` になります。
     ///
     /// # Errors
     ///
-    /// ホームディレクトリを取得できなかった場合、エラーを返します。
+    /// ホームディレクトリまたは設定ディレクトリを取得できなかった場合、エラーを返します。
     pub fn config_path() -> Result<PathBuf> {
+        if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+            return Ok(PathBuf::from(xdg).join("archrypt").join("config.json"));
+        }
+        if cfg!(target_os = "macos") {
+            return Self::legacy_config_path();
+        }
+        let config_dir = dirs::config_dir().context("Cannot determine config directory")?;
+        Ok(config_dir.join("archrypt").join("config.json"))
+    }
+
+    /// 全ての圧縮で適用するグローバルな除外ルールファイル（`<設定ディレクトリ>/ignore`）のパスを返します。
+    ///
+    /// # Errors
+    ///
+    /// 設定ディレクトリを取得できなかった場合、エラーを返します。
+    pub fn global_ignore_path() -> Result<PathBuf> {
+        let config_path = Self::config_path()?;
+        let config_dir = config_path.parent().context("Cannot determine config directory")?;
+        Ok(config_dir.join("ignore"))
+    }
+
+    /// インポートした鍵ファイルを保存するディレクトリ（設定ファイルと同じディレクトリの `keys`）を返します。
+    ///
+    /// # Errors
+    ///
+    /// インメモリの設定など、設定ファイルのパスを持たない場合にエラーを返します。
+    pub fn keys_dir(&self) -> Result<PathBuf> {
+        let path = self.path.as_ref().context("In-memory configuration has no keys directory")?;
+        Ok(Self::keys_dir_for(path))
+    }
+
+    /// 指定した設定ファイルに対応する、インポートした鍵ファイルの保存先ディレクトリを返します。
+    ///
+    /// # Parameters
+    ///
+    /// - `config_path`: 設定ファイルのパス
+    pub fn keys_dir_for(config_path: &Path) -> PathBuf {
+        let config_dir = match config_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        config_dir.join("keys")
+    }
+
+    /// 使用する設定ファイルのパスを決定します。
+    ///
+    /// 優先順位は CLI の `--config-path`、環境変数 `ARCHRYPT_CONFIG_PATH`、`config_path()` の順です。
+    ///
+    /// # Parameters
+    ///
+    /// - `cli_path`: `--config-path` で指定されたパス
+    ///
+    /// # Errors
+    ///
+    /// デフォルトのパスを取得できなかった場合、エラーを返します。
+    pub fn resolve_path(cli_path: Option<&Path>) -> Result<PathBuf> {
+        if let Some(path) = cli_path {
+            return Ok(path.to_path_buf());
+        }
+        if let Some(path) = std::env::var_os("ARCHRYPT_CONFIG_PATH").filter(|v| !v.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+        Self::config_path()
+    }
+
+    /// システム全体で共有する設定ファイルのパス（`/etc/archrypt/config.json`）を返します。
+    pub fn system_config_path() -> PathBuf {
+        PathBuf::from("/etc/archrypt/config.json")
+    }
+
+    /// 旧バージョンで使用していた設定ファイルのパス（`~/.archrypt/config.json`）を返します。
+    ///
+    /// # Errors
+    ///
+    /// ホームディレクトリを取得できなかった場合、エラーを返します。
+    pub fn legacy_config_path() -> Result<PathBuf> {
         let home = dirs::home_dir().context("Cannot determine home directory")?;
         Ok(home.join(".archrypt").join("config.json"))
     }
 
+    /// 旧パスに設定ファイルがあり、新しいパスに存在しない場合は新しいパスへコピーします。
+    ///
+    /// コピーした場合は移行した旨を一度だけ表示します。
+    ///
+    /// # Errors
+    ///
+    /// ディレクトリの作成またはファイルのコピーに失敗した場合、エラーを返します。
+    fn migrate_legacy_config(path: &Path) -> Result<()> {
+        let legacy = Self::legacy_config_path()?;
+        if legacy == path || path.exists() || !legacy.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&legacy, path)
+            .with_context(|| format!("Failed to migrate config file: {:?}", legacy))?;
+        eprintln!("Migrated configuration from {:?} to {:?}", legacy, path);
+        Ok(())
+    }
+
     /// 設定ファイルから設定情報を読み込みます。
     ///
+    /// 読み込むパスは `resolve_path()` で決定し、デフォルトのパスを使用する場合のみ旧パスからの移行を行います。
     /// ファイルが存在しない場合は、空の設定（空の鍵リスト、デフォルト未設定）を返します。
     ///
+    /// 環境変数 `ARCHRYPT_MERGE_CONFIGS=1` が設定されている場合は、読み込んだ設定にユーザーの設定（`config_path()`）、
+    /// システムの設定（`system_config_path()`）の順に `merge` します。デフォルトの鍵は、より優先度の高い設定のものを使用します。
+    /// 統合した設定を `save()` すると、統合された鍵のリストが読み込み元の設定ファイルに書き出されます。
+    ///
+    /// # Parameters
+    ///
+    /// - `cli_path`: `--config-path` で指定されたパス
+    ///
     /// # Errors
     ///
     /// 設定ファイルの読み込みまたはパースに失敗した場合、エラーを返します。
-    pub fn load() -> Result<Self> {
-        let path = Self::config_path()?;
+    pub fn load(cli_path: Option<&Path>) -> Result<Self> {
+        let path = Self::resolve_path(cli_path)?;
+        let user_path = Self::config_path()?;
+        if path == user_path {
+            Self::migrate_legacy_config(&path)?;
+        }
+        let mut config = Self::load_from(&path)?;
+        if std::env::var(MERGE_CONFIGS_ENV).is_ok_and(|v| v == "1") {
+            // 統合するだけの設定ファイルは、古いバージョンでも移行した内容を書き戻さない
+            if user_path != path {
+                config.merge(Self::read_from(&user_path, false)?);
+            }
+            let system_path = Self::system_config_path();
+            if system_path != path && system_path.exists() {
+                config.merge(Self::read_from(&system_path, false)?);
+            }
+        }
+        Ok(config)
+    }
+
+    /// 指定されたパスの設定ファイルから設定情報を読み込みます。
+    ///
+    /// ファイルが存在しない場合は、空の設定を返します。以降の `save()` はこのパスに書き出されます。
+    /// 古いバージョンの設定ファイルは現在のバージョンに移行し、移行した内容の書き戻しを試みます。
+    /// 書き戻しに失敗した場合（読み取り専用の設定ファイルなど）は警告を表示し、移行した設定をそのまま使用します。
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: 設定ファイルのパス
+    ///
+    /// # Errors
+    ///
+    /// 設定ファイルの読み込みまたはパースに失敗した場合、エラーを返します。
+    pub fn load_from(path: &Path) -> Result<Self> {
+        Self::read_from(path, true)
+    }
+
+    /// 指定されたパスの設定ファイルから設定情報を読み込みます。
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: 設定ファイルのパス
+    /// - `persist_migration`: 古いバージョンの設定ファイルを移行した場合に、移行した内容を書き戻すかどうか
+    ///
+    /// # Errors
+    ///
+    /// 設定ファイルの読み込みまたはパースに失敗した場合、エラーを返します。
+    fn read_from(path: &Path, persist_migration: bool) -> Result<Self> {
+        // 書き込み中断により設定ファイルが失われている場合はバックアップから復元
+        let backup = Self::backup_path(path);
+        if !path.exists() && backup.exists() {
+            fs::rename(&backup, path)
+                .with_context(|| format!("Failed to restore config file from backup: {:?}", backup))?;
+            eprintln!("Recovered configuration from backup {:?}", backup);
+        }
         if !path.exists() {
             return Ok(Config {
-                public_keys: Vec::new(),
-                default_public_key_index: None,
-                private_keys: Vec::new(),
-                default_private_key_index: None,
+                path: Some(path.to_path_buf()),
+                ..Self::in_memory()
             });
         }
-        let content = fs::read_to_string(&path)
+        let content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {:?}", path))?;
-        let config: Config = serde_json::from_str(&content)
+        // まずバージョンだけを読み取り、バージョンごとの形式でパースする
+        let version: ConfigVersion = serde_json::from_str(&content)
             .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+        let mut config = match version.schema_version {
+            1 => {
+                let v1: ConfigV1 = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+                let mut config = migrate_v1_to_v2(v1);
+                config.path = Some(path.to_path_buf());
+                if persist_migration {
+                    match config.save() {
+                        Ok(()) => eprintln!("Migrated configuration {:?} to schema version {}", path, CURRENT_SCHEMA_VERSION),
+                        Err(e) => eprintln!(
+                            "Warning: could not write the migrated configuration back to {:?} ({:#}); using it in memory only",
+                            path, e
+                        ),
+                    }
+                }
+                config
+            }
+            CURRENT_SCHEMA_VERSION => serde_json::from_str::<ConfigFile>(&content)
+                .map(Config::from)
+                .with_context(|| format!("Failed to parse config file: {:?}", path))?,
+            other => {
+                return Err(anyhow!(
+                    "Config file {:?} has schema version {}, but this version of acrp supports up to {}",
+                    path,
+                    other,
+                    CURRENT_SCHEMA_VERSION
+                ))
+            }
+        };
+        config.path = Some(path.to_path_buf());
         Ok(config)
     }
 
-    /// 現在の設定を設定ファイルに書き出します。
+    /// 現在の設定を読み込み元の設定ファイルに書き出します。
     ///
-    /// 必要なディレクトリも作成されます。
+    /// 必要なディレクトリも作成されます。インメモリの設定の場合は何もしません。
     ///
     /// # Errors
     ///
     /// 書き出しに失敗した場合、エラーを返します。
     pub fn save(&self) -> Result<()> {
-        let path = Self::config_path()?;
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+        match &self.path {
+            Some(path) => self.save_to(path),
+            None => Ok(()),
         }
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(&path, content)
+    }
+
+    /// 現在の設定を指定されたパスに書き出します。
+    ///
+    /// 必要なディレクトリも作成されます。
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: 書き出し先の設定ファイルのパス
+    ///
+    /// # Errors
+    ///
+    /// 書き出しに失敗した場合、エラーを返します。
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(parent)?;
+        let content = serde_json::to_string_pretty(&ConfigFile::from(self))?;
+
+        // 同じディレクトリの一時ファイルに書き出してから置き換えることで、書き込み中断による破損を防ぐ
+        let mut temp_file = NamedTempFile::new_in(parent)
+            .with_context(|| format!("Failed to write config file: {:?}", path))?;
+        temp_file.write_all(content.as_bytes())?;
+        temp_file.as_file().sync_all()?;
+
+        let backup = Self::backup_before_save(path)?;
+        temp_file
+            .persist(path)
             .with_context(|| format!("Failed to write config file: {:?}", path))?;
+        if let Some(backup) = backup {
+            fs::remove_file(backup)?;
+        }
         Ok(())
     }
 
+    /// 既存の設定ファイルを `config.json.bak` に退避し、退避先のパスを返します。
+    ///
+    /// 設定ファイルがまだ存在しない場合は何もせず None を返します。
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: 設定ファイルのパス
+    ///
+    /// # Errors
+    ///
+    /// 退避に失敗した場合、エラーを返します。
+    fn backup_before_save(path: &Path) -> Result<Option<PathBuf>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let backup = Self::backup_path(path);
+        fs::rename(path, &backup)
+            .with_context(|| format!("Failed to back up config file: {:?}", path))?;
+        Ok(Some(backup))
+    }
+
+    /// 設定ファイルのバックアップのパス（`<設定ファイル名>.bak`）を返します。
+    pub fn backup_path(path: &Path) -> PathBuf {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        PathBuf::from(backup)
+    }
+
+    /// 設定ファイルを `<設定ファイル名>.bak-<日時>` に退避し、鍵が登録されていない初期状態の設定を書き出します。
+    ///
+    /// 設定ファイルが壊れていても使用できるよう、現在の内容は読み込まずにそのまま退避します。
+    /// 設定ファイルが存在しない場合は退避せずに初期状態の設定を書き出します。
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: リセットする設定ファイルのパス
+    ///
+    /// # Returns
+    ///
+    /// 退避先のパスを返します。設定ファイルが存在しなかった場合は None を返します。
+    ///
+    /// # Errors
+    ///
+    /// 退避または書き出しに失敗した場合、エラーを返します。
+    pub fn reset(path: &Path) -> Result<Option<PathBuf>> {
+        let backup = if path.exists() {
+            let timestamp: String = format_iso8601(SystemTime::now()).chars().filter(|c| !matches!(c, '-' | ':')).collect();
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(format!(".bak-{}", timestamp));
+            let mut backup = PathBuf::from(backup);
+            // 同じ秒に複数回リセットした場合も以前の退避ファイルを上書きしない
+            let mut counter = 1;
+            while backup.exists() {
+                let mut numbered = path.as_os_str().to_owned();
+                numbered.push(format!(".bak-{}-{}", timestamp, counter));
+                backup = PathBuf::from(numbered);
+                counter += 1;
+            }
+            fs::copy(path, &backup).with_context(|| format!("Failed to back up config file: {:?}", path))?;
+            Some(backup)
+        } else {
+            None
+        };
+        Self::in_memory().save_to(path)?;
+        Ok(backup)
+    }
+
     /// 登録されている全ての公開鍵をクリアし、デフォルトの公開鍵設定をリセットします。
     ///
     /// その後、設定をファイルに保存します。
@@ -80,8 +478,7 @@ impl Config {
     ///
     /// 設定の保存に失敗した場合、エラーを返します。
     pub fn clear_public_key(&mut self) -> Result<()> {
-        self.public_keys = Vec::new();
-        self.default_public_key_index = None;
+        self.public_keystore.clear();
         self.save()?;
         Ok(())
     }
@@ -94,12 +491,48 @@ impl Config {
     ///
     /// 設定の保存に失敗した場合、エラーを返します。
     pub fn clear_private_key(&mut self) -> Result<()> {
-        self.private_keys = Vec::new();
-        self.default_private_key_index = None;
+        self.private_keystore.clear();
         self.save()?;
         Ok(())
     }
 
+    /// 進捗バーの表示形式（`indicatif` のテンプレート）を設定し、設定をファイルに保存します。
+    ///
+    /// # Parameters
+    ///
+    /// - `template`: 進捗バーのテンプレート。None の場合はデフォルトに戻します。
+    ///
+    /// # Errors
+    ///
+    /// テンプレートが不正な場合、または設定の保存に失敗した場合、エラーを返します。
+    pub fn set_progress_template(&mut self, template: Option<String>) -> Result<()> {
+        if let Some(template) = &template {
+            indicatif::ProgressStyle::with_template(template)
+                .map_err(|e| anyhow!("Invalid progress bar template {:?}: {}", template, e))?;
+        }
+        self.progress_template = template;
+        self.save()
+    }
+
+    /// 進捗バーの塗りつぶしに使用する文字を設定し、設定をファイルに保存します。
+    ///
+    /// # Parameters
+    ///
+    /// - `chars`: 塗りつぶしに使用する文字（例: `"=>-"`）。None の場合はデフォルトに戻します。
+    ///
+    /// # Errors
+    ///
+    /// 2文字未満の場合、または設定の保存に失敗した場合、エラーを返します。
+    pub fn set_progress_chars(&mut self, chars: Option<String>) -> Result<()> {
+        if let Some(chars) = &chars {
+            if chars.chars().count() < 2 {
+                return Err(anyhow!("Progress bar characters must have at least 2 characters: {:?}", chars));
+            }
+        }
+        self.progress_chars = chars;
+        self.save()
+    }
+
     /// 指定されたインデックスの公開鍵を削除します。
     ///
     /// 削除したキーがデフォルトの場合、デフォルト設定は解除され、削除したキーより後ろにある場合はインデックスが調整されます。
@@ -112,23 +545,7 @@ impl Config {
     ///
     /// インデックスが無効であるか、設定の保存に失敗した場合、エラーを返します。
     pub fn remove_public_key(&mut self, index: usize) -> Result<()> {
-        if index >= self.public_keys.len() {
-            return Err(anyhow!(
-                "Invalid index: {}. There are only {} public keys registered.",
-                index,
-                self.public_keys.len()
-            ));
-        }
-        self.public_keys.remove(index);
-
-        if let Some(default_index) = self.default_public_key_index {
-            if default_index == index {
-                self.default_public_key_index = None;
-            } else if default_index > index {
-                self.default_public_key_index = Some(default_index - 1);
-            }
-        }
-
+        self.public_keystore.remove_key(index)?;
         self.save()?;
         Ok(())
     }
@@ -145,38 +562,626 @@ impl Config {
     ///
     /// インデックスが無効であるか、設定の保存に失敗した場合、エラーを返します。
     pub fn remove_private_key(&mut self, index: usize) -> Result<()> {
-        if index >= self.private_keys.len() {
-            return Err(anyhow!(
-                "Invalid index: {}. There are only {} private keys registered.",
-                index,
-                self.private_keys.len()
-            ));
-        }
-        self.private_keys.remove(index);
-
-        if let Some(default_index) = self.default_private_key_index {
-            if default_index == index {
-                self.default_private_key_index = None;
-            } else if default_index > index {
-                self.default_private_key_index = Some(default_index - 1);
-            }
-        }
+        self.private_keystore.remove_key(index)?;
+        self.save()?;
+        Ok(())
+    }
+
+    /// 指定されたインデックスの公開鍵のパスを更新します。
+    ///
+    /// 鍵ファイルを移動した場合などに使用します。新しいパスは絶対パスに正規化して保存します。
+    ///
+    /// # Parameters
+    ///
+    /// - `index`: 更新する公開鍵のインデックス
+    /// - `new_path`: 新しい公開鍵ファイルのパス
+    ///
+    /// # Errors
+    ///
+    /// インデックスが無効である場合、新しいパスが存在しない場合、または設定の保存に失敗した場合、エラーを返します。
+    pub fn update_public_key_path(&mut self, index: usize, new_path: PathBuf) -> Result<()> {
+        self.public_keystore.update_key(index, new_path)?;
+        self.save()?;
+        Ok(())
+    }
+
+    /// 公開鍵の並び順を変更します。
+    ///
+    /// `from_index` の鍵を取り出して `to_index` に挿入し、間にある鍵を1つずつずらします。
+    /// デフォルトの公開鍵は移動後も同じ鍵を指すようにインデックスを調整します。
+    ///
+    /// # Parameters
+    ///
+    /// - `from_index`: 移動する公開鍵のインデックス
+    /// - `to_index`: 移動先のインデックス
+    ///
+    /// # Errors
+    ///
+    /// いずれかのインデックスが無効であるか、設定の保存に失敗した場合、エラーを返します。
+    pub fn reorder_public_key(&mut self, from_index: usize, to_index: usize) -> Result<()> {
+        self.public_keystore.move_key(from_index, to_index)?;
+        self.save()?;
+        Ok(())
+    }
 
+    /// 2つの公開鍵の位置を入れ替えます。
+    ///
+    /// 他の鍵のインデックスは変わりません。デフォルトの公開鍵は入れ替え後も同じ鍵を指すようにインデックスを調整します。
+    ///
+    /// # Parameters
+    ///
+    /// - `a`: 入れ替える公開鍵のインデックス
+    /// - `b`: 入れ替える公開鍵のインデックス
+    ///
+    /// # Errors
+    ///
+    /// いずれかのインデックスが無効であるか、設定の保存に失敗した場合、エラーを返します。
+    pub fn swap_public_keys(&mut self, a: usize, b: usize) -> Result<()> {
+        self.public_keystore.swap_keys(a, b)?;
         self.save()?;
         Ok(())
     }
 
+    /// 2つの秘密鍵の位置を入れ替えます。
+    ///
+    /// 他の鍵のインデックスは変わりません。デフォルトの秘密鍵は入れ替え後も同じ鍵を指すようにインデックスを調整します。
+    ///
+    /// # Parameters
+    ///
+    /// - `a`: 入れ替える秘密鍵のインデックス
+    /// - `b`: 入れ替える秘密鍵のインデックス
+    ///
+    /// # Errors
+    ///
+    /// いずれかのインデックスが無効であるか、設定の保存に失敗した場合、エラーを返します。
+    pub fn swap_private_keys(&mut self, a: usize, b: usize) -> Result<()> {
+        self.private_keystore.swap_keys(a, b)?;
+        self.save()?;
+        Ok(())
+    }
+
+    /// 指定されたインデックスの秘密鍵のパスを更新します。
+    ///
+    /// 鍵ファイルを移動した場合などに使用します。新しいパスは絶対パスに正規化して保存します。
+    ///
+    /// # Parameters
+    ///
+    /// - `index`: 更新する秘密鍵のインデックス
+    /// - `new_path`: 新しい秘密鍵ファイルのパス
+    ///
+    /// # Errors
+    ///
+    /// インデックスが無効である場合、新しいパスが存在しない場合、または設定の保存に失敗した場合、エラーを返します。
+    pub fn update_private_key_path(&mut self, index: usize, new_path: PathBuf) -> Result<()> {
+        self.private_keystore.update_key(index, new_path)?;
+        self.save()?;
+        Ok(())
+    }
+
+    /// 指定されたフィンガープリントを持つ公開鍵のインデックスを返します。
+    ///
+    /// 登録されている公開鍵を先頭から順に読み込んで比較し、最初に一致したものを返します。
+    /// フィンガープリントの `SHA256:` の接頭辞、コロン、大文字・小文字の違いは無視します。
+    ///
+    /// # Parameters
+    ///
+    /// - `fingerprint`: 検索するフィンガープリント（例: `SHA256:aa:bb:...`）
+    ///
+    /// # Errors
+    ///
+    /// 鍵ファイルの読み込みまたはパースに失敗した場合、エラーを返します。
+    pub fn find_public_key_by_fingerprint(&self, fingerprint: &str) -> Result<Option<usize>> {
+        self.public_keystore.find_by_fingerprint(fingerprint)
+    }
+
+    /// 指定されたフィンガープリント（対応する公開鍵のもの）を持つ秘密鍵のインデックスを返します。
+    ///
+    /// 登録されている秘密鍵を先頭から順に読み込んで比較し、最初に一致したものを返します。
+    /// フィンガープリントの `SHA256:` の接頭辞、コロン、大文字・小文字の違いは無視します。
+    ///
+    /// # Parameters
+    ///
+    /// - `fingerprint`: 検索するフィンガープリント（例: `SHA256:aa:bb:...`）
+    ///
+    /// # Errors
+    ///
+    /// 鍵ファイルの読み込みまたはパースに失敗した場合、エラーを返します。
+    pub fn find_private_key_by_fingerprint(&self, fingerprint: &str) -> Result<Option<usize>> {
+        self.private_keystore.find_by_fingerprint(fingerprint)
+    }
+
+    /// 登録されている公開鍵の一覧を、フィンガープリントやビット数などの情報とともに返します。
+    ///
+    /// フィンガープリントとビット数は、鍵ファイルが存在してパースできた場合のみ設定されます。
+    pub fn list_public_keys(&self) -> Vec<KeyInfo> {
+        self.public_keystore.key_infos()
+    }
+
+    /// 登録されている秘密鍵の一覧を、フィンガープリントやビット数などの情報とともに返します。
+    ///
+    /// フィンガープリントは対応する公開鍵のもので、鍵ファイルが存在してパースできた場合のみ設定されます。
+    pub fn list_private_keys(&self) -> Vec<KeyInfo> {
+        self.private_keystore.key_infos()
+    }
+
+    /// 他の設定の鍵を、この設定に統合します。
+    ///
+    /// `other` の鍵のうち、この設定に登録されていないもの（正規化したパスで比較）を末尾に追加します。
+    /// この設定にデフォルトの鍵がなく、`other` にある場合は、`other` のデフォルトの鍵をデフォルトにします。
+    /// 進捗バーの設定も、この設定で未設定の場合のみ `other` のものを使用します。
+    /// 読み込み元のパスはこの設定のものを維持します。
+    ///
+    /// # Parameters
+    ///
+    /// - `other`: 統合する設定
+    pub fn merge(&mut self, other: Config) -> &mut Self {
+        self.public_keystore.merge(other.public_keystore);
+        self.private_keystore.merge(other.private_keystore);
+        self.progress_template = self.progress_template.take().or(other.progress_template);
+        self.progress_chars = self.progress_chars.take().or(other.progress_chars);
+        self
+    }
+
     /// デフォルトの公開鍵への参照を返します。
     ///
-    /// デフォルトの公開鍵は `default_public_key_index` に基づいて決定されます。
+    /// `public_keystore` のデフォルトの鍵を返します。
     pub fn default_public_key(&self) -> Option<&PathBuf> {
-        self.default_public_key_index.and_then(|i| self.public_keys.get(i))
+        self.public_keystore.default_key()
     }
 
     /// デフォルトの秘密鍵への参照を返します。
     ///
-    /// デフォルトの秘密鍵は `default_private_key_index` に基づいて決定されます。
+    /// `private_keystore` のデフォルトの鍵を返します。
     pub fn default_private_key(&self) -> Option<&PathBuf> {
-        self.default_private_key_index.and_then(|i| self.private_keys.get(i))
+        self.private_keystore.default_key()
+    }
+
+    /// デフォルトの公開鍵への参照を返します。
+    ///
+    /// # Errors
+    ///
+    /// デフォルトの公開鍵が設定されていない場合、`ArchryptoError::NoDefaultKey` を返します。
+    pub fn default_public_key_or_err(&self) -> std::result::Result<&PathBuf, ArchryptoError> {
+        self.default_public_key()
+            .ok_or(ArchryptoError::NoDefaultKey(KeyType::Public))
+    }
+
+    /// デフォルトの秘密鍵への参照を返します。
+    ///
+    /// # Errors
+    ///
+    /// デフォルトの秘密鍵が設定されていない場合、`ArchryptoError::NoDefaultKey` を返します。
+    pub fn default_private_key_or_err(&self) -> std::result::Result<&PathBuf, ArchryptoError> {
+        self.default_private_key()
+            .ok_or(ArchryptoError::NoDefaultKey(KeyType::Private))
+    }
+
+    /// 使用する公開鍵のパスを決定します。
+    ///
+    /// `cli_override` が指定されている場合はそれを、指定されていない場合はデフォルトの公開鍵を使用します。
+    ///
+    /// # Parameters
+    ///
+    /// * `cli_override` - コマンドライン引数や環境変数で指定された公開鍵のパス。
+    ///
+    /// # Errors
+    ///
+    /// * `cli_override` が None でデフォルトの公開鍵も設定されていない場合、`ArchryptoError::NoKeyAvailable` を返します。
+    /// * 決定したパスにファイルが存在しない場合、`ArchryptoError::KeyFileNotFound` を返します。
+    /// * ファイルの存在を確認できなかった場合、`ArchryptoError::Io` を返します。
+    pub fn get_effective_public_key(&self, cli_override: Option<PathBuf>) -> std::result::Result<PathBuf, ArchryptoError> {
+        let path = match cli_override {
+            Some(path) => path,
+            None => self
+                .default_public_key_or_err()
+                .map_err(|_| ArchryptoError::NoKeyAvailable(KeyType::Public))?
+                .clone(),
+        };
+        ensure_key_file_exists(KeyType::Public, path)
+    }
+
+    /// 使用する秘密鍵のパスを決定します。
+    ///
+    /// `cli_override` が指定されている場合はそれを、指定されていない場合はデフォルトの秘密鍵を使用します。
+    ///
+    /// # Parameters
+    ///
+    /// * `cli_override` - コマンドライン引数や環境変数で指定された秘密鍵のパス。
+    ///
+    /// # Errors
+    ///
+    /// * `cli_override` が None でデフォルトの秘密鍵も設定されていない場合、`ArchryptoError::NoKeyAvailable` を返します。
+    /// * 決定したパスにファイルが存在しない場合、`ArchryptoError::KeyFileNotFound` を返します。
+    /// * ファイルの存在を確認できなかった場合、`ArchryptoError::Io` を返します。
+    pub fn get_effective_private_key(&self, cli_override: Option<PathBuf>) -> std::result::Result<PathBuf, ArchryptoError> {
+        let path = match cli_override {
+            Some(path) => path,
+            None => self
+                .default_private_key_or_err()
+                .map_err(|_| ArchryptoError::NoKeyAvailable(KeyType::Private))?
+                .clone(),
+        };
+        ensure_key_file_exists(KeyType::Private, path)
+    }
+}
+
+/// 鍵ファイルが存在する場合はそのパスを、存在しない場合は `ArchryptoError::KeyFileNotFound` を返します。
+fn ensure_key_file_exists(key_type: KeyType, path: PathBuf) -> std::result::Result<PathBuf, ArchryptoError> {
+    if !path.try_exists()? {
+        return Err(ArchryptoError::KeyFileNotFound { key_type, path });
+    }
+    Ok(path)
+}
+
+/// 設定ファイルのスキーマのバージョンのみを読み取るための構造体。
+#[derive(Deserialize)]
+struct ConfigVersion {
+    /// `schema_version` を持たない設定ファイルはバージョン 1 として扱う
+    #[serde(default = "schema_version_v1")]
+    schema_version: u32,
+}
+
+fn schema_version_v1() -> u32 {
+    1
+}
+
+/// バージョン 1（`schema_version` を持たない）の設定ファイルの形式。
+#[derive(Deserialize)]
+struct ConfigV1 {
+    public_keys: Vec<PathBuf>,
+    default_public_key_index: Option<usize>,
+    private_keys: Vec<PathBuf>,
+    default_private_key_index: Option<usize>,
+}
+
+/// バージョン 1 の設定を現在の形式（バージョン 2）に移行します。
+///
+/// バージョン 2 では `schema_version` が追加されたのみで、鍵の設定はそのまま引き継ぎます。
+fn migrate_v1_to_v2(v1: ConfigV1) -> Config {
+    Config::from(ConfigFile {
+        schema_version: 2,
+        public_keys: v1.public_keys,
+        default_public_key_index: v1.default_public_key_index,
+        private_keys: v1.private_keys,
+        default_private_key_index: v1.default_private_key_index,
+        progress_template: None,
+        progress_chars: None,
+    })
+}
+
+/// 設定の診断結果の重要度。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// 問題なし
+    Ok,
+    /// 動作はするが、確認が必要
+    Warning,
+    /// 圧縮・展開が失敗する設定
+    Error,
+}
+
+/// 診断の対象となった設定項目。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigField {
+    /// `public_keys` の指定されたインデックスの鍵
+    PublicKeys(usize),
+    /// `private_keys` の指定されたインデックスの鍵
+    PrivateKeys(usize),
+    /// `default_public_key_index`
+    DefaultPublicKeyIndex,
+    /// `default_private_key_index`
+    DefaultPrivateKeyIndex,
+}
+
+impl std::fmt::Display for ConfigField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigField::PublicKeys(i) => write!(f, "public_keys[{}]", i),
+            ConfigField::PrivateKeys(i) => write!(f, "private_keys[{}]", i),
+            ConfigField::DefaultPublicKeyIndex => write!(f, "default_public_key_index"),
+            ConfigField::DefaultPrivateKeyIndex => write!(f, "default_private_key_index"),
+        }
+    }
+}
+
+/// 設定の1項目についての診断結果。
+#[derive(Debug, Clone)]
+pub struct ConfigDiagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub field: ConfigField,
+}
+
+impl ConfigDiagnostic {
+    fn new(severity: Severity, field: ConfigField, message: String) -> Self {
+        ConfigDiagnostic { severity, message, field }
+    }
+}
+
+/// 設定の健全性をまとめて検査します。
+///
+/// 登録されている各鍵ファイルの存在とPEMとしての妥当性、鍵のビット数、
+/// 公開鍵・秘密鍵がそれぞれ1つ以上登録されているか、デフォルトのインデックスが範囲内かを確認します。
+///
+/// # Parameters
+///
+/// - `config`: 検査する設定
+///
+/// # Returns
+///
+/// 各項目の診断結果を返します。問題のない項目も `Severity::Ok` として含みます。
+pub fn verify_config(config: &Config) -> Vec<ConfigDiagnostic> {
+    let mut diagnostics = Vec::new();
+    for (i, path) in config.public_keystore.keys().iter().enumerate() {
+        let field = ConfigField::PublicKeys(i);
+        diagnostics.push(if !path.exists() {
+            ConfigDiagnostic::new(Severity::Error, field, format!("key file does not exist: {:?}", path))
+        } else {
+            match load_public_key(path) {
+                Ok(key) => ConfigDiagnostic::new(Severity::Ok, field, format!("{}-bit key {:?}", key_bits(&key), path)),
+                Err(e) => ConfigDiagnostic::new(Severity::Error, field, format!("not a valid PEM public key: {:?}: {}", path, e)),
+            }
+        });
+    }
+    for (i, path) in config.private_keystore.keys().iter().enumerate() {
+        let field = ConfigField::PrivateKeys(i);
+        diagnostics.push(if !path.exists() {
+            ConfigDiagnostic::new(Severity::Error, field, format!("key file does not exist: {:?}", path))
+        } else {
+            match load_private_key(path) {
+                Ok(key) => ConfigDiagnostic::new(Severity::Ok, field, format!("{}-bit key {:?}", key_bits(&key), path)),
+                Err(e) => ConfigDiagnostic::new(Severity::Error, field, format!("not a valid PKCS#8 PEM private key: {:?}: {}", path, e)),
+            }
+        });
+    }
+    diagnostics.push(check_default_index(
+        "public",
+        ConfigField::DefaultPublicKeyIndex,
+        config.public_keystore.default_index(),
+        config.public_keystore.keys().len(),
+    ));
+    diagnostics.push(check_default_index(
+        "private",
+        ConfigField::DefaultPrivateKeyIndex,
+        config.private_keystore.default_index(),
+        config.private_keystore.keys().len(),
+    ));
+    diagnostics
+}
+
+/// デフォルトのインデックスが鍵リストの範囲内にあるかを検査します。
+fn check_default_index(kind: &str, field: ConfigField, index: Option<usize>, len: usize) -> ConfigDiagnostic {
+    match index {
+        _ if len == 0 => ConfigDiagnostic::new(Severity::Warning, field, format!("no {} keys are registered", kind)),
+        None => ConfigDiagnostic::new(Severity::Warning, field, format!("no default {} key is set", kind)),
+        Some(index) if index >= len => ConfigDiagnostic::new(
+            Severity::Error,
+            field,
+            format!("default {} key index {} is out of range ({} keys registered)", kind, index, len),
+        ),
+        Some(index) => ConfigDiagnostic::new(Severity::Ok, field, format!("default {} key index {}", kind, index)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// バージョン 1 の設定ファイルの例。
+    const CONFIG_V1: &str = include_str!("../tests/fixtures/config_v1.json");
+
+    /// 一時ディレクトリにバージョン 1 の設定ファイルを書き出し、そのパスを返します。
+    fn write_v1_config(dir: &tempfile::TempDir) -> PathBuf {
+        let path = dir.path().join("config.json");
+        fs::write(&path, CONFIG_V1).unwrap();
+        path
+    }
+
+    /// バージョン 1 の設定ファイルの鍵の設定が、移行後の設定に引き継がれていることを確認します。
+    fn assert_migrated_fields(config: &Config) {
+        assert_eq!(config.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(
+            config.public_keystore.keys(),
+            [
+                PathBuf::from("/home/user/.archrypt/keys/alice.pub.pem"),
+                PathBuf::from("/home/user/.archrypt/keys/bob.pub.pem"),
+            ]
+        );
+        assert_eq!(config.public_keystore.default_index(), Some(1));
+        assert_eq!(config.private_keystore.keys(), [PathBuf::from("/home/user/.archrypt/keys/alice.pem")]);
+        assert_eq!(config.private_keystore.default_index(), Some(0));
+        assert_eq!(config.progress_template, None);
+        assert_eq!(config.progress_chars, None);
+    }
+
+    #[test]
+    fn load_from_migrates_v1_config_and_writes_it_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_v1_config(&dir);
+
+        let config = Config::load_from(&path).unwrap();
+        assert_migrated_fields(&config);
+
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["schema_version"], CURRENT_SCHEMA_VERSION);
+        assert_migrated_fields(&Config::load_from(&path).unwrap());
+    }
+
+    #[test]
+    fn read_from_without_persisting_leaves_v1_config_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_v1_config(&dir);
+
+        let config = Config::read_from(&path, false).unwrap();
+        assert_migrated_fields(&config);
+        assert_eq!(fs::read_to_string(&path).unwrap(), CONFIG_V1);
+    }
+
+    #[test]
+    fn load_from_keeps_migrated_config_when_write_back_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_v1_config(&dir);
+        // 退避先にディレクトリを置き、書き戻しの途中で失敗させる（root でも権限では失敗させられないため）
+        let backup = Config::backup_path(&path);
+        fs::create_dir(&backup).unwrap();
+        fs::write(backup.join("keep"), b"").unwrap();
+
+        let config = Config::load_from(&path).unwrap();
+        assert_migrated_fields(&config);
+        assert_eq!(fs::read_to_string(&path).unwrap(), CONFIG_V1);
+    }
+
+    #[test]
+    fn get_effective_key_prefers_cli_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let cli_key = dir.path().join("cli.pem");
+        fs::write(&cli_key, b"").unwrap();
+        let config = Config::with_defaults(dir.path().join("missing.pub.pem"), dir.path().join("missing.pem"));
+
+        assert_eq!(config.get_effective_public_key(Some(cli_key.clone())).unwrap(), cli_key);
+        assert_eq!(config.get_effective_private_key(Some(cli_key.clone())).unwrap(), cli_key);
+    }
+
+    #[test]
+    fn get_effective_key_falls_back_to_config_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let public_key = dir.path().join("default.pub.pem");
+        let private_key = dir.path().join("default.pem");
+        fs::write(&public_key, b"").unwrap();
+        fs::write(&private_key, b"").unwrap();
+        let config = Config::with_defaults(public_key.clone(), private_key.clone());
+
+        assert_eq!(config.get_effective_public_key(None).unwrap(), public_key);
+        assert_eq!(config.get_effective_private_key(None).unwrap(), private_key);
+    }
+
+    #[test]
+    fn get_effective_key_fails_without_cli_override_or_default() {
+        let config = Config::in_memory();
+
+        assert!(matches!(config.get_effective_public_key(None), Err(ArchryptoError::NoKeyAvailable(KeyType::Public))));
+        assert!(matches!(config.get_effective_private_key(None), Err(ArchryptoError::NoKeyAvailable(KeyType::Private))));
+    }
+
+    #[test]
+    fn get_effective_key_rejects_missing_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.pem");
+        let config = Config::in_memory();
+
+        assert!(matches!(
+            config.get_effective_public_key(Some(missing.clone())),
+            Err(ArchryptoError::KeyFileNotFound { key_type: KeyType::Public, path }) if path == missing
+        ));
+    }
+
+    #[test]
+    fn set_progress_template_rejects_invalid_template() {
+        let mut config = Config::in_memory();
+        assert!(config.set_progress_template(Some("{bar:abc}".to_string())).is_err());
+        assert_eq!(config.progress_template, None);
+
+        config.set_progress_template(Some("{wide_bar} {pos}/{len}".to_string())).unwrap();
+        assert_eq!(config.progress_template.as_deref(), Some("{wide_bar} {pos}/{len}"));
+    }
+
+    /// `dir` に空の鍵ファイルを作成し、そのパスを返します。
+    fn touch_key(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, b"").unwrap();
+        path
+    }
+
+    #[test]
+    fn merge_adds_a_key_reached_through_another_spelling_only_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let alice = touch_key(dir.path(), "alice.pub.pem");
+        let bob = touch_key(dir.path(), "bob.pub.pem");
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let alice_other_spelling = dir.path().join("sub").join("..").join("alice.pub.pem");
+
+        let mut config = Config::new(vec![alice.clone()], Vec::new());
+        config.merge(Config::new(vec![alice_other_spelling, bob.clone()], Vec::new()));
+        assert_eq!(config.public_keystore.keys(), [alice, bob]);
+        assert_eq!(config.public_keystore.default_index(), Some(0));
+    }
+
+    #[test]
+    fn merge_adopts_the_other_default_only_without_a_valid_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let alice = touch_key(dir.path(), "alice.pem");
+        let bob = touch_key(dir.path(), "bob.pem");
+        let other = || {
+            let mut other = Config::in_memory();
+            other.private_keystore = FileBackedKeyStore::new(KeyType::Private, vec![alice.clone(), bob.clone()], Some(1));
+            other
+        };
+
+        // 有効なデフォルトがある場合は維持する
+        let mut config = Config::new(Vec::new(), vec![alice.clone()]);
+        config.merge(other());
+        assert_eq!(config.default_private_key(), Some(&alice));
+
+        // デフォルトがない場合と、範囲外のインデックスの場合は other のデフォルトを使用する
+        for default_index in [None, Some(5)] {
+            let mut config = Config::in_memory();
+            config.private_keystore = FileBackedKeyStore::new(KeyType::Private, vec![alice.clone()], default_index);
+            config.merge(other());
+            assert_eq!(config.default_private_key(), Some(&bob));
+        }
+    }
+
+    #[test]
+    fn merge_keeps_own_progress_settings() {
+        let mut config = Config::in_memory();
+        config.progress_template = Some("{wide_bar}".to_string());
+        let mut other = Config::in_memory();
+        other.progress_template = Some("{bar:40}".to_string());
+        other.progress_chars = Some("#>-".to_string());
+
+        config.merge(other);
+        assert_eq!(config.progress_template.as_deref(), Some("{wide_bar}"));
+        assert_eq!(config.progress_chars.as_deref(), Some("#>-"));
+    }
+
+    #[test]
+    fn load_layers_the_user_config_when_merging_is_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let cli_key = touch_key(dir.path(), "cli.pub.pem");
+        let user_key = touch_key(dir.path(), "user.pub.pem");
+        let cli_path = dir.path().join("cli.json");
+        let mut cli_config = Config::in_memory();
+        cli_config.public_keystore = FileBackedKeyStore::new(KeyType::Public, vec![cli_key.clone()], None);
+        cli_config.save_to(&cli_path).unwrap();
+        let xdg = dir.path().join("xdg");
+        Config::new(vec![user_key.clone(), cli_key.clone()], Vec::new())
+            .save_to(&xdg.join("archrypt").join("config.json"))
+            .unwrap();
+
+        // 環境変数を変更するのはこのテストのみ
+        std::env::set_var("XDG_CONFIG_HOME", &xdg);
+        std::env::remove_var(MERGE_CONFIGS_ENV);
+        let unmerged = Config::load(Some(&cli_path)).unwrap();
+        std::env::set_var(MERGE_CONFIGS_ENV, "1");
+        let merged = Config::load(Some(&cli_path));
+        std::env::remove_var(MERGE_CONFIGS_ENV);
+        std::env::remove_var("XDG_CONFIG_HOME");
+        let merged = merged.unwrap();
+
+        assert_eq!(unmerged.public_keystore.keys(), std::slice::from_ref(&cli_key));
+        assert_eq!(&merged.public_keystore.keys()[..2], [cli_key, user_key.clone()]);
+        assert_eq!(merged.default_public_key(), Some(&user_key));
+        assert_eq!(merged.path.as_deref(), Some(cli_path.as_path()));
+    }
+
+    #[test]
+    fn default_key_or_err_reports_the_missing_key_type() {
+        let config = Config::in_memory();
+        assert!(matches!(config.default_public_key_or_err(), Err(ArchryptoError::NoDefaultKey(KeyType::Public))));
+        assert!(matches!(config.default_private_key_or_err(), Err(ArchryptoError::NoDefaultKey(KeyType::Private))));
+
+        let config = Config::with_defaults(PathBuf::from("public.pem"), PathBuf::from("private.pem"));
+        assert_eq!(config.default_public_key_or_err().unwrap(), Path::new("public.pem"));
+        assert_eq!(config.default_private_key_or_err().unwrap(), Path::new("private.pem"));
     }
 }
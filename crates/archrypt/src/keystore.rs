@@ -0,0 +1,476 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use archrypto_core::{key_bits, load_private_key, load_public_key, private_key_fingerprint, public_key_fingerprint, KeyType};
+
+/// 鍵の登録先を表すトレイト。
+///
+/// 鍵はインデックスで指定し、そのうち1つをデフォルトとして扱います。
+/// 現在の実装は設定ファイルに鍵ファイルのパスを保存する `FileBackedKeyStore` のみですが、
+/// OS のキーチェーンやハードウェアトークンなどの保存先もこのトレイトを実装することで扱えるようにします。
+pub trait KeyStore {
+    /// 登録されている鍵のパスのリストを返します。
+    fn keys(&self) -> &[PathBuf];
+
+    /// デフォルトの鍵のインデックスを返します（範囲外の値も、そのまま返します）。
+    fn default_index(&self) -> Option<usize>;
+
+    /// デフォルトの鍵への参照を返します。
+    fn default_key(&self) -> Option<&PathBuf> {
+        self.default_index().and_then(|i| self.keys().get(i))
+    }
+
+    /// 鍵を末尾に追加します。デフォルトの鍵が設定されていない場合は、先頭の鍵をデフォルトにします。
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: 追加する鍵ファイルのパス
+    fn add_key(&mut self, path: PathBuf);
+
+    /// 指定されたインデックスの鍵を削除し、削除した鍵のパスを返します。
+    ///
+    /// 削除した鍵がデフォルトの場合、デフォルト設定は解除され、削除した鍵より後ろにある場合はインデックスが調整されます。
+    ///
+    /// # Parameters
+    ///
+    /// - `index`: 削除する鍵のインデックス
+    ///
+    /// # Errors
+    ///
+    /// インデックスが無効である場合、エラーを返します。
+    fn remove_key(&mut self, index: usize) -> Result<PathBuf>;
+
+    /// 全ての鍵を削除し、デフォルト設定をリセットします。
+    fn clear(&mut self);
+
+    /// 指定されたインデックスの鍵をデフォルトに設定します。
+    ///
+    /// # Parameters
+    ///
+    /// - `index`: デフォルトにする鍵のインデックス
+    ///
+    /// # Errors
+    ///
+    /// インデックスが無効である場合、エラーを返します。
+    fn set_default(&mut self, index: usize) -> Result<()>;
+
+    /// 指定されたインデックスの鍵のパスを更新します。
+    ///
+    /// # Parameters
+    ///
+    /// - `index`: 更新する鍵のインデックス
+    /// - `new_path`: 新しい鍵ファイルのパス
+    ///
+    /// # Errors
+    ///
+    /// インデックスが無効である場合、または新しいパスが存在しない場合、エラーを返します。
+    fn update_key(&mut self, index: usize, new_path: PathBuf) -> Result<()>;
+
+    /// `from` の鍵を取り出して `to` に挿入し、間にある鍵を1つずつずらします。
+    /// デフォルトの鍵は移動後も同じ鍵を指すようにインデックスを調整します。
+    ///
+    /// # Parameters
+    ///
+    /// - `from`: 移動する鍵のインデックス
+    /// - `to`: 移動先のインデックス
+    ///
+    /// # Errors
+    ///
+    /// いずれかのインデックスが無効である場合、エラーを返します。
+    fn move_key(&mut self, from: usize, to: usize) -> Result<()>;
+
+    /// 2つの鍵の位置を入れ替えます。
+    /// デフォルトの鍵は入れ替え後も同じ鍵を指すようにインデックスを調整します。
+    ///
+    /// # Parameters
+    ///
+    /// - `a`: 入れ替える鍵のインデックス
+    /// - `b`: 入れ替える鍵のインデックス
+    ///
+    /// # Errors
+    ///
+    /// いずれかのインデックスが無効である場合、エラーを返します。
+    fn swap_keys(&mut self, a: usize, b: usize) -> Result<()>;
+
+    /// 指定されたフィンガープリントを持つ鍵のインデックスを返します。
+    ///
+    /// 登録されている鍵を先頭から順に読み込んで比較し、最初に一致したものを返します。
+    /// フィンガープリントの `SHA256:` の接頭辞、コロン、大文字・小文字の違いは無視します。
+    ///
+    /// # Parameters
+    ///
+    /// - `fingerprint`: 検索するフィンガープリント（例: `SHA256:aa:bb:...`）
+    ///
+    /// # Errors
+    ///
+    /// 鍵の読み込みまたはパースに失敗した場合、エラーを返します。
+    fn find_by_fingerprint(&self, fingerprint: &str) -> Result<Option<usize>>;
+}
+
+/// 登録されている鍵の一覧表示用の情報。
+#[derive(Debug, Clone)]
+pub struct KeyInfo {
+    /// 鍵のインデックス
+    pub index: usize,
+    /// 鍵ファイルのパス
+    pub path: PathBuf,
+    /// 鍵の名前（鍵ファイル名から拡張子を除いたもの）
+    #[allow(dead_code)]
+    pub name: Option<String>,
+    /// デフォルトの鍵であるかどうか
+    pub is_default: bool,
+    /// 鍵ファイルが存在するかどうか
+    pub exists: bool,
+    /// 鍵のフィンガープリント（秘密鍵の場合は対応する公開鍵のもの）。鍵を読み込めない場合は None です。
+    pub fingerprint: Option<String>,
+    /// 鍵のビット数。鍵を読み込めない場合は None です。
+    pub key_bits: Option<usize>,
+}
+
+/// 鍵ファイルのパスのリストとデフォルトのインデックスを保持する `KeyStore`。
+///
+/// 設定ファイルの `public_keys` / `default_public_key_index` など、従来の形式で保存されます。
+#[derive(Debug, Clone)]
+pub struct FileBackedKeyStore {
+    /// 保持している鍵の種類
+    key_type: KeyType,
+    /// 登録されている鍵のパスのリスト
+    keys: Vec<PathBuf>,
+    /// keys 内でのデフォルトの鍵のインデックス
+    default_index: Option<usize>,
+}
+
+impl FileBackedKeyStore {
+    /// 鍵のリストとデフォルトのインデックスから作成します。
+    ///
+    /// # Parameters
+    ///
+    /// - `key_type`: 保持する鍵の種類
+    /// - `keys`: 鍵のパスのリスト
+    /// - `default_index`: デフォルトの鍵のインデックス
+    pub fn new(key_type: KeyType, keys: Vec<PathBuf>, default_index: Option<usize>) -> Self {
+        FileBackedKeyStore { key_type, keys, default_index }
+    }
+
+    /// 鍵のリストとデフォルトのインデックスを取り出します。
+    pub fn into_parts(self) -> (Vec<PathBuf>, Option<usize>) {
+        (self.keys, self.default_index)
+    }
+
+    /// 他の `FileBackedKeyStore` の鍵のうち、登録されていないもの（正規化したパスで比較）を末尾に追加します。
+    ///
+    /// この鍵ストアにデフォルトの鍵がなく、`other` にある場合は、`other` のデフォルトの鍵をデフォルトにします。
+    ///
+    /// # Parameters
+    ///
+    /// - `other`: 統合する鍵ストア
+    pub fn merge(&mut self, other: FileBackedKeyStore) {
+        let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut seen: HashSet<PathBuf> = self.keys.iter().map(|path| canonical(path)).collect();
+        let other_default = other.default_key().map(|path| canonical(path));
+        for path in other.keys {
+            if seen.insert(canonical(&path)) {
+                self.keys.push(path);
+            }
+        }
+        if self.default_index.is_some_and(|i| i < self.keys.len()) {
+            return;
+        }
+        if let Some(index) = other_default.and_then(|wanted| self.keys.iter().position(|path| canonical(path) == wanted)) {
+            self.default_index = Some(index);
+        }
+    }
+
+    /// 登録されている鍵の一覧を、フィンガープリントなどの情報とともに返します。
+    ///
+    /// フィンガープリントとビット数は鍵ファイルが存在し、パースできた場合のみ設定します。
+    pub fn key_infos(&self) -> Vec<KeyInfo> {
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(index, path)| {
+                let exists = path.exists();
+                let (fingerprint, key_bits) = if exists {
+                    self.fingerprint_and_bits(path).map_or((None, None), |(fp, bits)| (Some(fp), Some(bits)))
+                } else {
+                    (None, None)
+                };
+                KeyInfo {
+                    index,
+                    path: path.clone(),
+                    name: path.file_stem().map(|stem| stem.to_string_lossy().into_owned()),
+                    is_default: self.default_index == Some(index),
+                    exists,
+                    fingerprint,
+                    key_bits,
+                }
+            })
+            .collect()
+    }
+
+    /// 鍵ファイルを読み込み、フィンガープリントとビット数を返します。
+    fn fingerprint_and_bits(&self, path: &Path) -> Result<(String, usize)> {
+        match self.key_type {
+            KeyType::Public => {
+                let key = load_public_key(path)?;
+                Ok((public_key_fingerprint(&key)?, key_bits(&key)))
+            }
+            KeyType::Private => {
+                let key = load_private_key(path)?;
+                Ok((private_key_fingerprint(&key)?, key_bits(&key)))
+            }
+        }
+    }
+
+    /// インデックスが範囲内であることを確認します。
+    fn check_index(&self, index: usize) -> Result<()> {
+        if index >= self.keys.len() {
+            return Err(anyhow!(
+                "Invalid index: {}. There are only {} {} keys registered.",
+                index,
+                self.keys.len(),
+                self.key_type
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl KeyStore for FileBackedKeyStore {
+    fn keys(&self) -> &[PathBuf] {
+        &self.keys
+    }
+
+    fn default_index(&self) -> Option<usize> {
+        self.default_index
+    }
+
+    fn add_key(&mut self, path: PathBuf) {
+        self.keys.push(path);
+        if self.default_index.is_none() {
+            self.default_index = Some(0);
+        }
+    }
+
+    fn remove_key(&mut self, index: usize) -> Result<PathBuf> {
+        self.check_index(index)?;
+        let removed = self.keys.remove(index);
+        self.default_index = default_index_after_remove(self.default_index, index);
+        Ok(removed)
+    }
+
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.default_index = None;
+    }
+
+    fn set_default(&mut self, index: usize) -> Result<()> {
+        self.check_index(index)?;
+        self.default_index = Some(index);
+        Ok(())
+    }
+
+    fn update_key(&mut self, index: usize, new_path: PathBuf) -> Result<()> {
+        self.check_index(index)?;
+        let new_path = fs::canonicalize(&new_path)
+            .with_context(|| format!("Key file not found: {:?}", new_path))?;
+        self.keys[index] = new_path;
+        Ok(())
+    }
+
+    fn move_key(&mut self, from: usize, to: usize) -> Result<()> {
+        self.check_index(from)?;
+        self.check_index(to)?;
+        let key = self.keys.remove(from);
+        self.keys.insert(to, key);
+        self.default_index = default_index_after_move(self.default_index, from, to);
+        Ok(())
+    }
+
+    fn swap_keys(&mut self, a: usize, b: usize) -> Result<()> {
+        self.check_index(a)?;
+        self.check_index(b)?;
+        self.keys.swap(a, b);
+        self.default_index = default_index_after_swap(self.default_index, a, b);
+        Ok(())
+    }
+
+    fn find_by_fingerprint(&self, fingerprint: &str) -> Result<Option<usize>> {
+        let wanted = normalize_fingerprint(fingerprint);
+        for (i, path) in self.keys.iter().enumerate() {
+            let key_fingerprint = match self.key_type {
+                KeyType::Public => {
+                    let key = load_public_key(path).with_context(|| format!("Failed to load public key: {:?}", path))?;
+                    public_key_fingerprint(&key)?
+                }
+                KeyType::Private => {
+                    let key = load_private_key(path).with_context(|| format!("Failed to load private key: {:?}", path))?;
+                    private_key_fingerprint(&key)?
+                }
+            };
+            if normalize_fingerprint(&key_fingerprint) == wanted {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// 比較のため、フィンガープリントから `SHA256:` の接頭辞とコロンを取り除き、小文字にします。
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    let fingerprint = fingerprint.trim();
+    let fingerprint = fingerprint
+        .strip_prefix("SHA256:")
+        .or_else(|| fingerprint.strip_prefix("sha256:"))
+        .unwrap_or(fingerprint);
+    fingerprint.replace(':', "").to_ascii_lowercase()
+}
+
+/// 鍵リストから `removed` 番目の要素を削除した後の、デフォルトのインデックスを返します。
+///
+/// 削除した鍵がデフォルトだった場合は None になります。デフォルトが削除位置より後ろにある場合、
+/// その鍵は削除により1つ前に詰められるため、インデックスを1つ減らします
+/// （デフォルトが末尾の要素でも同様で、減らした後のインデックスは常に新しい長さ未満になります）。
+///
+/// # Parameters
+///
+/// - `default_index`: 削除前のデフォルトのインデックス
+/// - `removed`: 削除した要素のインデックス
+fn default_index_after_remove(default_index: Option<usize>, removed: usize) -> Option<usize> {
+    match default_index {
+        Some(default_index) if default_index == removed => None,
+        Some(default_index) if default_index > removed => Some(default_index - 1),
+        other => other,
+    }
+}
+
+/// 鍵リストの `from` 番目の要素を `to` 番目に移動した後の、デフォルトのインデックスを返します。
+///
+/// 移動した鍵がデフォルトだった場合はデフォルトも `to` に移動します。それ以外の場合、
+/// デフォルトが移動元と移動先の間（移動先を含む）にあれば、その鍵は移動方向と逆に1つずれます。
+///
+/// # Parameters
+///
+/// - `default_index`: 移動前のデフォルトのインデックス
+/// - `from`: 移動した要素の移動前のインデックス
+/// - `to`: 移動した要素の移動後のインデックス
+fn default_index_after_move(default_index: Option<usize>, from: usize, to: usize) -> Option<usize> {
+    match default_index {
+        Some(default_index) if default_index == from => Some(to),
+        Some(default_index) if from < default_index && default_index <= to => Some(default_index - 1),
+        Some(default_index) if to <= default_index && default_index < from => Some(default_index + 1),
+        other => other,
+    }
+}
+
+/// 鍵リストの `a` 番目と `b` 番目の要素を入れ替えた後の、デフォルトのインデックスを返します。
+///
+/// # Parameters
+///
+/// - `default_index`: 入れ替え前のデフォルトのインデックス
+/// - `a`: 入れ替えた要素のインデックス
+/// - `b`: 入れ替えた要素のインデックス
+fn default_index_after_swap(default_index: Option<usize>, a: usize, b: usize) -> Option<usize> {
+    match default_index {
+        Some(default_index) if default_index == a => Some(b),
+        Some(default_index) if default_index == b => Some(a),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// `key0`, `key1`, ... という名前の鍵を `len` 個持つ鍵ストアを作成します。
+    fn store(len: usize, default_index: Option<usize>) -> FileBackedKeyStore {
+        let keys = (0..len).map(|i| PathBuf::from(format!("key{}", i))).collect();
+        FileBackedKeyStore::new(KeyType::Public, keys, default_index)
+    }
+
+    /// 鍵の数と、範囲内のデフォルトのインデックス（None を含む）の組を生成します。
+    fn len_and_default() -> impl Strategy<Value = (usize, Option<usize>)> {
+        (1usize..10).prop_flat_map(|len| (Just(len), proptest::option::of(0..len)))
+    }
+
+    proptest! {
+        #[test]
+        fn remove_key_keeps_default_key(
+            (len, default_index, removed) in len_and_default()
+                .prop_flat_map(|(len, default_index)| (Just(len), Just(default_index), 0..len))
+        ) {
+            let mut store = store(len, default_index);
+            let default_before = store.default_key().cloned();
+            let removed_key = store.remove_key(removed).unwrap();
+
+            prop_assert_eq!(store.keys().len(), len - 1);
+            if default_before.as_ref() == Some(&removed_key) {
+                prop_assert_eq!(store.default_index(), None);
+            } else {
+                prop_assert_eq!(store.default_key().cloned(), default_before);
+            }
+            if let Some(index) = store.default_index() {
+                prop_assert!(index < store.keys().len());
+            }
+        }
+
+        #[test]
+        fn move_key_keeps_default_key(
+            (len, default_index, from, to) in len_and_default()
+                .prop_flat_map(|(len, default_index)| (Just(len), Just(default_index), 0..len, 0..len))
+        ) {
+            check_move_keeps_default_key(len, default_index, from, to);
+        }
+
+        #[test]
+        fn swap_keys_keeps_default_key(
+            (len, default_index, a, b) in len_and_default()
+                .prop_flat_map(|(len, default_index)| (Just(len), Just(default_index), 0..len, 0..len))
+        ) {
+            let mut store = store(len, default_index);
+            let mut expected = store.keys().to_vec();
+            let default_before = store.default_key().cloned();
+            store.swap_keys(a, b).unwrap();
+
+            expected.swap(a, b);
+            prop_assert_eq!(store.keys(), expected.as_slice());
+            prop_assert_eq!(store.default_key().cloned(), default_before);
+        }
+    }
+
+    /// 鍵を移動した後も、デフォルトが同じ鍵を指していることを確認します。
+    fn check_move_keeps_default_key(len: usize, default_index: Option<usize>, from: usize, to: usize) {
+        let mut store = store(len, default_index);
+        let mut expected = store.keys().to_vec();
+        let default_before = store.default_key().cloned();
+        store.move_key(from, to).unwrap();
+
+        let key = expected.remove(from);
+        expected.insert(to, key);
+        assert_eq!(store.keys(), expected.as_slice());
+        assert_eq!(
+            store.default_key().cloned(),
+            default_before,
+            "len={} default={:?} from={} to={}",
+            len,
+            default_index,
+            from,
+            to
+        );
+    }
+
+    #[test]
+    fn move_key_keeps_default_key_exhaustive() {
+        for len in 1..=6 {
+            for default_index in std::iter::once(None).chain((0..len).map(Some)) {
+                for from in 0..len {
+                    for to in 0..len {
+                        check_move_keeps_default_key(len, default_index, from, to);
+                    }
+                }
+            }
+        }
+    }
+}
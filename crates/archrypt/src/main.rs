@@ -1,39 +1,37 @@
-use std::{process, path::PathBuf};
-use clap::{Arg, ArgAction, ArgGroup, Command};
-use archrypto_core::{compress_files, extract_files};
-use config::Config;
+use std::{io::IsTerminal, process, path::PathBuf, time::{Duration, Instant, SystemTime}};
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use archrypto_core::{cat_file, cat_files_matching, certificate_pem, check_revocation, compress_files, create_self_signed_rsa_cert, generate_rsa_keypair, save_private_key, diff_archives, entropy_available, list_archive_entries, merge_archives, compress_preencrypted_zip, compute_tree_checksum, expand_output_template, extract_files, format_iso8601, gc_temp_files, import_ssh_pubkey, load_private_key, public_key_fingerprint, public_key_pem, save_public_key, list_recipients, read_encrypted_metadata, test_decrypt, test_encrypt, read_paths_from_reader, run_benchmark, run_selftest, inspect_archive, stale_temp_files, validate_archive_structure, verify_archive_integrity, verify_keypair, ArchiveStats, ArchryptoError, ChangeType, CompressOptions, ConflictPolicy, ExtractOptions, FileTimingRecord, RenameStyle, KeySizeOverride, LimitPolicy, Manifest, NonUtf8Policy, OutputFormat, OutputNaming, OverwritePolicy, PathStyle, MAX_COMMENT_LEN};
+use std::path::Path;
+use config::{verify_config, Config, Severity};
+use keystore::{KeyInfo, KeyStore};
+use serde::Serialize;
 use std::fs;
+use std::io::Write;
 mod config;
+mod keystore;
 
 
+/// デバッグログの出力レベルを指定する環境変数（例: `ARCHRYPT_LOG=debug`）。
+const LOG_ENV: &str = "ARCHRYPT_LOG";
+
 fn main() {
+    // ARCHRYPT_LOG が設定されている場合のみ、archrypto_core のデバッグログを標準エラー出力に表示する
+    env_logger::Builder::from_env(env_logger::Env::new().filter(LOG_ENV)).init();
     let matches = build_cli().get_matches();
     
     match matches.subcommand() {
         Some(("pubkey", sub_m)) => {
             // 設定ファイルを読み込む
-            let mut config = Config::load().unwrap_or_else(|e| {
-                eprintln!("Failed to load configuration: {}", e);
-                process::exit(1);
-            });
+            let mut config = load_config(&matches);
 
             if sub_m.get_flag("list") {
                 // 登録されている公開鍵の一覧を表示
-                if config.public_keys.is_empty() {
+                let keys = config.list_public_keys();
+                if keys.is_empty() {
                     println!("No public keys registered.");
                 } else {
                     println!("Registered public keys:");
-                    for (i, key) in config.public_keys.iter().enumerate() {
-                        if let Some(default_index) = config.default_public_key_index {
-                            if i == default_index {
-                                println!("  {}: {:?} [default]", i, key);
-                            } else {
-                                println!("  {}: {:?}", i, key);
-                            }
-                        } else {
-                            println!("  {}: {:?}", i, key);
-                        }
-                    }
+                    print_key_infos(&keys);
                 }
             } else if let Some(new_key) = sub_m.get_one::<PathBuf>("add") {
                 let absolute_path = fs::canonicalize(new_key).unwrap_or_else(|e|{
@@ -41,11 +39,7 @@ fn main() {
                     process::exit(1);
                 });
                 // 公開鍵を追加
-                config.public_keys.push(absolute_path);
-                // もしデフォルトが未設定なら、最初の登録をデフォルトにするなどの処理
-                if config.default_public_key_index.is_none() {
-                    config.default_public_key_index = Some(0);
-                }
+                config.public_keystore.add_key(absolute_path);
                 config.save().unwrap_or_else(|e| {
                     eprintln!("Failed to save configuration: {}", e);
                     process::exit(1);
@@ -53,31 +47,115 @@ fn main() {
                 println!("Added public key: {:?}", new_key);
             } else if let Some(&index) = sub_m.get_one::<usize>("set") {
                 // 指定したインデックスをデフォルトに設定
-                if index >= config.public_keys.len() {
-                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.public_keys.len());
+                config.public_keystore.set_default(index).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
                     process::exit(1);
-                }
-                config.default_public_key_index = Some(index);
+                });
                 config.save().unwrap_or_else(|e| {
                     eprintln!("Failed to save configuration: {}", e);
                     process::exit(1);
                 });
                 println!("Set default public key to index {}", index);
             } else if let Some(&index) = sub_m.get_one::<usize>("delete") {
-                if index >= config.public_keys.len(){
-                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.public_keys.len());
+                if index >= config.public_keystore.keys().len(){
+                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.public_keystore.keys().len());
                     process::exit(1);
                 }
                 config.remove_public_key(index).unwrap_or_else(|e|{
                     eprintln!("{}",e);
                     process::exit(1);
                 });
+            } else if let Some(fingerprint) = sub_m.get_one::<String>("find-fingerprint") {
+                match config.find_public_key_by_fingerprint(fingerprint) {
+                    Ok(Some(index)) => println!("{}", index),
+                    Ok(None) => {
+                        eprintln!("No public key with fingerprint {} is registered.", fingerprint);
+                        process::exit(1);
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+            } else if let Some((index, new_path)) = parse_update_arg(sub_m) {
+                config.update_public_key_path(index, new_path.clone()).unwrap_or_else(|e|{
+                    eprintln!("{}",e);
+                    process::exit(1);
+                });
+                println!("Updated public key {} to {:?}", index, new_path);
 
+            } else if let Some(indices) = sub_m.get_many::<usize>("move") {
+                let indices: Vec<usize> = indices.copied().collect();
+                config.reorder_public_key(indices[0], indices[1]).unwrap_or_else(|e|{
+                    eprintln!("{}",e);
+                    process::exit(1);
+                });
+                println!("Moved public key {} to index {}", indices[0], indices[1]);
+            } else if let Some(indices) = sub_m.get_many::<usize>("swap") {
+                let indices: Vec<usize> = indices.copied().collect();
+                config.swap_public_keys(indices[0], indices[1]).unwrap_or_else(|e|{
+                    eprintln!("{}",e);
+                    process::exit(1);
+                });
+                println!("Swapped public keys {} and {}", indices[0], indices[1]);
             }  else if sub_m.get_flag("clear") {
                 config.clear_public_key().unwrap_or_else(|e|{
                     eprintln!("{}",e);
                     process::exit(1);
                 })
+            } else if let Some(ssh_file) = sub_m.get_one::<PathBuf>("import-ssh") {
+                import_ssh_keys(&mut config, ssh_file).unwrap_or_else(|e| {
+                    eprintln!("Failed to import SSH public keys: {}", e);
+                    process::exit(1);
+                });
+            } else if let Some(&index) = sub_m.get_one::<usize>("export-pem") {
+                let key_path = config.public_keystore.keys().get(index).unwrap_or_else(|| {
+                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.public_keystore.keys().len());
+                    process::exit(1);
+                });
+                let pem = fs::read_to_string(key_path).unwrap_or_else(|e| {
+                    eprintln!("Failed to read public key {:?}: {}", key_path, e);
+                    process::exit(1);
+                });
+                print!("{}", pem);
+            } else if let Some(&index) = sub_m.get_one::<usize>("test-encrypt") {
+                let key_path = config.public_keystore.keys().get(index).unwrap_or_else(|| {
+                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.public_keystore.keys().len());
+                    process::exit(1);
+                });
+                let (payload, ciphertext) = test_encrypt(key_path).unwrap_or_else(|e| {
+                    eprintln!("Failed to encrypt with public key {:?}: {}", key_path, e);
+                    process::exit(1);
+                });
+                // 暗号文のみを標準出力に出力し、復号結果と比較するための平文は標準エラー出力に表示する
+                eprintln!("Payload: {}", to_hex(&payload));
+                println!("{}", ciphertext);
+            } else if let Some(&index) = sub_m.get_one::<usize>("check-revocation") {
+                let key_path = config.public_keystore.keys().get(index).unwrap_or_else(|| {
+                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.public_keystore.keys().len());
+                    process::exit(1);
+                });
+                let crl_path = sub_m.get_one::<PathBuf>("crl").unwrap();
+                match check_revocation(key_path, crl_path) {
+                    Ok(serial) => println!("Certificate {} is not revoked.", serial),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+            } else if let Some(pair) = sub_m.get_many::<PathBuf>("verify-pair") {
+                let pair: Vec<&PathBuf> = pair.collect();
+                match verify_keypair(pair[0], pair[1]) {
+                    Ok(true) => println!("Keys match."),
+                    Ok(false) => {
+                        println!("Keys do NOT match.");
+                        process::exit(1);
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to verify key pair: {}", e);
+                        process::exit(1);
+                    }
+                }
             } else {
                 eprintln!("No valid pubkey option was provided.");
                 process::exit(1);
@@ -85,28 +163,16 @@ fn main() {
         }
         Some(("privatekey", sub_m)) => {
             // 設定ファイルを読み込む
-            let mut config = Config::load().unwrap_or_else(|e| {
-                eprintln!("Failed to load configuration: {}", e);
-                process::exit(1);
-            });
+            let mut config = load_config(&matches);
 
             if sub_m.get_flag("list") {
                 // 登録されている公開鍵の一覧を表示
-                if config.private_keys.is_empty() {
+                let keys = config.list_private_keys();
+                if keys.is_empty() {
                     println!("No private keys registered.");
                 } else {
                     println!("Registered private keys:");
-                    for (i, key) in config.private_keys.iter().enumerate() {
-                        if let Some(default_index) = config.default_private_key_index {
-                            if i == default_index {
-                                println!("  {}: {:?} [default]", i, key);
-                            } else {
-                                println!("  {}: {:?}", i, key);
-                            }
-                        } else {
-                            println!("  {}: {:?}", i, key);
-                        }
-                    }
+                    print_key_infos(&keys);
                 }
             } else if let Some(new_key) = sub_m.get_one::<PathBuf>("add") {
                 // 秘密鍵を追加
@@ -114,11 +180,7 @@ fn main() {
                     eprintln!("Error occured {}",e);
                     process::exit(1);
                 });
-                config.private_keys.push(absolute_path);
-                // もしデフォルトが未設定なら、最初の登録をデフォルトにするなどの処理
-                if config.default_private_key_index.is_none() {
-                    config.default_private_key_index = Some(0);
-                }
+                config.private_keystore.add_key(absolute_path);
                 config.save().unwrap_or_else(|e| {
                     eprintln!("Failed to save configuration: {}", e);
                     process::exit(1);
@@ -126,25 +188,74 @@ fn main() {
                 println!("Added private key: {:?}", new_key);
             } else if let Some(&index) = sub_m.get_one::<usize>("set") {
                 // 指定したインデックスをデフォルトに設定
-                if index >= config.private_keys.len() {
-                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.private_keys.len());
+                config.private_keystore.set_default(index).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
                     process::exit(1);
-                }
-                config.default_private_key_index = Some(index);
+                });
                 config.save().unwrap_or_else(|e| {
                     eprintln!("Failed to save configuration: {}", e);
                     process::exit(1);
                 });
                 println!("Set default private key to index {}", index);
             } else if let Some(&index) = sub_m.get_one::<usize>("delete") {
-                if index >= config.private_keys.len(){
-                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.private_keys.len());
+                if index >= config.private_keystore.keys().len(){
+                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.private_keystore.keys().len());
                     process::exit(1);
                 }
                 config.remove_private_key(index).unwrap_or_else(|e|{
                     eprintln!("{}",e);
                     process::exit(1);
                 });
+            } else if let Some(fingerprint) = sub_m.get_one::<String>("find-fingerprint") {
+                match config.find_private_key_by_fingerprint(fingerprint) {
+                    Ok(Some(index)) => println!("{}", index),
+                    Ok(None) => {
+                        eprintln!("No private key with fingerprint {} is registered.", fingerprint);
+                        process::exit(1);
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+            } else if let Some((index, new_path)) = parse_update_arg(sub_m) {
+                config.update_private_key_path(index, new_path.clone()).unwrap_or_else(|e|{
+                    eprintln!("{}",e);
+                    process::exit(1);
+                });
+                println!("Updated private key {} to {:?}", index, new_path);
+            } else if let Some(indices) = sub_m.get_many::<usize>("swap") {
+                let indices: Vec<usize> = indices.copied().collect();
+                config.swap_private_keys(indices[0], indices[1]).unwrap_or_else(|e|{
+                    eprintln!("{}",e);
+                    process::exit(1);
+                });
+                println!("Swapped private keys {} and {}", indices[0], indices[1]);
+            } else if let Some(&index) = sub_m.get_one::<usize>("export-public-pem") {
+                let key_path = config.private_keystore.keys().get(index).unwrap_or_else(|| {
+                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.private_keystore.keys().len());
+                    process::exit(1);
+                });
+                let pem = load_private_key(key_path)
+                    .and_then(|private_key| public_key_pem(&private_key.to_public_key()))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Failed to derive the public key from {:?}: {}", key_path, e);
+                        process::exit(1);
+                    });
+                print!("{}", pem);
+            } else if let Some(&index) = sub_m.get_one::<usize>("test-decrypt") {
+                let key_path = config.private_keystore.keys().get(index).unwrap_or_else(|| {
+                    eprintln!("Invalid index: {}. There are only {} keys registered.", index, config.private_keystore.keys().len());
+                    process::exit(1);
+                });
+                let ciphertext = sub_m.get_one::<String>("ciphertext").unwrap();
+                match test_decrypt(key_path, ciphertext) {
+                    Ok(payload) => println!("Payload: {}", to_hex(&payload)),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
             } else if sub_m.get_flag("clear") {
                 config.clear_private_key().unwrap_or_else(|e|{
                     eprintln!("{}",e);
@@ -155,45 +266,480 @@ fn main() {
                 process::exit(1);
             }
         }
+        Some(("inspect", sub_m)) => {
+            let archive = sub_m.get_one::<PathBuf>("archive").unwrap();
+            let header = inspect_archive(archive).unwrap_or_else(|e| {
+                eprintln!("Failed to read archive header: {}", e);
+                process::exit(1);
+            });
+            println!("Archive: {}", archive.display());
+            if header.version == 0 {
+                println!("Format version: 0 (legacy, no header)");
+            } else {
+                println!("Format version: {}", header.version);
+            }
+            match header.created_at {
+                Some(created_at) => println!("Created: {}", format_iso8601(created_at)),
+                None => println!("Created: (unknown)"),
+            }
+            println!("Comment: {}", header.comment.as_deref().unwrap_or("(none)"));
+            println!("Creator: {}", header.creator.as_deref().unwrap_or("(unknown)"));
+            println!("Tool version: {}", header.tool_version.as_deref().unwrap_or("(unknown)"));
+            match header.input_checksum {
+                Some(checksum) => println!("Input checksum: {}", to_hex(&checksum)),
+                None => println!("Input checksum: (none)"),
+            }
+            println!("Metadata: {}", header.metadata.as_deref().unwrap_or("(none)"));
+            if let Some(private_key) = sub_m.get_one::<PathBuf>("private-key") {
+                match read_encrypted_metadata(archive, private_key) {
+                    Ok(metadata) => println!(
+                        "Encrypted metadata: {}",
+                        metadata.map_or_else(|| "(none)".to_string(), |metadata| metadata.to_string())
+                    ),
+                    Err(e) => {
+                        eprintln!("Failed to read encrypted metadata: {}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+        Some(("verify", sub_m)) => {
+            let archive = sub_m.get_one::<PathBuf>("archive").unwrap();
+            if let Err(e) = validate_archive_structure(archive) {
+                eprintln!("Archive structure is invalid: {}", e);
+                process::exit(1);
+            }
+            println!("Archive structure: OK");
+            if let Some(paths) = sub_m.get_many::<PathBuf>("check-input-hash") {
+                let paths: Vec<PathBuf> = paths.cloned().collect();
+                let header = inspect_archive(archive).unwrap_or_else(|e| {
+                    eprintln!("Failed to read archive header: {}", e);
+                    process::exit(1);
+                });
+                let Some(expected) = header.input_checksum else {
+                    eprintln!("Archive has no input checksum (create it with --embed-input-checksum)");
+                    process::exit(1);
+                };
+                let actual = compute_tree_checksum(&paths).unwrap_or_else(|e| {
+                    eprintln!("Failed to compute input checksum: {}", e);
+                    process::exit(1);
+                });
+                if actual != expected {
+                    eprintln!("Input checksum mismatch: archive {}, inputs {}", to_hex(&expected), to_hex(&actual));
+                    process::exit(1);
+                }
+                println!("Input checksum: OK ({})", to_hex(&actual));
+            }
+            if let Some(manifest) = sub_m.get_one::<PathBuf>("manifest") {
+                let private_key = resolve_private_key(sub_m, &load_config(&matches));
+                let report = verify_archive_integrity(archive, manifest, &private_key).unwrap_or_else(|e| {
+                    eprintln!("Failed to verify archive against manifest: {}", e);
+                    process::exit(1);
+                });
+                for entry in &report.missing_entries {
+                    eprintln!("Missing entry: {}", entry);
+                }
+                for entry in &report.hash_mismatches {
+                    eprintln!("SHA-256 mismatch: {}", entry);
+                }
+                for entry in &report.unexpected_entries {
+                    eprintln!("Unexpected entry: {}", entry);
+                }
+                if report.tampered {
+                    eprintln!("Archive does not match {}: it may have been tampered with", manifest.display());
+                    process::exit(1);
+                }
+                println!("Manifest: OK");
+            }
+        }
+        Some(("verify-config", _)) => {
+            let config = load_config(&matches);
+            let diagnostics = verify_config(&config);
+            for diagnostic in &diagnostics {
+                print_diagnostic(diagnostic);
+            }
+            if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+                process::exit(1);
+            }
+        }
+        Some(("config", sub_m)) if sub_m.get_flag("reset") => {
+            // 壊れた設定ファイルもリセットできるよう、設定は読み込まずにパスのみ決定する
+            let cli_path = matches.get_one::<PathBuf>("config-path").map(PathBuf::as_path);
+            let path = Config::resolve_path(cli_path).unwrap_or_else(|e| {
+                eprintln!("Failed to determine the config file path: {}", e);
+                process::exit(1);
+            });
+            if !sub_m.get_flag("yes") && !confirm_typed_yes("This will remove all registered keys. Type 'yes' to confirm: ") {
+                eprintln!("Aborted: the configuration was not reset");
+                process::exit(1);
+            }
+            match Config::reset(&path) {
+                Ok(backup) => {
+                    if let Some(backup) = backup {
+                        println!("Backed up the previous configuration to {}", backup.display());
+                    }
+                    println!("Reset the configuration {}", path.display());
+                }
+                Err(e) => {
+                    eprintln!("Failed to reset the configuration: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(("config", sub_m)) => {
+            let mut config = load_config(&matches);
+            // 空文字列を指定した場合は設定を解除してデフォルトに戻す
+            let non_empty = |value: &String| Some(value.clone()).filter(|value| !value.is_empty());
+            if let Some(template) = sub_m.get_one::<String>("set-progress-template") {
+                if let Err(e) = config.set_progress_template(non_empty(template)) {
+                    eprintln!("Failed to set progress bar template: {}", e);
+                    process::exit(1);
+                }
+            }
+            if let Some(chars) = sub_m.get_one::<String>("set-progress-chars") {
+                if let Err(e) = config.set_progress_chars(non_empty(chars)) {
+                    eprintln!("Failed to set progress bar characters: {}", e);
+                    process::exit(1);
+                }
+            }
+            println!("progress_template: {}", config.progress_template.as_deref().unwrap_or("(default)"));
+            println!("progress_chars: {}", config.progress_chars.as_deref().unwrap_or("(default)"));
+        }
+        Some(("list-configs", _)) => {
+            list_configs(&matches);
+        }
+        Some(("list-recipients", sub_m)) => {
+            let archive = sub_m.get_one::<PathBuf>("archive").unwrap();
+            let recipients = list_recipients(archive).unwrap_or_else(|e| {
+                eprintln!("Failed to read archive recipients: {}", e);
+                process::exit(1);
+            });
+            for recipient in recipients {
+                match recipient.key_id {
+                    Some(key_id) => println!("{} ({} bytes)", key_id, recipient.key_size_bytes),
+                    None => println!("(unknown key, {} bytes)", recipient.key_size_bytes),
+                }
+            }
+        }
+        Some(("cat", sub_m)) => {
+            let archive = sub_m.get_one::<PathBuf>("input").unwrap();
+            let entry = sub_m.get_one::<String>("entry").unwrap();
+            let private_key = resolve_private_key(sub_m, &load_config(&matches));
+            let contents = if sub_m.get_flag("glob") {
+                cat_files_matching(archive, &private_key, entry).map(|entries| entries.into_iter().map(|(_, data)| data).collect())
+            } else {
+                cat_file(archive, &private_key, entry).map(|data| vec![data])
+            };
+            let contents: Vec<Vec<u8>> = contents.unwrap_or_else(|e| {
+                eprintln!("Failed to read {} from the archive: {}", entry, e);
+                process::exit(1);
+            });
+            if contents.is_empty() {
+                eprintln!("No entries match {}", entry);
+                process::exit(1);
+            }
+            let mut stdout = std::io::stdout().lock();
+            for data in contents {
+                if let Err(e) = stdout.write_all(&data) {
+                    eprintln!("Failed to write to stdout: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(("diff", sub_m)) => {
+            let archives: Vec<&PathBuf> = sub_m.get_many::<PathBuf>("archives").unwrap().collect();
+            let private_key = resolve_private_key(sub_m, &load_config(&matches));
+            let changes = diff_archives(archives[0], archives[1], &private_key).unwrap_or_else(|e| {
+                eprintln!("Failed to compare archives: {}", e);
+                process::exit(1);
+            });
+            for change in changes {
+                if sub_m.get_flag("quiet") && change.change_type == ChangeType::Unchanged {
+                    continue;
+                }
+                println!("{} {}", change.change_type.symbol(), change.entry_name);
+            }
+        }
+        Some(("list", sub_m)) => {
+            let archive = sub_m.get_one::<PathBuf>("archive").unwrap();
+            let private_key = resolve_private_key(sub_m, &load_config(&matches));
+            let entries = list_archive_entries(archive, &private_key).unwrap_or_else(|e| {
+                eprintln!("Failed to list archive entries: {}", e);
+                process::exit(1);
+            });
+            for entry in entries {
+                println!("{:>12}  {:<32}  {}", entry.size, entry.content_type.as_deref().unwrap_or("-"), entry.name);
+            }
+        }
+        Some(("merge", sub_m)) => {
+            let inputs: Vec<&PathBuf> = sub_m.get_many::<PathBuf>("inputs").unwrap().collect();
+            let output = sub_m.get_one::<PathBuf>("output").unwrap();
+            let cfg = load_config(&matches);
+            let private_key = resolve_private_key(sub_m, &cfg);
+            let public_key = resolve_public_key(sub_m, &cfg);
+            let conflict_policy = match sub_m.get_one::<String>("conflict").map(String::as_str) {
+                Some("last-wins") => ConflictPolicy::LastWins,
+                Some("error-on-conflict") => ConflictPolicy::ErrorOnConflict,
+                _ => ConflictPolicy::FirstWins,
+            };
+            match merge_archives(&inputs, &private_key, output, &public_key, conflict_policy) {
+                Ok(stats) => {
+                    println!("Merged {} files from {} archives.", stats.files_written, inputs.len());
+                    if let Some(output_path) = &stats.output_path {
+                        println!("{}", absolute_display(output_path));
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Merging archives failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(("keygen", sub_m)) => {
+            if let Err(e) = generate_keys(sub_m) {
+                eprintln!("Key generation failed: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(("selftest", _)) => {
+            let report = run_selftest();
+            println!("RSA key size: {} bits, cipher: {}", report.key_bits, report.cipher);
+            for step in &report.steps {
+                match &step.error {
+                    None => println!("[PASS] {} ({:.3}s)", step.name, step.elapsed.as_secs_f64()),
+                    Some(e) => println!("[FAIL] {} ({:.3}s): {}", step.name, step.elapsed.as_secs_f64(), e),
+                }
+            }
+            if !report.passed() {
+                eprintln!("Self-test failed");
+                process::exit(1);
+            }
+            println!("Self-test passed");
+        }
+        Some(("gc", sub_m)) => {
+            let max_age = *sub_m.get_one::<Duration>("max-age").unwrap();
+            let temp_dir = std::env::temp_dir();
+            let stale = stale_temp_files(&temp_dir, max_age).unwrap_or_else(|e| {
+                eprintln!("Failed to search {:?}: {}", temp_dir, e);
+                process::exit(1);
+            });
+            // 削除後はサイズを取得できないため、先にサイズを求めておく
+            let sizes: Vec<u64> = stale.iter().map(|path| fs::metadata(path).map_or(0, |m| m.len())).collect();
+            if sub_m.get_flag("dry-run") {
+                for path in &stale {
+                    println!("Would remove {}", path.display());
+                }
+                println!("{} files, {} bytes would be freed", stale.len(), sizes.iter().sum::<u64>());
+            } else {
+                let removed = gc_temp_files(&temp_dir, max_age).unwrap_or_else(|e| {
+                    eprintln!("Failed to remove stale temp files: {}", e);
+                    process::exit(1);
+                });
+                let freed: u64 = stale
+                    .iter()
+                    .zip(&sizes)
+                    .filter(|(path, _)| removed.contains(path))
+                    .map(|(_, size)| size)
+                    .sum();
+                for path in &removed {
+                    println!("Removed {}", path.display());
+                }
+                println!("{} files, {} bytes freed", removed.len(), freed);
+            }
+        }
+        Some(("bench", sub_m)) => {
+            let size = *sub_m.get_one::<u64>("size").unwrap();
+            println!("Benchmarking with {} bytes of random data...", size);
+            let results = run_benchmark(usize::try_from(size).unwrap_or(usize::MAX)).unwrap_or_else(|e| {
+                eprintln!("Benchmark failed: {}", e);
+                process::exit(1);
+            });
+            println!("{:<20} {:>12} {:>12} {:>12} {:>12}", "cipher", "encrypt", "MB/s", "decrypt", "MB/s");
+            for result in results {
+                println!(
+                    "{:<20} {:>11.3}s {:>12.1} {:>11.3}s {:>12.1}",
+                    result.cipher,
+                    result.encrypt.as_secs_f64(),
+                    result.encrypt_throughput(),
+                    result.decrypt.as_secs_f64(),
+                    result.decrypt_throughput(),
+                );
+            }
+        }
         _=>{
             //メインコマンド引数処理
-            let output_path: PathBuf = matches.get_one::<PathBuf>("output").unwrap().clone();
+            let manifest = load_manifest(&matches);
+            let compress_targets = compress_targets(&matches, manifest.as_ref());
+            let output_path: PathBuf = match matches.get_one::<String>("output-name-template") {
+                Some(template) => {
+                    let targets = compress_targets.as_deref().unwrap_or_default();
+                    expand_output_template(template, targets).unwrap_or_else(|e| {
+                        eprintln!("Failed to expand output name template: {}", e);
+                        process::exit(1);
+                    })
+                }
+                // --to-original-paths では出力先を使用しない
+                None => matches.get_one::<PathBuf>("output").cloned().unwrap_or_default(),
+            };
         
             //configload
-            let cfg = Config::load().unwrap_or_else(|e| {
-                eprintln!("Failed to load configuration: {}", e);
-                process::exit(1);
-            });
+            let cfg = load_config(&matches);
         
-            if  let Some(specify_files) = matches.get_many::<PathBuf>("compress"){
-                let files: Vec<PathBuf> = specify_files.cloned().collect();
-                let public_key: PathBuf = if let Some(pk) = matches.get_one::<PathBuf>("public-key") {
-                    pk.clone()
-                } else if let Some(default_pk) = cfg.default_public_key() {
-                    default_pk.clone().to_path_buf()
-                } else {
-                    eprintln!("Public key is not specified and no default is set.");
-                    process::exit(1);
-                };
+            if  let Some(files) = compress_targets {
+                let public_key = resolve_public_key(&matches, &cfg);
                 
                 
-                if let Err(e) = compress_files(&output_path,&public_key ,&files) {
-                    eprintln!("Compression failed: {}", e);
-                    process::exit(1);
+                let opts = CompressOptions {
+                    max_file_size: matches.get_one::<u64>("max-file-size").copied(),
+                    limit_policy: match matches.get_one::<String>("limit-policy").map(String::as_str) {
+                        Some("skip") => LimitPolicy::Skip,
+                        _ => LimitPolicy::Error,
+                    },
+                    on_skip: Some(warn_skipped),
+                    on_confirm: interactive_confirm(&matches),
+                    follow_symlinks: matches.get_flag("follow-symlinks"),
+                    max_depth: matches.get_one::<usize>("max-depth").copied(),
+                    min_depth: matches.get_one::<usize>("min-depth").copied(),
+                    comment: matches.get_one::<String>("comment").cloned(),
+                    creator: matches.get_one::<String>("creator").cloned(),
+                    metadata: matches.get_one::<serde_json::Value>("metadata").cloned(),
+                    encrypt_metadata: matches.get_flag("encrypt-metadata"),
+                    include_hidden: !matches.get_flag("no-hidden"),
+                    use_gitignore: matches.get_flag("gitignore"),
+                    use_archryptignore: !matches.get_flag("no-archryptignore"),
+                    global_ignore_file: Config::global_ignore_path()
+                        .ok()
+                        .filter(|path| path.exists() && !matches.get_flag("no-archryptignore")),
+                    embed_manifest: matches.get_flag("embed-manifest"),
+                    allow_empty: matches.get_flag("allow-empty"),
+                    max_entries: matches.get_one::<usize>("max-entries").copied(),
+                    use_temp_file: !matches.get_flag("no-temp-file"),
+                    target_overrides: manifest.map(|manifest| manifest.targets).unwrap_or_default(),
+                    path_style: match matches.get_one::<String>("path-style").map(String::as_str) {
+                        Some("relative") => PathStyle::RelativeToCommonAncestor,
+                        Some("absolute") => PathStyle::Absolute,
+                        _ => PathStyle::BaseName,
+                    },
+                    output_format: match matches.get_one::<String>("format").map(String::as_str) {
+                        Some("armored") => OutputFormat::Base64Armored,
+                        _ => OutputFormat::Binary,
+                    },
+                    pre_compress_hook: matches.get_one::<String>("pre-hook").cloned(),
+                    post_compress_hook: matches.get_one::<String>("post-hook").cloned(),
+                    key_size_override: KeySizeOverride::Enforce,
+                    embed_input_checksum: matches.get_flag("embed-input-checksum"),
+                    deduplicate: matches.get_flag("deduplicate"),
+                    output_naming: match matches.get_one::<String>("output-naming").map(String::as_str) {
+                        Some("content-hash") => OutputNaming::ContentHash,
+                        _ => OutputNaming::Specified,
+                    },
+                    non_utf8_policy: match matches.get_one::<String>("non-utf8").map(String::as_str) {
+                        Some("error") => NonUtf8Policy::Error,
+                        Some("skip") => NonUtf8Policy::Skip,
+                        _ => NonUtf8Policy::Lossy,
+                    },
+                    retry_count: *matches.get_one::<u32>("retry").unwrap(),
+                    retry_delay: *matches.get_one::<Duration>("retry-delay").unwrap(),
+                    sort_entries: matches.get_flag("reproducible"),
+                    fixed_timestamps: matches.get_flag("reproducible"),
+                    check_revocation: matches.get_one::<PathBuf>("crl").cloned(),
+                    record_timings: matches.get_flag("verbose"),
+                    emit_manifest: matches.get_flag("emit-manifest"),
+                    progress_template: cfg.progress_template.clone(),
+                    progress_chars: cfg.progress_chars.clone(),
+                    public_key_source: None,
+                    detect_content_type: matches.get_flag("detect-content-type"),
+                    resume: matches.get_flag("resume"),
+                };
+                if !entropy_available() {
+                    eprintln!("Warning: the OS random number generator reports low entropy; generating the encryption key may block");
+                }
+                let mut reporter = Reporter::new(&matches, "compress");
+                match compress_files(&output_path,&public_key ,&files, &opts) {
+                    Ok(stats) => {
+                        print_used_key(&stats, "public", "encryption");
+                        if stats.comment_truncated {
+                            eprintln!("Warning: comment exceeds {} bytes and was truncated", MAX_COMMENT_LEN);
+                        }
+                        if opts.record_timings {
+                            print_slowest_files(&stats.slowest_files);
+                        }
+                        if stats.files_resumed > 0 {
+                            eprintln!("Resumed {} of {} files from the interrupted run", stats.files_resumed, stats.files_written);
+                        }
+                        let written_path = stats.output_path.as_ref().unwrap_or(&output_path);
+                        let output_bytes = fs::metadata(written_path).map_or(0, |m| m.len());
+                        reporter.succeeded(written_path, stats.files_written, stats.total_bytes, output_bytes);
+                    }
+                    Err(e) => {
+                        eprintln!("Compression failed: {}", e);
+                        reporter.failed(&output_path, &e);
+                        drop(reporter);
+                        process::exit(1);
+                    }
+                }
+            }else if let Some(zip_path) = matches.get_one::<PathBuf>("wrap-zip") {
+                let public_key = resolve_public_key(&matches, &cfg);
+                let mut reporter = Reporter::new(&matches, "wrap-zip");
+                match compress_preencrypted_zip(zip_path, &public_key, &output_path) {
+                    Ok(stats) => {
+                        println!("Complete!");
+                        println!("{}", absolute_display(stats.output_path.as_ref().unwrap_or(&output_path)));
+                        let output_bytes = fs::metadata(&output_path).map_or(0, |m| m.len());
+                        reporter.succeeded(&output_path, stats.files_written, stats.total_bytes, output_bytes);
+                    }
+                    Err(e) => {
+                        eprintln!("Wrapping ZIP failed: {}", e);
+                        reporter.failed(&output_path, &e);
+                        drop(reporter);
+                        process::exit(1);
+                    }
                 }
             }else if let Some(extract_file) = matches.get_one::<PathBuf>("extract") {
-                
-                let private_key: PathBuf = if let Some(pk) = matches.get_one::<PathBuf>("private-key") {
-                    pk.clone()
-                } else if let Some(default_pk) = cfg.default_private_key() {
-                    default_pk.clone().to_path_buf()
-                } else {
-                    eprintln!("Private key is not specified and no configuration file found.");
-                    process::exit(1);
+                let private_key = resolve_private_key(&matches, &cfg);
+                let overwrite_policy = match matches.get_one::<String>("overwrite-policy").map(String::as_str) {
+                    Some("skip") => OverwritePolicy::Skip,
+                    Some("error") => OverwritePolicy::Error,
+                    Some("rename") => OverwritePolicy::Rename,
+                    _ => OverwritePolicy::Overwrite,
                 };
-                if let Err(e) = extract_files(extract_file,&private_key, &output_path) {
-                    eprintln!("Extraction failed: {}", e);
-                    process::exit(1);
+                let opts = ExtractOptions {
+                    flatten: matches.get_flag("flat"),
+                    overwrite_policy,
+                    rename_style: match matches.get_one::<String>("rename-style").map(String::as_str) {
+                        Some("finder") => RenameStyle::Finder,
+                        _ => RenameStyle::Counter,
+                    },
+                    dry_run: matches.get_flag("dry-run"),
+                    extract_threads: *matches.get_one::<usize>("threads").unwrap(),
+                    extract_to_original_paths: matches.get_flag("to-original-paths"),
+                    on_confirm: interactive_confirm(&matches),
+                    post_extract_hook: matches.get_one::<String>("post-extract-hook").cloned(),
+                    post_extract_hook_on_error: matches.get_flag("post-extract-hook-on-error"),
+                    verify_manifest: matches.get_one::<PathBuf>("verify-manifest").cloned(),
+                    progress_template: cfg.progress_template.clone(),
+                    progress_chars: cfg.progress_chars.clone(),
+                    private_key_source: None,
+                };
+                let mut reporter = Reporter::new(&matches, "extract");
+                match extract_files(extract_file,&private_key, &output_path, &opts) {
+                    Ok(stats) => {
+                        print_used_key(&stats, "private", "decryption");
+                        if opts.dry_run {
+                            println!("{} files, {} bytes would be extracted.", stats.files, stats.total_bytes);
+                        }
+                        for (entry, path) in &stats.renamed_files {
+                            println!("Renamed {} -> {}", entry, path.display());
+                        }
+                        let input_bytes = fs::metadata(extract_file).map_or(0, |m| m.len());
+                        reporter.succeeded(&output_path, stats.files_written, input_bytes, stats.total_bytes);
+                    }
+                    Err(e) => {
+                        eprintln!("Extraction failed: {:#}", e);
+                        reporter.failed(&output_path, &e);
+                        drop(reporter);
+                        process::exit(1);
+                    }
                 }
             }
 
@@ -202,47 +748,758 @@ fn main() {
 
 }
 
+/// `--config-path`、環境変数、デフォルトパスの優先順で設定ファイルを読み込みます。
+/// 読み込みに失敗した場合はプロセスを終了します。
+fn load_config(matches: &ArgMatches) -> Config {
+    let cli_path = matches.get_one::<PathBuf>("config-path").map(PathBuf::as_path);
+    let config = Config::load(cli_path).unwrap_or_else(|e| {
+        eprintln!("Failed to load configuration: {}", e);
+        process::exit(1);
+    });
+    if matches.get_flag("warn-on-startup") && matches.subcommand_name() != Some("verify-config") {
+        for diagnostic in verify_config(&config).iter().filter(|d| d.severity != Severity::Ok) {
+            print_diagnostic(diagnostic);
+        }
+    }
+    config
+}
+
+/// 設定ファイルなど、acrp が使用するパスとその有無、設定に影響する環境変数を表示します。
+fn list_configs(matches: &ArgMatches) {
+    let cli_path = matches.get_one::<PathBuf>("config-path").map(PathBuf::as_path);
+    let source = if cli_path.is_some() {
+        "--config-path"
+    } else if std::env::var_os("ARCHRYPT_CONFIG_PATH").is_some_and(|v| !v.is_empty()) {
+        "ARCHRYPT_CONFIG_PATH"
+    } else {
+        "default"
+    };
+    match Config::resolve_path(cli_path) {
+        Ok(config_path) => {
+            print_config_path(&format!("Config file ({})", source), &config_path);
+            print_config_path("Config backup", &Config::backup_path(&config_path));
+            print_config_path("Keys directory", &Config::keys_dir_for(&config_path));
+        }
+        Err(e) => println!("{:<24} (unavailable: {})", "Config file", e),
+    }
+    if let Ok(ignore_path) = Config::global_ignore_path() {
+        print_config_path("Global ignore file", &ignore_path);
+    }
+    print_config_path("System config file", &Config::system_config_path());
+    if let Ok(legacy_path) = Config::legacy_config_path() {
+        print_config_path("Legacy config file", &legacy_path);
+    }
+    print_config_path("Temp directory", &std::env::temp_dir());
+
+    println!("Environment:");
+    let mut any_set = false;
+    for name in ["ARCHRYPT_CONFIG_PATH", "ARCHRYPT_PUBLIC_KEY", "ARCHRYPT_PRIVATE_KEY", "ARCHRYPT_MERGE_CONFIGS", LOG_ENV, "XDG_CONFIG_HOME"] {
+        if let Some(value) = std::env::var_os(name) {
+            println!("  {}={}", name, value.to_string_lossy());
+            any_set = true;
+        }
+    }
+    if !any_set {
+        println!("  (no overriding variables set)");
+    }
+}
+
+/// `list-configs` の1行（項目名、パス、存在するかどうか）を表示します。
+fn print_config_path(label: &str, path: &Path) {
+    let status = if path.exists() { "exists" } else { "missing" };
+    println!("{:<24} {} ({})", label, path.display(), status);
+}
+
+/// 設定の診断結果を重要度に応じた色（緑: OK, 黄: 警告, 赤: エラー）で表示します。
+/// 警告とエラーは標準エラー出力に表示します。
+fn print_diagnostic(diagnostic: &config::ConfigDiagnostic) {
+    match diagnostic.severity {
+        Severity::Ok => println!("\x1b[32m[ OK  ]\x1b[0m {}: {}", diagnostic.field, diagnostic.message),
+        Severity::Warning => eprintln!("\x1b[33m[WARN ]\x1b[0m {}: {}", diagnostic.field, diagnostic.message),
+        Severity::Error => eprintln!("\x1b[31m[ERROR]\x1b[0m {}: {}", diagnostic.field, diagnostic.message),
+    }
+}
+
+/// `--manifest` が指定されている場合、マニフェストファイルを読み込みます。
+/// 読み込みに失敗した場合はプロセスを終了します。
+fn load_manifest(matches: &ArgMatches) -> Option<Manifest> {
+    let path = matches.get_one::<PathBuf>("manifest")?;
+    Some(Manifest::load(path).unwrap_or_else(|e| {
+        eprintln!("{:#}", e);
+        process::exit(1);
+    }))
+}
+
+/// `--compress` に指定されたパスと、`--manifest` に記述されたパス、
+/// `--stdin-paths` 指定時は標準入力から読み取ったパスを返します。
+/// 圧縮モードでない場合は None を返し、`--allow-empty` なしで対象パスが1つもない場合はプロセスを終了します。
+fn compress_targets(matches: &ArgMatches, manifest: Option<&Manifest>) -> Option<Vec<PathBuf>> {
+    if !matches.contains_id("compress") {
+        return None;
+    }
+    let mut targets: Vec<PathBuf> = matches
+        .get_many::<PathBuf>("compress")
+        .map(|paths| paths.cloned().collect())
+        .unwrap_or_default();
+    if let Some(manifest) = manifest {
+        targets.extend(manifest.target_paths());
+    }
+    if matches.get_flag("stdin-paths") {
+        let reader = std::io::BufReader::new(std::io::stdin());
+        for path in read_paths_from_reader(reader, matches.get_flag("null")) {
+            targets.push(path.unwrap_or_else(|e| {
+                eprintln!("Failed to read paths from stdin: {}", e);
+                process::exit(1);
+            }));
+        }
+    }
+    if targets.is_empty() && !matches.get_flag("allow-empty") {
+        eprintln!("No target paths were given (use --allow-empty to create an empty archive).");
+        process::exit(1);
+    }
+    Some(targets)
+}
+
+/// OpenSSH 形式の公開鍵ファイル（`id_rsa.pub` や `authorized_keys`）に含まれる `ssh-rsa` の鍵を
+/// PEM形式に変換して設定ディレクトリの `keys` に保存し、公開鍵として登録します。
+///
+/// 登録済みの鍵は再登録しません。`ssh-rsa` 以外の鍵の行は警告を表示して読み飛ばします。
+fn import_ssh_keys(config: &mut Config, ssh_file: &Path) -> anyhow::Result<()> {
+    let content = fs::read_to_string(ssh_file)?;
+    let keys_dir = config.keys_dir()?;
+    let mut imported = 0;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !line.split_whitespace().any(|field| field == "ssh-rsa") {
+            eprintln!("Warning: skipped a non ssh-rsa key: {}", line.split_whitespace().next().unwrap_or_default());
+            continue;
+        }
+        let public_key = import_ssh_pubkey(line)?;
+        let fingerprint = public_key_fingerprint(&public_key)?;
+        let file_name: String = fingerprint.trim_start_matches("SHA256:").replace(':', "").chars().take(16).collect();
+        let pem_path = keys_dir.join(format!("ssh-{}.pem", file_name));
+        if config.public_keystore.keys().contains(&pem_path) {
+            println!("Already registered: {:?}", pem_path);
+            continue;
+        }
+        fs::create_dir_all(&keys_dir)?;
+        save_public_key(&public_key, &pem_path)?;
+        config.public_keystore.add_key(pem_path.clone());
+        println!("Imported public key {}: {:?}", fingerprint, pem_path);
+        imported += 1;
+    }
+    if imported > 0 {
+        config.save()?;
+    }
+    Ok(())
+}
+
+/// RSA 鍵ペアを生成して PEM 形式で書き出し、`--output-cert` が指定されていれば自己署名証明書も書き出します。
+///
+/// 既存のファイルは上書きしません。
+fn generate_keys(sub_m: &ArgMatches) -> anyhow::Result<()> {
+    let private_key_path = sub_m.get_one::<PathBuf>("output-key").unwrap();
+    let public_key_path = sub_m.get_one::<PathBuf>("output-pubkey").unwrap();
+    let cert_path = sub_m.get_one::<PathBuf>("output-cert");
+    for path in [Some(private_key_path), Some(public_key_path), cert_path].into_iter().flatten() {
+        if path.exists() {
+            return Err(anyhow::anyhow!("{:?} already exists", path));
+        }
+    }
+    let (public_key, private_key) = generate_rsa_keypair(*sub_m.get_one::<usize>("bits").unwrap())?;
+    save_private_key(&private_key, private_key_path)?;
+    save_public_key(&public_key, public_key_path)?;
+    println!("Private key: {}", private_key_path.display());
+    println!("Public key: {} ({})", public_key_path.display(), public_key_fingerprint(&public_key)?);
+    if let Some(cert_path) = cert_path {
+        let subject = sub_m.get_one::<String>("subject").unwrap();
+        let der = create_self_signed_rsa_cert(&private_key, subject)?;
+        fs::write(cert_path, certificate_pem(&der)?)?;
+        println!("Certificate: {}", cert_path.display());
+    }
+    Ok(())
+}
+
+/// `--update INDEX PATH` の引数を解析します。指定されていない場合は None を返し、
+/// インデックスが数値でない場合はプロセスを終了します。
+fn parse_update_arg(sub_m: &ArgMatches) -> Option<(usize, PathBuf)> {
+    let mut values = sub_m.get_many::<String>("update")?;
+    let index = values.next()?;
+    let path = values.next()?;
+    let index = index.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid index: {}", index);
+        process::exit(1);
+    });
+    Some((index, PathBuf::from(path)))
+}
+
+/// 暗号化に使用する公開鍵のパスを決定します。
+/// 優先順位: --public-key > ARCHRYPT_PUBLIC_KEY > 設定ファイルのデフォルト。いずれもない場合や、鍵ファイルが存在しない場合はプロセスを終了します。
+fn resolve_public_key(matches: &ArgMatches, cfg: &Config) -> PathBuf {
+    let cli_override = matches.get_one::<PathBuf>("public-key").cloned().or_else(|| key_from_env("ARCHRYPT_PUBLIC_KEY"));
+    cfg.get_effective_public_key(cli_override).unwrap_or_else(|e| {
+        eprintln!("Public key is not available (--public-key or ARCHRYPT_PUBLIC_KEY): {}", e);
+        process::exit(1);
+    })
+}
+
+/// 復号に使用する秘密鍵のパスを決定します。
+/// 優先順位: --private-key > ARCHRYPT_PRIVATE_KEY > 設定ファイルのデフォルト。いずれもない場合や、鍵ファイルが存在しない場合はプロセスを終了します。
+fn resolve_private_key(matches: &ArgMatches, cfg: &Config) -> PathBuf {
+    let cli_override = matches.get_one::<PathBuf>("private-key").cloned().or_else(|| key_from_env("ARCHRYPT_PRIVATE_KEY"));
+    cfg.get_effective_private_key(cli_override).unwrap_or_else(|e| {
+        eprintln!("Private key is not available (--private-key or ARCHRYPT_PRIVATE_KEY): {}", e);
+        process::exit(1);
+    })
+}
+
+/// バイト列を小文字の16進数文字列に変換します。
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 環境変数で指定された鍵ファイルのパスを返します。未設定または空の場合は None を返します。
+fn key_from_env(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+/// 圧縮時にスキップしたファイルを警告として表示します。
+fn warn_skipped(path: &Path, reason: &str) {
+    eprintln!("Warning: skipped {}: {}", path.display(), reason);
+}
+
+/// `--report-file` に書き出す処理結果の JSON.
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    operation: &'static str,
+    exit: &'static str,
+    output: String,
+    files: usize,
+    input_bytes: u64,
+    output_bytes: u64,
+    duration_ms: u128,
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_message: Option<String>,
+}
+
+/// 処理結果を蓄積し、Drop 時に `--report-file` へ JSON として書き出します。
+/// `--report-file` が指定されていない場合は何も書き出しません。
+///
+/// `process::exit` では Drop が実行されないため、終了前に明示的に drop してください。
+struct Reporter {
+    /// 書き出し先。`-` の場合は標準出力に書き出します。
+    dest: Option<PathBuf>,
+    started: Instant,
+    report: Report,
+}
+
+impl Reporter {
+    fn new(matches: &ArgMatches, operation: &'static str) -> Self {
+        Reporter {
+            dest: matches.get_one::<PathBuf>("report-file").cloned(),
+            started: Instant::now(),
+            report: Report {
+                operation,
+                timestamp: format_iso8601(SystemTime::now()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// 処理が成功したことを記録します。
+    fn succeeded(&mut self, output: &Path, files: usize, input_bytes: u64, output_bytes: u64) {
+        self.report.exit = "ok";
+        self.report.output = absolute_display(output);
+        self.report.files = files;
+        self.report.input_bytes = input_bytes;
+        self.report.output_bytes = output_bytes;
+    }
+
+    /// 処理が失敗したことを、エラーの種類とメッセージとともに記録します。
+    fn failed(&mut self, output: &Path, err: &anyhow::Error) {
+        self.report.exit = "error";
+        self.report.output = absolute_display(output);
+        self.report.error_code = Some(error_code(err));
+        self.report.error_message = Some(err.to_string());
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        let Some(dest) = &self.dest else {
+            return;
+        };
+        self.report.duration_ms = self.started.elapsed().as_millis();
+        let json = match serde_json::to_string(&self.report) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("Failed to serialize report: {}", e);
+                return;
+            }
+        };
+        if dest.as_os_str() == "-" {
+            println!("{}", json);
+        } else if let Err(e) = fs::write(dest, json + "\n") {
+            eprintln!("Failed to write report file {}: {}", dest.display(), e);
+        }
+    }
+}
+
+/// レポートに記録するエラーの種類を返します。`ArchryptoError` 以外のエラーは `"Error"` とします。
+fn error_code(err: &anyhow::Error) -> &'static str {
+    match err.downcast_ref::<ArchryptoError>() {
+        Some(ArchryptoError::OverlappingPaths { .. }) => "OverlappingPaths",
+        Some(ArchryptoError::DuplicatePaths { .. }) => "DuplicatePaths",
+        Some(ArchryptoError::FileTooLarge { .. }) => "FileTooLarge",
+        Some(ArchryptoError::NoTargetPaths) => "NoTargetPaths",
+        Some(ArchryptoError::MaxEntriesExceeded { .. }) => "MaxEntriesExceeded",
+        Some(ArchryptoError::OutputNotWritable { .. }) => "OutputNotWritable",
+        Some(ArchryptoError::UnsafeEntryPath { .. }) => "UnsafeEntryPath",
+        Some(ArchryptoError::NoDefaultKey(_)) => "NoDefaultKey",
+        Some(ArchryptoError::ManifestMismatch { .. }) => "ManifestMismatch",
+        Some(ArchryptoError::KeyTooSmall { .. }) => "KeyTooSmall",
+        Some(ArchryptoError::UnsupportedVersion { .. }) => "UnsupportedVersion",
+        Some(ArchryptoError::CorruptedHeader { .. }) => "CorruptedHeader",
+        Some(ArchryptoError::ImplausibleKeySize { .. }) => "ImplausibleKeySize",
+        Some(ArchryptoError::TruncatedArchive { .. }) => "TruncatedArchive",
+        Some(ArchryptoError::NonUtf8Filename { .. }) => "NonUtf8Filename",
+        Some(ArchryptoError::KeyRevoked { .. }) => "KeyRevoked",
+        Some(ArchryptoError::EntryConflict { .. }) => "EntryConflict",
+        Some(ArchryptoError::ManifestVerificationFailed { .. }) => "ManifestVerificationFailed",
+        Some(ArchryptoError::TooManyConflicts { .. }) => "TooManyConflicts",
+        Some(ArchryptoError::KeyFileNotFound { .. }) => "KeyFileNotFound",
+        Some(ArchryptoError::NoKeyAvailable(_)) => "NoKeyAvailable",
+        Some(ArchryptoError::Io(_)) => "Io",
+        None => "Error",
+    }
+}
+
+/// パスを絶対パスの文字列に変換します。正規化できない場合はそのまま返します。
+fn absolute_display(path: &Path) -> String {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// `--interactive` が指定され、標準入力が端末の場合に確認用のコールバックを返します。
+fn interactive_confirm(matches: &ArgMatches) -> Option<fn(&str) -> bool> {
+    (matches.get_flag("interactive") && std::io::stdin().is_terminal()).then_some(confirm as fn(&str) -> bool)
+}
+
+/// `prompt` に `[y/N]` を付けて表示し、標準入力から1行読み取ります。`y` または `Y` の場合のみ true を返します。
+fn confirm(prompt: &str) -> bool {
+    eprint!("{} [y/N] ", prompt);
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y")
+}
+
+/// `prompt` を表示して標準入力から1行読み取り、`yes` と入力された場合のみ true を返します。
+fn confirm_typed_yes(prompt: &str) -> bool {
+    eprint!("{}", prompt);
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    answer.trim() == "yes"
+}
+
+/// `100M` や `1.5G` のような単位付きのサイズ指定をバイト数に変換します。
+///
+/// 単位は K, M, G, T（1024 単位、末尾の `B` / `iB` は省略可）に対応し、単位なしはバイトとして扱います。
+fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    let number_part = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (number, multiplier) = match number_part.chars().last() {
+        Some('K') => (&number_part[..number_part.len() - 1], 1u64 << 10),
+        Some('M') => (&number_part[..number_part.len() - 1], 1u64 << 20),
+        Some('G') => (&number_part[..number_part.len() - 1], 1u64 << 30),
+        Some('T') => (&number_part[..number_part.len() - 1], 1u64 << 40),
+        _ => (number_part, 1),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size: {}", value))?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(format!("invalid size: {}", value));
+    }
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// `500ms`, `30s`, `30m`, `24h`, `7d` のような期間の指定を解釈します。単位を省略した場合は秒として扱います。
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    if let Some(millis) = value.strip_suffix("ms") {
+        return millis
+            .trim()
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|_| format!("invalid duration: {}", value));
+    }
+    let (number, multiplier) = match value.chars().last() {
+        Some('s') => (&value[..value.len() - 1], 1),
+        Some('m') => (&value[..value.len() - 1], 60),
+        Some('h') => (&value[..value.len() - 1], 60 * 60),
+        Some('d') => (&value[..value.len() - 1], 24 * 60 * 60),
+        _ => (value, 1),
+    };
+    let number: u64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid duration: {}", value))?;
+    number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration: {}", value))
+}
+
+/// `--verbose` で、格納にかかった時間が長いファイルの一覧を表示します。
+fn print_slowest_files(records: &[FileTimingRecord]) {
+    if records.is_empty() {
+        return;
+    }
+    println!("Top {} slowest files:", records.len());
+    println!("{:>10} {:>14}  path", "seconds", "bytes");
+    for record in records {
+        println!("{:>10.3} {:>14}  {}", record.elapsed().as_secs_f64(), record.bytes, record.path.display());
+    }
+}
+
+/// 誤ってテスト用の鍵などを使用していないか気付けるよう、圧縮・展開に使用した鍵のビット数とフィンガープリントを表示します。
+fn print_used_key(stats: &ArchiveStats, key_kind: &str, purpose: &str) {
+    if let (Some(bits), Some(fingerprint)) = (stats.key_bits, &stats.key_fingerprint) {
+        eprintln!("Used RSA-{} {} key ({}) for {}", bits, key_kind, fingerprint, purpose);
+    }
+}
+
+/// `pubkey --list` / `privatekey --list` の鍵の一覧を表示します。
+fn print_key_infos(keys: &[KeyInfo]) {
+    for key in keys {
+        let details = match (&key.fingerprint, key.key_bits) {
+            (Some(fingerprint), Some(bits)) => format!("RSA-{}, {}", bits, fingerprint),
+            _ if !key.exists => "missing".to_string(),
+            _ => "unreadable".to_string(),
+        };
+        let default = if key.is_default { " [default]" } else { "" };
+        println!("  {}: {:?} ({}){}", key.index, key.path, details, default);
+    }
+}
+
+/// `--metadata` の値を JSON として解釈します。
+fn parse_metadata(value: &str) -> Result<serde_json::Value, String> {
+    serde_json::from_str(value).map_err(|e| format!("invalid JSON: {}", e))
+}
 
 ///コマンドのオプションの設定
 /// 
 fn build_cli() -> Command {
-    let matches = Command::new("acrp")
+    Command::new("acrp")
     .version("0.1")
     .subcommand_negates_reqs(true)
     .about("File compression and encryption tool")
+    .arg(Arg::new("config-path")
+        .long("config-path")
+        .global(true)
+        .value_parser(clap::value_parser!(PathBuf))
+        .help("Path to the config file (overrides ARCHRYPT_CONFIG_PATH and the default location)"))
+    .arg(Arg::new("warn-on-startup")
+        .long("warn-on-startup")
+        .global(true)
+        .action(ArgAction::SetTrue)
+        .help("Check the configuration when it is loaded and print any warnings or errors"))
     .arg(Arg::new("compress")
         .short('c')
         .long("compress")
         .help("Compress files")
-        .num_args(1..)
+        .num_args(0..)
         .value_parser(clap::value_parser!(PathBuf))
         .conflicts_with("extract")) // compressとextractは同時に使えない
+    .arg(Arg::new("interactive")
+        .long("interactive")
+        .action(ArgAction::SetTrue)
+        .help("Ask before overwriting the output file, compressing sensitive paths (/etc, ~/.ssh, ~/.gnupg) or extracting to original paths; ignored when stdin is not a terminal"))
+    .arg(Arg::new("no-temp-file")
+        .long("no-temp-file")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Encrypt the ZIP as it is built instead of spooling it to a temporary file first (ignored with --embed-input-checksum)"))
+    .arg(Arg::new("manifest")
+        .long("manifest")
+        .value_parser(clap::value_parser!(PathBuf))
+        .requires("compress")
+        .help("Read target paths and per-target settings (exclude_patterns, max_depth) from a TOML file"))
+    .arg(Arg::new("stdin-paths")
+        .long("stdin-paths")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Read additional target paths from stdin, one per line (blank lines and lines starting with '#' are ignored)"))
+    .arg(Arg::new("null")
+        .short('0')
+        .long("null")
+        .action(ArgAction::SetTrue)
+        .requires("stdin-paths")
+        .help("Paths read with --stdin-paths are separated by NUL characters (e.g. find -print0)"))
+    .arg(Arg::new("allow-empty")
+        .long("allow-empty")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Create an archive with no entries when no target paths are given"))
     .arg(Arg::new("extract")
         .short('x')
         .long("extract")
         .value_parser(clap::value_parser!(PathBuf))
         .help("Extract files")
         .conflicts_with("compress"))// compressとextractは同時に使えない
+    .arg(Arg::new("wrap-zip")
+        .long("wrap-zip")
+        .value_parser(clap::value_parser!(PathBuf))
+        .help("Encrypt an existing ZIP file (including ZipCrypto or AES-encrypted ZIPs) as-is, without recompressing it"))
     .arg(Arg::new("output")
         .short('o')
         .long("output")
         .value_parser(clap::value_parser!(PathBuf))
-        .required(true)
+        .required_unless_present_any(["output-name-template", "to-original-paths"])
+        .conflicts_with("output-name-template")
         .help("Output path for compressed file or extraction directory"))
+    .arg(Arg::new("output-name-template")
+        .long("output-name-template")
+        .requires("compress")
+        .help("Name the archive from a template instead of --output ({date}, {target}, {hash}; e.g. \"{date}_{target}_{hash}.acrp\")"))
     .arg(Arg::new("public-key")
         .short('p')
         .long("public-key")
         .value_parser(clap::value_parser!(PathBuf))
         //.required_if_eq("compress", "true")
-        .help("Path to the public key used for encryption"))
+        .help("Path to the public key used for encryption [env: ARCHRYPT_PUBLIC_KEY, takes precedence over the configured default]"))
     .arg(Arg::new("private-key")
         .short('k')
         .long("private-key")
         .value_parser(clap::value_parser!(PathBuf))
         .required_if_eq("extract", "true")
-        .help("Path to the private key used for decryption"))
+        .help("Path to the private key used for decryption [env: ARCHRYPT_PRIVATE_KEY, takes precedence over the configured default]"))
+    .arg(Arg::new("max-file-size")
+        .long("max-file-size")
+        .value_parser(parse_byte_size)
+        .requires("compress")
+        .help("Maximum size of a single file to archive (e.g. 100M, 2G)"))
+    .arg(Arg::new("max-entries")
+        .long("max-entries")
+        .value_parser(clap::value_parser!(usize))
+        .requires("compress")
+        .help("Abort if more than this many files would be archived (e.g. 100000)"))
+    .arg(Arg::new("path-style")
+        .long("path-style")
+        .value_parser(["base", "relative", "absolute"])
+        .default_value("base")
+        .requires("compress")
+        .help("How target paths appear in entry names: base name only, relative to the common ancestor, or absolute"))
+    .arg(Arg::new("pre-hook")
+        .long("pre-hook")
+        .requires("compress")
+        .help("Command to run before compressing, e.g. \"clamscan {files}\"; {files} expands to the target paths (appended if absent) and a non-zero exit aborts"))
+    .arg(Arg::new("post-hook")
+        .long("post-hook")
+        .requires("compress")
+        .help("Command to run after the archive is written, e.g. \"aws s3 cp {output} s3://bucket/\"; the stats are passed as JSON in ARCHRYPT_STATS"))
+    .arg(Arg::new("embed-input-checksum")
+        .long("embed-input-checksum")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Store a SHA-256 of the archived files (names, sizes and contents) in the header, for acrp verify --check-input-hash"))
+    .arg(Arg::new("deduplicate")
+        .long("deduplicate")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Store files with identical contents only once; later copies reference the first entry (uses memory per unique file)"))
+    .arg(Arg::new("format")
+        .long("format")
+        .value_parser(["binary", "armored"])
+        .default_value("binary")
+        .requires("compress")
+        .help("Output format: binary .acrp, or Base64 text wrapped in BEGIN/END ARCHRYPT ARCHIVE lines (detected automatically on extract)"))
+    .arg(Arg::new("output-naming")
+        .long("output-naming")
+        .value_parser(["path", "content-hash"])
+        .default_value("path")
+        .requires("compress")
+        .help("Name of the written archive: the --output path, or <first 8 hex digits of its SHA-256>.acrp in the same directory"))
+    .arg(Arg::new("non-utf8")
+        .long("non-utf8")
+        .value_parser(["lossy", "error", "skip"])
+        .default_value("lossy")
+        .requires("compress")
+        .help("How to store file names that are not valid UTF-8: replace invalid bytes with U+FFFD, fail, or skip the file"))
+    .arg(Arg::new("verbose")
+        .short('v')
+        .long("verbose")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Time each archived file and print the 10 slowest at the end"))
+    .arg(Arg::new("crl")
+        .long("crl")
+        .value_parser(clap::value_parser!(PathBuf))
+        .requires("compress")
+        .help("Refuse to encrypt if the public key is a certificate listed in this CRL (PEM or DER)"))
+    .arg(Arg::new("reproducible")
+        .long("reproducible")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Store entries sorted by name with a fixed modification time, so the same input produces the same ZIP (the encrypted bytes still differ)"))
+    .arg(Arg::new("retry")
+        .long("retry")
+        .value_parser(clap::value_parser!(u32))
+        .default_value("0")
+        .requires("compress")
+        .help("Retry creating the output and temp files this many times when they fail transiently (e.g. on NFS or SMB)"))
+    .arg(Arg::new("retry-delay")
+        .long("retry-delay")
+        .value_parser(parse_duration)
+        .default_value("500ms")
+        .requires("compress")
+        .help("Delay before the first retry, doubled on each further retry (e.g. 500ms, 2s)"))
+    .arg(Arg::new("limit-policy")
+        .long("limit-policy")
+        .value_parser(["skip", "error"])
+        .default_value("error")
+        .requires("compress")
+        .help("Whether files over --max-file-size are skipped with a warning or abort compression"))
+    .arg(Arg::new("follow-symlinks")
+        .long("follow-symlinks")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Follow symbolic links inside target directories (by default they are not archived)"))
+    .arg(Arg::new("max-depth")
+        .long("max-depth")
+        .value_parser(clap::value_parser!(usize))
+        .requires("compress")
+        .help("Maximum recursion depth inside target directories (files directly inside a target are depth 1)"))
+    .arg(Arg::new("min-depth")
+        .long("min-depth")
+        .value_parser(clap::value_parser!(usize))
+        .requires("compress")
+        .help("Minimum depth inside target directories; shallower files are not archived"))
+    .arg(Arg::new("no-hidden")
+        .long("no-hidden")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Exclude hidden files and directories (names starting with '.') inside target directories"))
+    .arg(Arg::new("gitignore")
+        .long("gitignore")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Exclude files matched by .gitignore rules inside target directories"))
+    .arg(Arg::new("no-archryptignore")
+        .long("no-archryptignore")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Ignore .archryptignore files and the global ignore file in the config directory"))
+    .arg(Arg::new("comment")
+        .long("comment")
+        .requires("compress")
+        .help("Comment stored in the archive header (not encrypted, up to 256 bytes)"))
+    .arg(Arg::new("metadata")
+        .long("metadata")
+        .value_parser(parse_metadata)
+        .requires("compress")
+        .help("JSON metadata stored in the archive header (not encrypted), e.g. '{\"project\":\"backup\",\"retention\":\"30d\"}'"))
+    .arg(Arg::new("encrypt-metadata")
+        .long("encrypt-metadata")
+        .action(ArgAction::SetTrue)
+        .requires("metadata")
+        .help("Store --metadata inside the encrypted payload instead of the header (shown by inspect --private-key)"))
+    .arg(Arg::new("creator")
+        .long("creator")
+        .requires("compress")
+        .help("Creator name stored in the archive header (not encrypted)"))
+    .arg(Arg::new("embed-manifest")
+        .long("embed-manifest")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Embed a MANIFEST.sha256 of all archived files, verified on extraction"))
+    .arg(Arg::new("emit-manifest")
+        .long("emit-manifest")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Also write <output>.manifest.json listing each entry's path, size, SHA-256 and mtime (not encrypted)"))
+    .arg(Arg::new("detect-content-type")
+        .long("detect-content-type")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Detect each file's MIME type from its magic bytes and record it in the archive (shown by `acrp list`)"))
+    .arg(Arg::new("resume")
+        .long("resume")
+        .action(ArgAction::SetTrue)
+        .requires("compress")
+        .help("Journal finished entries under ~/.archrypt/journal so that rerunning an interrupted compression with the same output skips them; the journal is deleted on success"))
+    .arg(Arg::new("flat")
+        .long("flat")
+        .action(ArgAction::SetTrue)
+        .requires("extract")
+        .help("Extract all files into the output directory without their directory structure"))
+    .arg(Arg::new("to-original-paths")
+        .long("to-original-paths")
+        .action(ArgAction::SetTrue)
+        .requires("extract")
+        .conflicts_with_all(["flat", "output"])
+        .help("DANGEROUS: instead of an output directory, write each file back to the absolute path it was archived from \
+               (archives created with --path-style absolute); existing system files may be overwritten"))
+    .arg(Arg::new("overwrite-policy")
+        .long("overwrite-policy")
+        .value_parser(["overwrite", "skip", "error", "rename"])
+        .default_value("overwrite")
+        .requires("extract")
+        .help("What to do when an extracted file already exists"))
+    .arg(Arg::new("rename-style")
+        .long("rename-style")
+        .value_parser(["counter", "finder"])
+        .default_value("counter")
+        .requires("extract")
+        .help("How --overwrite-policy rename names files: counter (name.txt.1) or finder (name (1).txt)"))
+    .arg(Arg::new("dry-run")
+        .long("dry-run")
+        .action(ArgAction::SetTrue)
+        .requires("extract")
+        .help("List the files that would be extracted without writing anything (the archive is still decrypted)"))
+    .arg(Arg::new("threads")
+        .long("threads")
+        .value_parser(clap::value_parser!(usize))
+        .default_value("1")
+        .requires("extract")
+        .help("Number of threads used to write extracted files"))
+    .arg(Arg::new("post-extract-hook")
+        .long("post-extract-hook")
+        .requires("extract")
+        .help("Command to run after extraction, e.g. \"bash deploy.sh {output_dir}\"; {output_dir} expands to the output directory (appended if absent) and the stats are passed as JSON in ARCHRYPT_STATS"))
+    .arg(Arg::new("post-extract-hook-on-error")
+        .long("post-extract-hook-on-error")
+        .action(ArgAction::SetTrue)
+        .requires("post-extract-hook")
+        .help("Also run --post-extract-hook when extraction fails partway, with the stats of the files extracted so far"))
+    .arg(Arg::new("verify-manifest")
+        .long("verify-manifest")
+        .value_name("MANIFEST_JSON")
+        .value_parser(clap::value_parser!(PathBuf))
+        .requires("extract")
+        .help("Before extracting, check every entry of a manifest written by --emit-manifest against the decrypted archive"))
+    .arg(Arg::new("report-file")
+        .long("report-file")
+        .value_parser(clap::value_parser!(PathBuf))
+        .help("Write a JSON summary of the operation to this file (\"-\" for stdout)"))
     .group(ArgGroup::new("mode")
-        .args(&["compress", "extract"])
+        .args(["compress", "extract", "wrap-zip"])
         .required(true))// グループ全体として必須
     .subcommand(
         Command::new("pubkey")
@@ -257,6 +1514,32 @@ fn build_cli() -> Command {
             .long("add")
             .value_parser(clap::value_parser!(PathBuf))
             .help("Add a public key to the configuration"))
+        .arg(Arg::new("find-fingerprint")
+            .long("find-fingerprint")
+            .value_name("FINGERPRINT")
+            .help("Print the index of the registered public key with this fingerprint (SHA256:aa:bb:...)"))
+        .arg(Arg::new("import-ssh")
+            .long("import-ssh")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Import ssh-rsa keys from an OpenSSH public key file (e.g. ~/.ssh/id_rsa.pub or authorized_keys)"))
+        .arg(Arg::new("update")
+            .short('u')
+            .long("update")
+            .num_args(2)
+            .value_names(["INDEX", "PATH"])
+            .help("Update the path of the public key at INDEX (e.g. after moving the key file)"))
+        .arg(Arg::new("move")
+            .long("move")
+            .num_args(2)
+            .value_names(["FROM", "TO"])
+            .value_parser(clap::value_parser!(usize))
+            .help("Move the public key at index FROM to index TO, shifting the keys in between"))
+        .arg(Arg::new("swap")
+            .long("swap")
+            .num_args(2)
+            .value_names(["A", "B"])
+            .value_parser(clap::value_parser!(usize))
+            .help("Swap the public keys at indices A and B, leaving the other indices unchanged"))
         .arg(Arg::new("set")
             .short('s')
             .long("set")
@@ -272,6 +1555,33 @@ fn build_cli() -> Command {
             .long("clear")
             .action(ArgAction::SetTrue)
             .help("All publickey setting remove"))
+        .arg(Arg::new("export-pem")
+            .long("export-pem")
+            .value_name("INDEX")
+            .value_parser(clap::value_parser!(usize))
+            .help("Print the PEM of the registered public key at INDEX to stdout"))
+        .arg(Arg::new("test-encrypt")
+            .long("test-encrypt")
+            .value_name("INDEX")
+            .value_parser(clap::value_parser!(usize))
+            .help("Encrypt a random 32-byte payload with the public key at INDEX and print the Base64 ciphertext (the payload is shown on stderr); check it with privatekey --test-decrypt"))
+        .arg(Arg::new("check-revocation")
+            .long("check-revocation")
+            .value_name("INDEX")
+            .value_parser(clap::value_parser!(usize))
+            .requires("crl")
+            .help("Check whether the certificate registered at INDEX is listed in the CRL given by --crl"))
+        .arg(Arg::new("crl")
+            .long("crl")
+            .value_parser(clap::value_parser!(PathBuf))
+            .requires("check-revocation")
+            .help("Certificate revocation list (PEM or DER) for --check-revocation"))
+        .arg(Arg::new("verify-pair")
+            .long("verify-pair")
+            .num_args(2)
+            .value_names(["PUBLIC_KEY", "PRIVATE_KEY"])
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Check that a public key and a private key belong to the same key pair"))
     ).subcommand(
         Command::new("privatekey")
         .about("Manage private key configuration")
@@ -285,6 +1595,22 @@ fn build_cli() -> Command {
             .long("add")
             .value_parser(clap::value_parser!(PathBuf))
             .help("Add a private key to the configuration"))
+        .arg(Arg::new("find-fingerprint")
+            .long("find-fingerprint")
+            .value_name("FINGERPRINT")
+            .help("Print the index of the registered private key with this fingerprint (SHA256:aa:bb:...)"))
+        .arg(Arg::new("update")
+            .short('u')
+            .long("update")
+            .num_args(2)
+            .value_names(["INDEX", "PATH"])
+            .help("Update the path of the private key at INDEX (e.g. after moving the key file)"))
+        .arg(Arg::new("swap")
+            .long("swap")
+            .num_args(2)
+            .value_names(["A", "B"])
+            .value_parser(clap::value_parser!(usize))
+            .help("Swap the private keys at indices A and B, leaving the other indices unchanged"))
         .arg(Arg::new("set")
             .short('s')
             .long("set")
@@ -300,8 +1626,234 @@ fn build_cli() -> Command {
             .long("clear")
             .action(ArgAction::SetTrue)
             .help("All privatekey setting remove"))
-    );
-    
-    return matches;
+        .arg(Arg::new("export-public-pem")
+            .long("export-public-pem")
+            .value_name("INDEX")
+            .value_parser(clap::value_parser!(usize))
+            .help("Derive the public key from the registered private key at INDEX and print its PEM to stdout"))
+        .arg(Arg::new("test-decrypt")
+            .long("test-decrypt")
+            .value_name("INDEX")
+            .value_parser(clap::value_parser!(usize))
+            .requires("ciphertext")
+            .help("Decrypt the --ciphertext printed by pubkey --test-encrypt with the private key at INDEX and print the payload"))
+        .arg(Arg::new("ciphertext")
+            .long("ciphertext")
+            .value_name("BASE64")
+            .requires("test-decrypt")
+            .help("Base64 ciphertext for --test-decrypt"))
+    ).subcommand(
+        Command::new("inspect")
+        .about("Show the unencrypted header of an archive without decrypting it")
+        .arg(Arg::new("archive")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the .acrp archive"))
+        .arg(Arg::new("private-key")
+            .long("private-key")
+            .short('k')
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Decrypt the archive with this private key to also show metadata stored with --encrypt-metadata"))
+    )
+    .subcommand(
+        Command::new("bench")
+        .about("Measure encryption and decryption throughput on this machine")
+        .arg(Arg::new("size")
+            .long("size")
+            .value_parser(parse_byte_size)
+            .default_value("100M")
+            .help("Amount of random data to encrypt and decrypt (e.g. 100M, 1G)"))
+    )
+    .subcommand(
+        Command::new("verify")
+        .about("Check the structure of an archive without decrypting it, and optionally compare its input checksum or manifest")
+        .arg(Arg::new("archive")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the .acrp archive"))
+        .arg(Arg::new("check-input-hash")
+            .long("check-input-hash")
+            .num_args(1..)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Recompute the checksum of these source paths and compare it with the one embedded by --embed-input-checksum (default compression settings are assumed)"))
+        .arg(Arg::new("manifest")
+            .long("manifest")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Decrypt the archive and compare every entry with a manifest written by --emit-manifest; exits non-zero on any missing, modified or unexpected entry"))
+        .arg(Arg::new("private-key")
+            .short('k')
+            .long("private-key")
+            .value_parser(clap::value_parser!(PathBuf))
+            .requires("manifest")
+            .help("Path to the private key used with --manifest [env: ARCHRYPT_PRIVATE_KEY, takes precedence over the configured default]"))
+    )
+    .subcommand(
+        Command::new("selftest")
+        .about("Generate a throwaway key pair, then compress, extract and compare a test file to check the installation")
+    )
+    .subcommand(
+        Command::new("gc")
+        .about("Remove temp files left behind by interrupted runs (archrypt-* in the system temp directory)")
+        .arg(Arg::new("dry-run")
+            .long("dry-run")
+            .action(ArgAction::SetTrue)
+            .help("Only list the files that would be removed"))
+        .arg(Arg::new("max-age")
+            .long("max-age")
+            .value_parser(parse_duration)
+            .default_value("24h")
+            .help("Only remove temp files last modified at least this long ago (e.g. 30m, 24h, 7d)"))
+    )
+    .subcommand(
+        Command::new("verify-config")
+        .about("Check that registered keys exist, parse, and that the default indices are valid")
+    )
+    .subcommand(
+        Command::new("config")
+        .about("Show or change general settings stored in the config file")
+        .arg(Arg::new("set-progress-template")
+            .long("set-progress-template")
+            .value_name("TEMPLATE")
+            .help("Progress bar template, e.g. \"{spinner} {bytes}/{total_bytes}\" (an empty string restores the default)"))
+        .arg(Arg::new("set-progress-chars")
+            .long("set-progress-chars")
+            .value_name("CHARS")
+            .help("Characters used to draw the progress bar, e.g. \"=>-\" (an empty string restores the default)"))
+        .arg(Arg::new("reset")
+            .long("reset")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["set-progress-template", "set-progress-chars"])
+            .help("Back up the config file to <config>.bak-<timestamp> and replace it with an empty configuration"))
+        .arg(Arg::new("yes")
+            .long("yes")
+            .action(ArgAction::SetTrue)
+            .requires("reset")
+            .help("Do not ask for confirmation before --reset"))
+    )
+    .subcommand(
+        Command::new("list-configs")
+        .about("Show the config file, key and temp paths in use, and any overriding environment variables")
+    )
+    .subcommand(
+        Command::new("cat")
+        .about("Decrypt an archive and print the contents of an entry to stdout without extracting anything")
+        .arg(Arg::new("input")
+            .short('i')
+            .long("input")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the .acrp archive"))
+        .arg(Arg::new("private-key")
+            .short('k')
+            .long("private-key")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the private key used for decryption [env: ARCHRYPT_PRIVATE_KEY, takes precedence over the configured default]"))
+        .arg(Arg::new("entry")
+            .required(true)
+            .help("Entry name inside the archive, e.g. path/to/file.txt"))
+        .arg(Arg::new("glob")
+            .long("glob")
+            .action(ArgAction::SetTrue)
+            .help("Treat the entry name as a glob pattern (e.g. \"*.json\") and print every matching entry in archive order"))
+    )
+    .subcommand(
+        Command::new("diff")
+        .about("Decrypt two archives and list added (A), deleted (D), modified (M) and unchanged (U) entries")
+        .arg(Arg::new("private-key")
+            .short('k')
+            .long("private-key")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the private key used to decrypt both archives [env: ARCHRYPT_PRIVATE_KEY, takes precedence over the configured default]"))
+        .arg(Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .action(ArgAction::SetTrue)
+            .help("Do not list unchanged entries"))
+        .arg(Arg::new("archives")
+            .required(true)
+            .num_args(2)
+            .value_names(["OLD", "NEW"])
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("The two .acrp archives to compare"))
+    )
+    .subcommand(
+        Command::new("list")
+        .about("Decrypt an archive and list its entries with their size and recorded MIME type")
+        .arg(Arg::new("private-key")
+            .short('k')
+            .long("private-key")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the private key used to decrypt the archive [env: ARCHRYPT_PRIVATE_KEY, takes precedence over the configured default]"))
+        .arg(Arg::new("archive")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the .acrp archive"))
+    )
+    .subcommand(
+        Command::new("merge")
+        .about("Decrypt several archives and re-encrypt all of their entries into a single archive")
+        .arg(Arg::new("output")
+            .short('o')
+            .long("output")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path of the merged .acrp archive"))
+        .arg(Arg::new("private-key")
+            .short('k')
+            .long("private-key")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the private key used to decrypt the inputs [env: ARCHRYPT_PRIVATE_KEY, takes precedence over the configured default]"))
+        .arg(Arg::new("public-key")
+            .short('p')
+            .long("public-key")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the public key used to encrypt the merged archive [env: ARCHRYPT_PUBLIC_KEY, takes precedence over the configured default]"))
+        .arg(Arg::new("conflict")
+            .long("conflict")
+            .value_parser(["first-wins", "last-wins", "error-on-conflict"])
+            .default_value("first-wins")
+            .help("What to do when several inputs contain an entry with the same name"))
+        .arg(Arg::new("inputs")
+            .required(true)
+            .num_args(1..)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("The .acrp archives to merge, in order"))
+    )
+    .subcommand(
+        Command::new("keygen")
+        .about("Generate an RSA key pair in PEM format, optionally with a self-signed X.509 certificate")
+        .arg(Arg::new("output-key")
+            .long("output-key")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to write the PKCS#8 private key to"))
+        .arg(Arg::new("output-pubkey")
+            .long("output-pubkey")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to write the public key to"))
+        .arg(Arg::new("bits")
+            .long("bits")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("4096")
+            .help("RSA key size in bits (at least 2048)"))
+        .arg(Arg::new("output-cert")
+            .long("output-cert")
+            .value_parser(clap::value_parser!(PathBuf))
+            .requires("subject")
+            .help("Also write a self-signed X.509 certificate (PEM, valid for 10 years) for the public key"))
+        .arg(Arg::new("subject")
+            .long("subject")
+            .requires("output-cert")
+            .help("Certificate subject, e.g. \"CN=Alice\" (a value without '=' is used as the CN)"))
+    )
+    .subcommand(
+        Command::new("list-recipients")
+        .about("Show the keys an archive was encrypted for, without decrypting it")
+        .arg(Arg::new("archive")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Path to the .acrp archive"))
+    )
 }
 
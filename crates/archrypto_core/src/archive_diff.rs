@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use crate::decrypt_archive_entries;
+
+/// 2つのアーカイブ間でのエントリの変更の種類.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeType {
+    /// 新しいアーカイブにのみ存在する。
+    Added,
+    /// 古いアーカイブにのみ存在する。
+    Deleted,
+    /// 両方に存在し、内容が異なる。
+    Modified,
+    /// 両方に存在し、内容が同じ。
+    Unchanged,
+}
+
+impl ChangeType {
+    /// `A` / `D` / `M` / `U` の1文字の記号を返します.
+    pub fn symbol(self) -> char {
+        match self {
+            ChangeType::Added => 'A',
+            ChangeType::Deleted => 'D',
+            ChangeType::Modified => 'M',
+            ChangeType::Unchanged => 'U',
+        }
+    }
+}
+
+/// `diff_archives` の1つのエントリの比較結果.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveDiffEntry {
+    /// 変更の種類
+    pub change_type: ChangeType,
+    /// ZIP内のエントリ名
+    pub entry_name: String,
+}
+
+/// 2つの `.acrp` ファイルを復号し、エントリ名と内容の SHA-256 を比較します.
+///
+/// どちらのアーカイブもディスクには展開せず、メモリ上で比較します。
+///
+/// # Arguments
+///
+/// * `a` - 比較元（古い方）の `.acrp` ファイルのパス。
+/// * `b` - 比較先（新しい方）の `.acrp` ファイルのパス。
+/// * `private_key` - 両方のアーカイブの復号に使用する秘密鍵ファイルのパス。
+///
+/// # Returns
+///
+/// 両方のアーカイブの全てのエントリの比較結果を、エントリ名の順に返します。
+///
+/// # Errors
+///
+/// ファイルの読み込み、秘密鍵のパース、またはいずれかのアーカイブの復号に失敗した場合にエラーを返します。
+pub fn diff_archives(
+    a: impl AsRef<Path>,
+    b: impl AsRef<Path>,
+    private_key: impl AsRef<Path>,
+) -> Result<Vec<ArchiveDiffEntry>> {
+    let old = entry_digests(a.as_ref(), private_key.as_ref())?;
+    let mut new = entry_digests(b.as_ref(), private_key.as_ref())?;
+    let mut changes: BTreeMap<String, ChangeType> = BTreeMap::new();
+    for (name, digest) in old {
+        let change_type = match new.remove(&name) {
+            Some(new_digest) if new_digest == digest => ChangeType::Unchanged,
+            Some(_) => ChangeType::Modified,
+            None => ChangeType::Deleted,
+        };
+        changes.insert(name, change_type);
+    }
+    for name in new.into_keys() {
+        changes.insert(name, ChangeType::Added);
+    }
+    Ok(changes
+        .into_iter()
+        .map(|(entry_name, change_type)| ArchiveDiffEntry { change_type, entry_name })
+        .collect())
+}
+
+/// アーカイブを復号し、各エントリの内容の SHA-256 を返します.
+fn entry_digests(archive: &Path, private_key: &Path) -> Result<BTreeMap<String, [u8; 32]>> {
+    Ok(decrypt_archive_entries(archive, private_key)?
+        .into_iter()
+        .map(|(name, data)| (name, Sha256::digest(&data).into()))
+        .collect())
+}
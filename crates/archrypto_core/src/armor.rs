@@ -0,0 +1,139 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// ASCII アーマーの開始行.
+const ARMOR_BEGIN: &str = "-----BEGIN ARCHRYPT ARCHIVE-----";
+/// ASCII アーマーの終了行.
+const ARMOR_END: &str = "-----END ARCHRYPT ARCHIVE-----";
+/// ASCII アーマーの Base64 部分の1行あたりの文字数（PEM と同じ）.
+const LINE_LEN: usize = 64;
+
+/// `Read + Seek` を実装するリーダーをまとめて扱うためのトレイト.
+pub(crate) trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// ASCII アーマーの Base64 部分の1行に対応する元データのバイト数.
+const LINE_BYTES: usize = LINE_LEN / 4 * 3;
+
+/// 書き込まれた `.acrp` のバイト列を、PEM 形式に似た ASCII アーマーで囲んだ Base64 テキストに変換しながら `inner` に書き出すライター.
+///
+/// 開始行は作成時に、1行に満たない末尾と終了行は `finish` で書き出すため、最後に必ず `finish` を呼び出してください。
+pub(crate) struct ArmorWriter<W: Write> {
+    inner: W,
+    /// まだ書き出していない、1行分に満たないデータ
+    pending: Vec<u8>,
+}
+
+impl<W: Write> ArmorWriter<W> {
+    /// 開始行を書き出して作成します.
+    ///
+    /// # Errors
+    ///
+    /// 開始行の書き出しに失敗した場合にエラーを返します。
+    pub(crate) fn new(mut inner: W) -> io::Result<Self> {
+        writeln!(inner, "{}", ARMOR_BEGIN)?;
+        Ok(ArmorWriter { inner, pending: Vec::with_capacity(LINE_BYTES) })
+    }
+
+    /// 残りのデータと終了行を書き出し、`inner` を返します.
+    ///
+    /// # Errors
+    ///
+    /// 書き出しに失敗した場合にエラーを返します。
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            writeln!(self.inner, "{}", STANDARD.encode(&self.pending))?;
+        }
+        writeln!(self.inner, "{}", ARMOR_END)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ArmorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let take = (LINE_BYTES - self.pending.len()).min(buf.len());
+        self.pending.extend_from_slice(&buf[..take]);
+        if self.pending.len() == LINE_BYTES {
+            writeln!(self.inner, "{}", STANDARD.encode(&self.pending))?;
+            self.pending.clear();
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// データが ASCII アーマー形式（先頭の空白を除いて `-----` で始まる）であれば true を返します.
+pub(crate) fn is_armored(data: &[u8]) -> bool {
+    data.trim_ascii_start().starts_with(b"-----")
+}
+
+/// ASCII アーマー形式であればバイナリの `.acrp` に戻し、そうでなければそのまま返します.
+///
+/// # Errors
+///
+/// アーマーの開始行・終了行がない場合や、Base64 の形式が不正な場合にエラーを返します。
+pub(crate) fn dearmor(data: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if !is_armored(data) {
+        return Ok(Cow::Borrowed(data));
+    }
+    let text = std::str::from_utf8(data).map_err(|_| anyhow!("Armored archive is not valid ASCII"))?;
+    let body = text
+        .trim_start()
+        .strip_prefix(ARMOR_BEGIN)
+        .ok_or_else(|| anyhow!("Armored archive does not start with {}", ARMOR_BEGIN))?;
+    let end = body
+        .find(ARMOR_END)
+        .ok_or_else(|| anyhow!("Armored archive is missing {}", ARMOR_END))?;
+    let encoded: String = body[..end].chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let decoded = STANDARD
+        .decode(encoded)
+        .map_err(|e| anyhow!("Armored archive contains invalid Base64: {}", e))?;
+    Ok(Cow::Owned(decoded))
+}
+
+/// `.acrp` ファイルを開きます. ASCII アーマー形式の場合はデコードしたデータを読み取るリーダーを返します.
+///
+/// # Errors
+///
+/// ファイルの読み込み、またはアーマーのデコードに失敗した場合にエラーを返します。
+pub(crate) fn open_archive(path: &Path) -> Result<Box<dyn ReadSeek>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if !is_armored(reader.fill_buf()?) {
+        return Ok(Box::new(reader));
+    }
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(Box::new(io::Cursor::new(dearmor(&data)?.into_owned())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armor_writer_round_trips_through_dearmor() {
+        for len in [0, 1, LINE_BYTES - 1, LINE_BYTES, LINE_BYTES + 1, 10 * LINE_BYTES + 7] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 7 % 256) as u8).collect();
+            let mut writer = ArmorWriter::new(Vec::new()).unwrap();
+            for chunk in data.chunks(5) {
+                writer.write_all(chunk).unwrap();
+            }
+            let armored = writer.finish().unwrap();
+            let text = std::str::from_utf8(&armored).unwrap();
+            assert!(text.starts_with(ARMOR_BEGIN), "len={}", len);
+            assert!(text.ends_with(&format!("{}\n", ARMOR_END)), "len={}", len);
+            assert!(text.lines().all(|line| line.len() <= LINE_LEN || line.starts_with("-----")), "len={}", len);
+            assert!(is_armored(&armored));
+            assert_eq!(dearmor(&armored).unwrap().as_ref(), data.as_slice(), "len={}", len);
+        }
+    }
+}
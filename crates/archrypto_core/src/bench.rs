@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use rsa::{RsaPrivateKey, RsaPublicKey, rand_core::{OsRng, RngCore}};
+
+use crate::{compress_bytes, extract_bytes};
+
+/// ベンチマークに使用する RSA 鍵のビット数.
+const BENCH_KEY_BITS: usize = 2048;
+
+/// 1つの暗号方式についてのベンチマーク結果.
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    /// 暗号方式の名前（`--cipher` で指定する名前）
+    pub cipher: &'static str,
+    /// 計測に使用したデータのバイト数
+    pub size: usize,
+    /// 圧縮・暗号化にかかった時間
+    pub encrypt: Duration,
+    /// 復号・展開にかかった時間
+    pub decrypt: Duration,
+}
+
+impl BenchResult {
+    /// 圧縮・暗号化のスループット（MB/s）を返します.
+    pub fn encrypt_throughput(&self) -> f64 {
+        throughput(self.size, self.encrypt)
+    }
+
+    /// 復号・展開のスループット（MB/s）を返します.
+    pub fn decrypt_throughput(&self) -> f64 {
+        throughput(self.size, self.decrypt)
+    }
+}
+
+/// ランダムなデータを `compress_bytes` / `extract_bytes` で暗号化・復号し、暗号方式ごとの処理時間を計測します.
+///
+/// RSA 鍵の生成は計測に含みません。現在利用できる暗号方式は AES-256-GCM のみです。
+///
+/// # Arguments
+///
+/// * `size` - 計測に使用するデータのバイト数。
+///
+/// # Errors
+///
+/// 鍵の生成、暗号化・復号のいずれかに失敗した場合、または復号結果が元のデータと一致しない場合にエラーを返します。
+pub fn run_benchmark(size: usize) -> Result<Vec<BenchResult>> {
+    let mut rng = OsRng;
+    let private_key = RsaPrivateKey::new(&mut rng, BENCH_KEY_BITS)?;
+    let public_key = RsaPublicKey::from(&private_key);
+
+    let mut data = vec![0u8; size];
+    rng.fill_bytes(&mut data);
+
+    let started = Instant::now();
+    let archive = compress_bytes("bench.bin", &data, &public_key)?;
+    let encrypt = started.elapsed();
+
+    let started = Instant::now();
+    let entries = extract_bytes(&archive, &private_key)?;
+    let decrypt = started.elapsed();
+
+    if entries.len() != 1 || entries[0].1 != data {
+        return Err(anyhow::anyhow!("Benchmark round trip produced different data"));
+    }
+    Ok(vec![BenchResult {
+        cipher: "aes256gcm",
+        size,
+        encrypt,
+        decrypt,
+    }])
+}
+
+/// バイト数と処理時間から MB/s（1 MB = 1024 * 1024 バイト）を計算します.
+fn throughput(size: usize, elapsed: Duration) -> f64 {
+    size as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64().max(f64::EPSILON)
+}
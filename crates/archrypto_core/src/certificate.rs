@@ -0,0 +1,77 @@
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use rsa::pkcs1v15::{Signature, SigningKey};
+use rsa::rand_core::{OsRng, RngCore};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use sha2::Sha256;
+use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+use x509_cert::der::{pem, Encode};
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::SubjectPublicKeyInfoOwned;
+use x509_cert::time::Validity;
+
+/// `create_self_signed_rsa_cert` で作成する証明書の有効期間（10年）.
+const CERTIFICATE_VALIDITY: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+/// RSA 公開鍵を、自己署名した最小限の X.509 v3 証明書に格納します.
+///
+/// PKCS#12 や S/MIME など、公開鍵ではなく証明書を要求するツールで archrypt の鍵を使用するためのものです。
+/// 証明書は作成時点から10年間有効で、発行者とサブジェクトは同じ名前になります。
+/// 署名には SHA-256 を用いた RSASSA-PKCS1-v1_5 を使用し、鍵用途には keyEncipherment を含めます。
+///
+/// # Arguments
+///
+/// * `key` - 証明書に格納する公開鍵に対応し、署名に使用する秘密鍵。
+/// * `subject` - サブジェクトの識別名（例: `"CN=Alice"` や `"CN=Alice,O=Example"`）。
+///   `=` を含まない場合は CN として扱います。
+///
+/// # Returns
+///
+/// DER 形式の証明書を返します。
+///
+/// # Errors
+///
+/// サブジェクトの書式が不正な場合、または証明書の作成や署名に失敗した場合にエラーを返します。
+pub fn create_self_signed_rsa_cert(key: &RsaPrivateKey, subject: &str) -> Result<Vec<u8>> {
+    let subject = if subject.contains('=') {
+        subject.to_string()
+    } else {
+        format!("CN={}", subject)
+    };
+    let subject = Name::from_str(&subject).map_err(|e| anyhow!("Invalid certificate subject {:?}: {}", subject, e))?;
+    let public_key_info = SubjectPublicKeyInfoOwned::from_key(RsaPublicKey::from(key))?;
+    let signer = SigningKey::<Sha256>::new(key.clone());
+    let builder = CertificateBuilder::new(
+        Profile::Leaf {
+            issuer: subject.clone(),
+            enable_key_agreement: false,
+            enable_key_encipherment: true,
+        },
+        random_serial_number()?,
+        Validity::from_now(CERTIFICATE_VALIDITY)?,
+        subject,
+        public_key_info,
+        &signer,
+    )?;
+    Ok(builder.build::<Signature>()?.to_der()?)
+}
+
+/// DER 形式の証明書を PEM 形式（`-----BEGIN CERTIFICATE-----`）の文字列に変換します.
+///
+/// # Errors
+///
+/// エンコードに失敗した場合にエラーを返します。
+pub fn certificate_pem(der: &[u8]) -> Result<String> {
+    pem::encode_string("CERTIFICATE", pem::LineEnding::LF, der).map_err(|e| anyhow!("Failed to encode certificate PEM: {}", e))
+}
+
+/// 証明書のシリアル番号として、正の値となる16バイトの乱数を作成します.
+fn random_serial_number() -> Result<SerialNumber> {
+    let mut serial = [0u8; 16];
+    OsRng.fill_bytes(&mut serial);
+    // 最上位ビットを落として正の値にし、先頭のバイトが 0 にならないようにする
+    serial[0] = (serial[0] & 0x7f) | 0x01;
+    Ok(SerialNumber::new(&serial)?)
+}
@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use anyhow::Result;
+use zip::write::FullFileOptions;
+use zip::DateTime;
+
+use crate::dedup;
+
+/// 内容から判定した MIME タイプを保持するZIP拡張フィールドのID.
+///
+/// APPNOTE で予約されていない値を使用しています。
+pub(crate) const CONTENT_TYPE_EXTRA_FIELD_ID: u16 = 0xAC01;
+
+/// MIME タイプの判定に使用する、ファイルの先頭のバイト数.
+const SNIFF_LEN: u64 = 8192;
+
+/// ファイルの先頭のマジックバイトから MIME タイプを判定します.
+///
+/// 判定できない場合（テキストファイルなど）は None を返します。
+///
+/// # Errors
+///
+/// ファイルの読み込みに失敗した場合にエラーを返します。
+pub(crate) fn detect(path: &Path) -> Result<Option<String>> {
+    let mut head = Vec::new();
+    File::open(path)?.take(SNIFF_LEN).read_to_end(&mut head)?;
+    Ok(infer::get(&head).map(|kind| kind.mime_type().to_string()))
+}
+
+/// MIME タイプを拡張フィールドに記録したエントリのオプションを返します.
+///
+/// # Arguments
+///
+/// * `content_type` - 記録する MIME タイプ。
+/// * `last_modified` - エントリの更新日時。
+///
+/// # Errors
+///
+/// 拡張フィールドに格納できない長さの場合にエラーを返します。
+pub(crate) fn entry_options(content_type: &str, last_modified: DateTime) -> Result<FullFileOptions<'static>> {
+    let mut options = FullFileOptions::default().last_modified_time(last_modified);
+    options.add_extra_data(CONTENT_TYPE_EXTRA_FIELD_ID, content_type.as_bytes().into(), false)?;
+    Ok(options)
+}
+
+/// ZIPエントリの拡張フィールドから MIME タイプを取り出します.
+///
+/// 拡張フィールドがない場合や、MIME タイプのフィールドを含まない場合は None を返します。
+pub(crate) fn from_extra_data(extra_data: Option<&[u8]>) -> Option<String> {
+    String::from_utf8(dedup::find_extra_field(extra_data, CONTENT_TYPE_EXTRA_FIELD_ID)?.to_vec()).ok()
+}
@@ -0,0 +1,68 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use zip::write::FullFileOptions;
+use zip::{CompressionMethod, DateTime};
+
+/// 重複排除したエントリに付与する、内容が同じ最初のエントリ名を保持するZIP拡張フィールドのID.
+///
+/// APPNOTE で予約されていない値を使用しています（リトルエンディアンで `"ar"`）。
+pub(crate) const DEDUP_EXTRA_FIELD_ID: u16 = 0x7261;
+
+/// ファイルの内容の SHA-256 を計算します.
+///
+/// # Errors
+///
+/// ファイルの読み込みに失敗した場合にエラーを返します。
+pub(crate) fn hash_file(path: &Path) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// 内容を持たず、`source` のエントリを参照する重複エントリのオプションを返します.
+///
+/// # Arguments
+///
+/// * `source` - 参照先のエントリ名。
+/// * `last_modified` - エントリの更新日時。
+///
+/// # Errors
+///
+/// エントリ名が拡張フィールドに格納できない長さの場合にエラーを返します。
+pub(crate) fn reference_options(source: &str, last_modified: DateTime) -> Result<FullFileOptions<'static>> {
+    if source.len() > usize::from(u16::MAX) - 4 {
+        return Err(anyhow!("Entry name is too long to be referenced: {}", source));
+    }
+    let mut options = FullFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .last_modified_time(last_modified);
+    options.add_extra_data(DEDUP_EXTRA_FIELD_ID, source.as_bytes().into(), false)?;
+    Ok(options)
+}
+
+/// ZIPエントリの拡張フィールドから、重複排除で参照している元のエントリ名を取り出します.
+///
+/// 拡張フィールドがない場合や、重複排除のフィールドを含まない場合は None を返します。
+pub(crate) fn source_entry(extra_data: Option<&[u8]>) -> Option<String> {
+    String::from_utf8(find_extra_field(extra_data, DEDUP_EXTRA_FIELD_ID)?.to_vec()).ok()
+}
+
+/// ZIPエントリの拡張フィールドから、指定したIDのフィールドのデータを取り出します.
+///
+/// 拡張フィールドがない場合や、指定したIDのフィールドを含まない場合は None を返します。
+pub(crate) fn find_extra_field(extra_data: Option<&[u8]>, field_id: u16) -> Option<&[u8]> {
+    let mut rest = extra_data?;
+    while rest.len() >= 4 {
+        let id = u16::from_le_bytes([rest[0], rest[1]]);
+        let len = usize::from(u16::from_le_bytes([rest[2], rest[3]]));
+        let data = rest.get(4..4 + len)?;
+        if id == field_id {
+            return Some(data);
+        }
+        rest = &rest[4 + len..];
+    }
+    None
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, Result};
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey};
+
+use crate::armor;
+use crate::header::{ArchiveHeader, CURRENT_VERSION, MAGIC};
+use crate::{load_private_key, private_key_fingerprint};
+
+/// Nonce(12) + AES鍵のサイズ(u16)
+const KEY_PREFIX_LEN: usize = 14;
+/// AES-256 の鍵のバイト数
+pub(crate) const AES_KEY_LEN: usize = 32;
+
+/// `decrypt_with_diagnostics` の結果. 復号に失敗した場合は、どの工程で失敗したかを表します.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticDecryptResult {
+    /// 復号に成功した。値は復号したZIPデータです。
+    Success(Vec<u8>),
+    /// 秘密鍵が、アーカイブの暗号化に使用した公開鍵と対応していない。
+    /// `key_fingerprint` はヘッダに記録された暗号化時の公開鍵のフィンガープリントです（旧形式では None）。
+    WrongKey { key_fingerprint: Option<String> },
+    /// ヘッダ、または暗号化されたAES鍵までの部分が壊れている。
+    CorruptedHeader { reason: String },
+    /// AES-GCM の認証タグが一致しない（暗号化ZIPデータやヘッダが破損・改ざんされている）。
+    AeadTagMismatch,
+    /// このバージョンでは読み取れない、新しい形式のアーカイブ。
+    UnsupportedVersion(u16),
+}
+
+impl DiagnosticDecryptResult {
+    /// 復号に成功した場合はZIPデータを返し、失敗した場合は `Display` のメッセージでエラーを返します.
+    ///
+    /// # Errors
+    ///
+    /// `Success` 以外の場合にエラーを返します。
+    pub fn into_result(self) -> Result<Vec<u8>> {
+        match self {
+            DiagnosticDecryptResult::Success(zip_data) => Ok(zip_data),
+            other => Err(anyhow!("{}", other)),
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticDecryptResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticDecryptResult::Success(zip_data) => write!(f, "Decrypted {} bytes", zip_data.len()),
+            DiagnosticDecryptResult::WrongKey { key_fingerprint: Some(fingerprint) } => write!(
+                f,
+                "The private key does not match the key used to encrypt this archive (encrypted for {})",
+                fingerprint
+            ),
+            DiagnosticDecryptResult::WrongKey { key_fingerprint: None } => {
+                write!(f, "The private key does not match the key used to encrypt this archive")
+            }
+            DiagnosticDecryptResult::CorruptedHeader { reason } => {
+                write!(f, "The archive header is corrupted: {}", reason)
+            }
+            DiagnosticDecryptResult::AeadTagMismatch => write!(
+                f,
+                "The archive failed its integrity check; the file is corrupted or has been modified"
+            ),
+            DiagnosticDecryptResult::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported archive version {}; this archive was created by a newer version of archrypt",
+                version
+            ),
+        }
+    }
+}
+
+/// `.acrp` ファイルを復号し、失敗した場合はその原因を判別して返します.
+///
+/// ヘッダの解析、RSA による AES 鍵の復号、AES-GCM による復号を順に行い、
+/// どの工程で失敗したかを `DiagnosticDecryptResult` の各バリアントで表します。
+///
+/// # Arguments
+///
+/// * `path` - `.acrp` ファイルのパス。
+/// * `private_key_path` - 復号に使用する秘密鍵ファイルのパス。
+///
+/// # Errors
+///
+/// アーカイブや秘密鍵の読み込みに失敗した場合にエラーを返します。復号の失敗はエラーではなく結果として返します。
+pub fn decrypt_with_diagnostics(path: &Path, private_key_path: &Path) -> Result<DiagnosticDecryptResult> {
+    let encrypted_data = fs::read(path)?;
+    let private_key = load_private_key(private_key_path)?;
+    Ok(diagnose_decrypt(&encrypted_data, &private_key))
+}
+
+/// `.acrp` 形式のバイト列を秘密鍵で復号し、失敗した場合はその原因を判別して返します.
+///
+/// ASCII アーマー形式のデータは、デコードしてから復号します。
+pub(crate) fn diagnose_decrypt(encrypted_data: &[u8], private_key: &RsaPrivateKey) -> DiagnosticDecryptResult {
+    let encrypted_data = match armor::dearmor(encrypted_data) {
+        Ok(encrypted_data) => encrypted_data,
+        Err(e) => return DiagnosticDecryptResult::CorruptedHeader { reason: e.to_string() },
+    };
+    let parts = match split_archive(encrypted_data.as_ref()) {
+        Ok(parts) => parts,
+        Err(result) => return result,
+    };
+    // 暗号化時の公開鍵が記録されていれば、RSA の復号を試す前に照合する
+    if let Some(recipient_key_id) = &parts.header.recipient_key_id {
+        if private_key_fingerprint(private_key).is_ok_and(|fingerprint| &fingerprint != recipient_key_id) {
+            return DiagnosticDecryptResult::WrongKey { key_fingerprint: Some(recipient_key_id.clone()) };
+        }
+    }
+
+    // 鍵が異なる場合、RSA の復号に失敗するか、AES 鍵として不正な長さのデータになる
+    match private_key.decrypt(Pkcs1v15Encrypt, parts.encrypted_key) {
+        Ok(aes_key) => decrypt_parts(&parts, &aes_key),
+        Err(_) => DiagnosticDecryptResult::WrongKey { key_fingerprint: parts.header.recipient_key_id },
+    }
+}
+
+/// `.acrp` 形式のバイト列を、RSA で復号済みの AES 鍵で復号し、失敗した場合はその原因を判別して返します.
+///
+/// ASCII アーマー形式のデータは、デコードしてから復号します。暗号化された AES 鍵は使用しません。
+pub(crate) fn decrypt_with_aes_key(encrypted_data: &[u8], aes_key: &[u8; AES_KEY_LEN]) -> DiagnosticDecryptResult {
+    let encrypted_data = match armor::dearmor(encrypted_data) {
+        Ok(encrypted_data) => encrypted_data,
+        Err(e) => return DiagnosticDecryptResult::CorruptedHeader { reason: e.to_string() },
+    };
+    match split_archive(encrypted_data.as_ref()) {
+        Ok(parts) => decrypt_parts(&parts, aes_key),
+        Err(result) => result,
+    }
+}
+
+/// `.acrp` 形式のデータを構成する各部分.
+struct ArchiveParts<'a> {
+    /// 解析した平文ヘッダ
+    header: ArchiveHeader,
+    /// AES-GCM の追加認証データとなる、ヘッダのバイト列
+    header_bytes: &'a [u8],
+    /// AES-GCM の Nonce
+    nonce: &'a [u8],
+    /// RSA で暗号化された AES 鍵
+    encrypted_key: &'a [u8],
+    /// AES-GCM で暗号化されたZIPデータ
+    encrypted_zip: &'a [u8],
+}
+
+/// `.acrp` 形式のデータを、ヘッダ, Nonce, 暗号化された AES 鍵, 暗号化ZIPデータに分割します.
+///
+/// 形式が不正な場合は、その原因を表す `DiagnosticDecryptResult` を返します。
+fn split_archive(encrypted_data: &[u8]) -> Result<ArchiveParts<'_>, DiagnosticDecryptResult> {
+    if let Some(version) = header_version(encrypted_data) {
+        if version > CURRENT_VERSION {
+            return Err(DiagnosticDecryptResult::UnsupportedVersion(version));
+        }
+    }
+    let (header, header_len) = ArchiveHeader::parse(encrypted_data)
+        .map_err(|e| DiagnosticDecryptResult::CorruptedHeader { reason: e.to_string() })?;
+
+    let (header_bytes, body) = encrypted_data.split_at(header_len);
+    let prefix = body.get(..KEY_PREFIX_LEN).ok_or_else(|| DiagnosticDecryptResult::CorruptedHeader {
+        reason: "archive is too short to contain the nonce and key size".to_string(),
+    })?;
+    let key_size = u16::from_be_bytes([prefix[12], prefix[13]]) as usize;
+    let encrypted_key = body
+        .get(KEY_PREFIX_LEN..KEY_PREFIX_LEN + key_size)
+        .ok_or_else(|| DiagnosticDecryptResult::CorruptedHeader {
+            reason: format!("encrypted AES key ({} bytes) is truncated", key_size),
+        })?;
+    Ok(ArchiveParts {
+        header,
+        header_bytes,
+        nonce: &prefix[..12],
+        encrypted_key,
+        encrypted_zip: &body[KEY_PREFIX_LEN + key_size..],
+    })
+}
+
+/// 分割した `.acrp` 形式のデータのZIPデータを、AES 鍵で復号します.
+///
+/// AES 鍵が不正な長さの場合は `WrongKey` を返します。
+fn decrypt_parts(parts: &ArchiveParts<'_>, aes_key: &[u8]) -> DiagnosticDecryptResult {
+    if aes_key.len() != AES_KEY_LEN {
+        return DiagnosticDecryptResult::WrongKey { key_fingerprint: parts.header.recipient_key_id.clone() };
+    }
+    let cipher = match Aes256Gcm::new_from_slice(aes_key) {
+        Ok(cipher) => cipher,
+        Err(_) => return DiagnosticDecryptResult::WrongKey { key_fingerprint: parts.header.recipient_key_id.clone() },
+    };
+    let nonce = Nonce::from_slice(parts.nonce);
+    match cipher.decrypt(nonce, Payload { msg: parts.encrypted_zip, aad: parts.header_bytes }) {
+        Ok(zip_data) => DiagnosticDecryptResult::Success(zip_data),
+        Err(_) => DiagnosticDecryptResult::AeadTagMismatch,
+    }
+}
+
+/// マジックバイトで始まるデータであれば、ヘッダのバージョンを返します.
+fn header_version(data: &[u8]) -> Option<u16> {
+    let version = data.strip_prefix(MAGIC.as_slice())?.get(..2)?;
+    Some(u16::from_be_bytes([version[0], version[1]]))
+}
@@ -0,0 +1,60 @@
+/// 十分とみなすエントロピーの推定ビット数.
+const MIN_ENTROPY_BITS: u32 = 128;
+
+/// `RNDGETENTCNT` ioctl を発行する対象のデバイス.
+#[cfg(target_os = "linux")]
+const RANDOM_DEVICE_PATH: &str = "/dev/random";
+
+/// `<linux/random.h>` の `RNDGETENTCNT`（`_IOR('R', 0x00, int)`）.
+///
+/// libc クレートには定義がないため、ioctl の方向ビットの配置が異なるアーキテクチャごとに値を定義します。
+#[cfg(all(target_os = "linux", any(target_arch = "mips", target_arch = "mips64", target_arch = "powerpc", target_arch = "powerpc64", target_arch = "sparc", target_arch = "sparc64")))]
+const RNDGETENTCNT: u32 = 0x4004_5200;
+#[cfg(all(target_os = "linux", not(any(target_arch = "mips", target_arch = "mips64", target_arch = "powerpc", target_arch = "powerpc64", target_arch = "sparc", target_arch = "sparc64"))))]
+const RNDGETENTCNT: u32 = 0x8004_5200;
+
+/// OS の乱数生成器に十分なエントロピーがあるかを返します.
+///
+/// Linux では `/dev/random` に対して `RNDGETENTCNT` ioctl を発行してカーネルの推定エントロピーを取得し、
+/// 128 ビット未満の場合に false を返します。
+/// 値を取得できない場合や、起動直後でもブロックしない乱数生成器を持つ他の OS（Windows など）では true を返します。
+///
+/// エントロピーが不足していても `/dev/urandom` からの非ブロッキングな読み取りには切り替えません。
+/// 鍵の生成に使う `getrandom(2)` はプールの初期化が終わるまで待つだけで、初期化後は `/dev/urandom` と同じ出力になるため、
+/// 切り替えて得られるのは初期化前の予測可能な乱数だけだからです。呼び出し側は警告を表示するにとどめてください。
+pub fn entropy_available() -> bool {
+    estimated_entropy_bits().is_none_or(|bits| bits >= MIN_ENTROPY_BITS)
+}
+
+/// カーネルが推定しているエントロピーのビット数を返します.
+#[cfg(target_os = "linux")]
+fn estimated_entropy_bits() -> Option<u32> {
+    use std::os::fd::AsRawFd;
+
+    let device = std::fs::File::open(RANDOM_DEVICE_PATH).ok()?;
+    let mut count: libc::c_int = 0;
+    // SAFETY: RNDGETENTCNT は int 1 つを書き込むだけで、count はその間有効
+    let ret = unsafe { libc::ioctl(device.as_raw_fd(), RNDGETENTCNT as _, &mut count as *mut libc::c_int) };
+    if ret == -1 {
+        return None;
+    }
+    u32::try_from(count).ok()
+}
+
+/// カーネルが推定しているエントロピーのビット数を返します（Linux 以外では取得できません）.
+#[cfg(not(target_os = "linux"))]
+fn estimated_entropy_bits() -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn rndgetentcnt_reports_entropy_estimate() {
+        // 5.18 以降のカーネルは初期化後に常に 256 を返すため、値そのものではなく取得できることを確認する
+        assert!(estimated_entropy_bits().is_some());
+    }
+}
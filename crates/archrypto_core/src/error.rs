@@ -0,0 +1,98 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// 鍵の種類.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    /// 公開鍵（暗号化に使用）
+    Public,
+    /// 秘密鍵（復号に使用）
+    Private,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::Public => write!(f, "public"),
+            KeyType::Private => write!(f, "private"),
+        }
+    }
+}
+
+/// archrypto_core の処理で発生する、呼び出し側が種類を判別できるエラー.
+///
+/// 各関数は `anyhow::Result` を返すため、種類を判別したい場合は
+/// `err.downcast_ref::<ArchryptoError>()` で取り出します。
+#[derive(Debug, Error)]
+pub enum ArchryptoError {
+    /// 圧縮対象のパスが、別の圧縮対象のディレクトリ配下に含まれている。
+    #[error("Target path {child:?} is already included by {parent:?}")]
+    OverlappingPaths { parent: PathBuf, child: PathBuf },
+    /// 同じ実体を指す圧縮対象のパスが複数指定されている。
+    #[error("Target paths {first:?} and {second:?} refer to the same file or directory")]
+    DuplicatePaths { first: PathBuf, second: PathBuf },
+    /// ファイルサイズが `CompressOptions::max_file_size` の上限を超えている。
+    #[error("File {path:?} is {size} bytes, which exceeds the limit of {limit} bytes")]
+    FileTooLarge { path: PathBuf, size: u64, limit: u64 },
+    /// 圧縮対象のパスが1つも指定されていない（`CompressOptions::allow_empty` で許可できます）。
+    #[error("No target paths were given")]
+    NoTargetPaths,
+    /// アーカイブに格納するファイル数が `CompressOptions::max_entries` の上限を超えた。
+    #[error("Number of archived files exceeds the limit of {limit}")]
+    MaxEntriesExceeded { limit: usize },
+    /// 出力先のディレクトリが存在しないか、出力ファイルを書き込めない。
+    #[error("Output path {path:?} is not writable (check that the directory exists and has write permission)")]
+    OutputNotWritable { path: PathBuf },
+    /// ZIPエントリ名が展開先ディレクトリの外を指している（`..` による親ディレクトリへの移動など）。
+    #[error("Archive entry {entry:?} points outside the output directory")]
+    UnsafeEntryPath { entry: String },
+    /// 設定ファイルにデフォルトの鍵が設定されていない（`Config::default_public_key_or_err` など）。
+    #[error("No default {0} key is set")]
+    NoDefaultKey(KeyType),
+    /// 展開したファイルの SHA-256 が、アーカイブに埋め込まれた `MANIFEST.sha256` と一致しない。
+    /// マニフェストに記載のないファイルや、マニフェストにのみ存在するファイルも含みます。
+    #[error("Entry {entry:?} does not match the embedded manifest")]
+    ManifestMismatch { entry: String },
+    /// 暗号化に使用する RSA 鍵のビット数が `MIN_RSA_KEY_BITS` に満たない。
+    #[error("RSA key is {bits} bits, but at least {min} bits are required")]
+    KeyTooSmall { bits: usize, min: usize },
+    /// アーカイブのヘッダのバージョンが、このバージョンの archrypto_core で読み取れない。
+    #[error("Unsupported archive version {version}; this archive was created by a newer version of archrypt")]
+    UnsupportedVersion { version: u16 },
+    /// アーカイブのヘッダの形式が不正。
+    #[error("The archive header is corrupted: {reason}")]
+    CorruptedHeader { reason: String },
+    /// 暗号化された AES 鍵のサイズが、RSA 1024〜8192 ビットの鍵で取り得る範囲（128〜1024 バイト）にない。
+    #[error("Encrypted key size of {key_size} bytes is not valid for an RSA key; the file is corrupted or is not an archrypt archive")]
+    ImplausibleKeySize { key_size: usize },
+    /// ファイルが、ヘッダと暗号化された AES 鍵から求めた最小の長さより短い。
+    #[error("Archive is truncated: {len} bytes, but at least {min} bytes are required")]
+    TruncatedArchive { len: u64, min: u64 },
+    /// ZIPエントリ名が UTF-8 として解釈できない（`NonUtf8Policy::Error` の場合）。
+    #[error("File name {name:?} is not valid UTF-8")]
+    NonUtf8Filename { name: OsString },
+    /// 公開鍵の証明書が、証明書失効リスト（CRL）で失効している。
+    #[error("The certificate with serial number {serial} has been revoked")]
+    KeyRevoked { serial: String },
+    /// アーカイブの結合で、同じ名前のエントリが複数のアーカイブにある（`ConflictPolicy::ErrorOnConflict` の場合）。
+    #[error("Entry {entry:?} exists in more than one input archive")]
+    EntryConflict { entry: String },
+    /// `ExtractOptions::verify_manifest` で指定したマニフェストJSONのエントリが、復号したアーカイブに存在しない、
+    /// または SHA-256 が一致しない。復号（AES-GCM の認証）には成功しているため、破損ではなく内容の差し替えを示します。
+    #[error("Manifest verification failed for entry {entry:?}: {reason}")]
+    ManifestVerificationFailed { entry: String, reason: String },
+    /// `OverwritePolicy::Rename` で、`attempts` 個の別名が全て既に存在する。
+    #[error("Could not find a free name for {path:?} after {attempts} attempts")]
+    TooManyConflicts { path: PathBuf, attempts: u32 },
+    /// 指定された、または設定ファイルのデフォルトの鍵ファイルが存在しない。
+    #[error("The {key_type} key file {path:?} does not exist")]
+    KeyFileNotFound { key_type: KeyType, path: PathBuf },
+    /// コマンドライン引数でも設定ファイルのデフォルトでも鍵が指定されていない。
+    #[error("No {0} key is available; specify one on the command line or set a default in the config file")]
+    NoKeyAvailable(KeyType),
+    /// ファイルの存在確認などの入出力に失敗した。
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
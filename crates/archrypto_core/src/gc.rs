@@ -0,0 +1,95 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use anyhow::Result;
+use tempfile::NamedTempFile;
+
+/// archrypt が作成する一時ファイルの名前の接頭辞.
+///
+/// SIGKILL などで異常終了した際に残った一時ファイルを `gc_temp_files` で見つけるために使用します。
+pub const TEMP_FILE_PREFIX: &str = "archrypt-";
+
+/// システムの一時ディレクトリに、`TEMP_FILE_PREFIX` で始まる名前の一時ファイルを作成します.
+pub(crate) fn new_temp_file() -> io::Result<NamedTempFile> {
+    tempfile::Builder::new().prefix(TEMP_FILE_PREFIX).tempfile()
+}
+
+/// 出力ファイルを書き出すための一時ファイルを、出力先と同じディレクトリに作成します.
+///
+/// 書き出しが完了してから出力ファイルの名前に変更（`NamedTempFile::persist`）することで、
+/// 途中で失敗した場合に不完全な出力ファイルが残らないようにします。
+/// Unix では `File::create` と同じく umask を適用したパーミッション（0o666 & !umask）で作成します。
+///
+/// # Arguments
+///
+/// * `dir` - 出力先のディレクトリ。
+pub(crate) fn new_output_temp_file(dir: &Path) -> io::Result<NamedTempFile> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(TEMP_FILE_PREFIX);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    builder.tempfile_in(dir)
+}
+
+/// 一時ディレクトリにある archrypt の一時ファイルのうち、最終更新から `max_age` 以上経過したものを返します.
+///
+/// 名前が `TEMP_FILE_PREFIX` で始まる通常のファイルのみを対象とします。
+///
+/// # Arguments
+///
+/// * `temp_dir` - 検索する一時ディレクトリ（通常は `std::env::temp_dir()`）。
+/// * `max_age` - これより古いファイルを対象とする経過時間。
+///
+/// # Errors
+///
+/// ディレクトリの読み込みに失敗した場合にエラーを返します。
+pub fn stale_temp_files(temp_dir: &Path, max_age: Duration) -> Result<Vec<PathBuf>> {
+    let now = SystemTime::now();
+    let mut stale = Vec::new();
+    for entry in fs::read_dir(temp_dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(TEMP_FILE_PREFIX) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        // 更新日時が未来の場合は経過時間を求められないため、使用中の可能性があるものとして残す
+        let age = metadata.modified().ok().and_then(|modified| now.duration_since(modified).ok());
+        if age.is_some_and(|age| age >= max_age) {
+            stale.push(entry.path());
+        }
+    }
+    stale.sort();
+    Ok(stale)
+}
+
+/// 異常終了により残った archrypt の一時ファイルを削除します.
+///
+/// `stale_temp_files` が返すファイルを削除します。実行中の別のプロセスが使用している一時ファイルを削除しないよう、
+/// `max_age` には圧縮・展開にかかる時間より十分に長い時間を指定してください。
+///
+/// # Arguments
+///
+/// * `temp_dir` - 検索する一時ディレクトリ（通常は `std::env::temp_dir()`）。
+/// * `max_age` - これより古いファイルを削除する経過時間。
+///
+/// # Returns
+///
+/// 削除したファイルのパスを返します。
+///
+/// # Errors
+///
+/// ディレクトリの読み込み、またはファイルの削除に失敗した場合にエラーを返します。
+pub fn gc_temp_files(temp_dir: &Path, max_age: Duration) -> Result<Vec<PathBuf>> {
+    let stale = stale_temp_files(temp_dir, max_age)?;
+    for path in &stale {
+        fs::remove_file(path)?;
+    }
+    Ok(stale)
+}
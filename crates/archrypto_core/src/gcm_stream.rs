@@ -0,0 +1,191 @@
+use std::io::{self, Write};
+use aes_gcm::aead::consts::U12;
+use aes_gcm::aes::cipher::BlockEncrypt;
+use aes_gcm::aes::Aes256;
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use ctr::Ctr32BE;
+use ghash::universal_hash::{KeyInit, UniversalHash};
+use ghash::{Block, GHash};
+
+/// AES-GCM で1つのメッセージとして暗号化できる平文の最大バイト数（`aes-gcm` クレートと同じ）.
+const P_MAX: u64 = 1 << 36;
+
+/// 1回の `write` で暗号化するバイト数の上限.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// 書き込まれたデータを AES-256-GCM で暗号化しながら `inner` に書き出すライター.
+///
+/// 出力は `Aes256Gcm::encrypt` で一括して暗号化した場合と同じ `暗号文 || 認証タグ（16バイト）` になります。
+/// 平文全体をメモリに保持しないため、大きなZIPデータもファイルやネットワークへ順に書き出せます。
+/// 認証タグは `finish` で書き出すため、最後に必ず `finish` を呼び出してください。
+pub(crate) struct GcmEncryptWriter<W: Write> {
+    inner: W,
+    ctr: Ctr32BE<Aes256>,
+    ghash: GHash,
+    /// 認証タグに XOR する `E(K, J0)`
+    tag_mask: Block,
+    /// 追加認証データのバイト数
+    aad_len: u64,
+    /// これまでに暗号化した平文のバイト数
+    len: u64,
+    /// GHASH にまだ入力していない、16バイトに満たない暗号文
+    pending: Block,
+    pending_len: usize,
+    /// 暗号化に使用する作業領域
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> GcmEncryptWriter<W> {
+    /// 鍵と 96 ビットの Nonce、追加認証データを指定して作成します.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - 暗号文の書き出し先。
+    /// * `key` - AES-256 の鍵。
+    /// * `nonce` - 96 ビットの Nonce。
+    /// * `aad` - 追加認証データ（暗号化はせず、認証タグの計算にのみ使用します）。
+    pub(crate) fn new(inner: W, key: &Key<Aes256Gcm>, nonce: &Nonce<U12>, aad: &[u8]) -> Self {
+        // NIST SP 800-38D: H = E(K, 0^128)、96 ビットの Nonce では J0 = Nonce || 0^31 || 1
+        let mut hash_key = Block::default();
+        Aes256::new(key).encrypt_block(&mut hash_key);
+        let mut ghash = GHash::new(&hash_key);
+        ghash.update_padded(aad);
+
+        let mut j0 = Block::default();
+        j0[..12].copy_from_slice(nonce);
+        j0[15] = 1;
+        let mut ctr = Ctr32BE::<Aes256>::new(key, &j0);
+        // カウンタの最初のブロック（J0）は認証タグのマスクに使い、暗号化は次のブロックから始める
+        let mut tag_mask = Block::default();
+        ctr.apply_keystream(&mut tag_mask);
+
+        GcmEncryptWriter {
+            inner,
+            ctr,
+            ghash,
+            tag_mask,
+            aad_len: aad.len() as u64,
+            len: 0,
+            pending: Block::default(),
+            pending_len: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// 認証タグを書き出して暗号化を完了し、`inner` を返します.
+    ///
+    /// # Errors
+    ///
+    /// 認証タグの書き出しに失敗した場合にエラーを返します。
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        if self.pending_len > 0 {
+            self.ghash.update_padded(&self.pending[..self.pending_len]);
+        }
+        let mut lengths = Block::default();
+        lengths[..8].copy_from_slice(&(self.aad_len * 8).to_be_bytes());
+        lengths[8..].copy_from_slice(&(self.len * 8).to_be_bytes());
+        self.ghash.update(&[lengths]);
+        let mut tag = self.ghash.finalize();
+        for (a, b) in tag.iter_mut().zip(self.tag_mask.iter()) {
+            *a ^= *b;
+        }
+        self.inner.write_all(&tag)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// 暗号文を GHASH に入力します. 16バイトに満たない末尾は次の入力まで保持します。
+    fn absorb(&mut self, mut ciphertext: &[u8]) {
+        if self.pending_len > 0 {
+            let take = (16 - self.pending_len).min(ciphertext.len());
+            self.pending[self.pending_len..self.pending_len + take].copy_from_slice(&ciphertext[..take]);
+            self.pending_len += take;
+            ciphertext = &ciphertext[take..];
+            if self.pending_len < 16 {
+                return;
+            }
+            self.ghash.update(&[self.pending]);
+            self.pending_len = 0;
+        }
+        let mut blocks = ciphertext.chunks_exact(16);
+        for block in &mut blocks {
+            self.ghash.update(&[*Block::from_slice(block)]);
+        }
+        let rest = blocks.remainder();
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.pending_len = rest.len();
+    }
+}
+
+impl<W: Write> Write for GcmEncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(CHUNK_LEN);
+        if self.len + len as u64 > P_MAX {
+            return Err(io::Error::other("data is too large to encrypt as a single AES-GCM message"));
+        }
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+        buffer.extend_from_slice(&buf[..len]);
+        self.ctr.apply_keystream(&mut buffer);
+        self.inner.write_all(&buffer)?;
+        self.absorb(&buffer);
+        self.len += len as u64;
+        self.buffer = buffer;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm::aead::{Aead, Payload};
+
+    /// `data` を `chunk_len` バイトずつ `GcmEncryptWriter` に書き込み、暗号化した結果を返します。
+    fn encrypt_streaming(key: &Key<Aes256Gcm>, nonce: &[u8; 12], aad: &[u8], data: &[u8], chunk_len: usize) -> Vec<u8> {
+        let mut writer = GcmEncryptWriter::new(Vec::new(), key, nonce.into(), aad);
+        for chunk in data.chunks(chunk_len) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn matches_one_shot_aes_gcm() {
+        let key = Key::<Aes256Gcm>::from([7u8; 32]);
+        let nonce = [3u8; 12];
+        let cipher = Aes256Gcm::new(&key);
+        for len in [0, 1, 15, 16, 17, 31, 32, 33, 1000, CHUNK_LEN + 5, 3 * CHUNK_LEN] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 31 % 251) as u8).collect();
+            for aad in [&b""[..], b"header", &[0xAAu8; 40]] {
+                let expected = cipher.encrypt((&nonce).into(), Payload { msg: &data, aad }).unwrap();
+                for chunk_len in [1, 7, 16, 100, CHUNK_LEN * 2] {
+                    assert_eq!(
+                        encrypt_streaming(&key, &nonce, aad, &data, chunk_len),
+                        expected,
+                        "len={} aad_len={} chunk_len={}",
+                        len,
+                        aad.len(),
+                        chunk_len
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn output_decrypts_with_aes_gcm() {
+        let key = Key::<Aes256Gcm>::from([9u8; 32]);
+        let nonce = [1u8; 12];
+        let data = vec![0x5Au8; 100_000];
+        let encrypted = encrypt_streaming(&key, &nonce, b"aad", &data, 4096);
+        let decrypted = Aes256Gcm::new(&key)
+            .decrypt((&nonce).into(), Payload { msg: &encrypted, aad: b"aad" })
+            .unwrap();
+        assert_eq!(decrypted, data);
+    }
+}
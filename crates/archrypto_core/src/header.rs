@@ -0,0 +1,291 @@
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Result};
+
+/// `.acrp` ファイル先頭のマジックバイト.
+pub const MAGIC: &[u8; 4] = b"ACRP";
+/// 現在書き出しているヘッダのバージョン.
+pub const CURRENT_VERSION: u16 = 1;
+/// ヘッダに保存できるコメントの最大バイト数（UTF-8）.
+pub const MAX_COMMENT_LEN: usize = 256;
+
+/// マジックバイト + バージョン(u16) + フィールド部の長さ(u32)
+const FIXED_LEN: usize = MAGIC.len() + 2 + 4;
+/// フィールド部の長さの上限（壊れたファイルで巨大な確保をしないため）
+const MAX_FIELDS_LEN: u32 = 16 * 1024 * 1024;
+
+const TAG_COMMENT: u8 = 1;
+const TAG_RECIPIENT_KEY_ID: u8 = 2;
+const TAG_CREATED_AT: u8 = 3;
+const TAG_CREATOR: u8 = 4;
+const TAG_TOOL_VERSION: u8 = 5;
+const TAG_INPUT_CHECKSUM: u8 = 6;
+const TAG_METADATA: u8 = 7;
+
+/// `.acrp` ファイルの平文ヘッダ.
+///
+/// ヘッダは暗号化されず、秘密鍵なしで読み取ることができます。
+/// 改ざん検出のため、ヘッダ全体は AES-GCM の追加認証データ（AAD）として暗号文に紐付けられます。
+///
+/// バイナリレイアウト:
+///
+/// ```text
+/// "ACRP" | version: u16 BE | fields_len: u32 BE | fields... | nonce(12) | key_size: u16 BE | 暗号化AES鍵 | 暗号化ZIP
+/// ```
+///
+/// 各フィールドは `tag: u8 | len: u32 BE | value` の形式で、未知のタグは読み飛ばします。
+/// マジックバイトで始まらないファイルは、ヘッダを持たない旧形式（バージョン 0）として扱います。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveHeader {
+    /// ヘッダのバージョン。旧形式のファイルは 0 になります。
+    pub version: u16,
+    /// アーカイブのコメント（暗号化されません）
+    pub comment: Option<String>,
+    /// 暗号化に使用した公開鍵のフィンガープリント（`SHA256:xx:xx:...`）
+    pub recipient_key_id: Option<String>,
+    /// アーカイブの作成日時（UTC、秒単位。ヘッダには Unix 時間の u64 BE で保存します）
+    pub created_at: Option<SystemTime>,
+    /// アーカイブの作成者（バックアップスクリプト名やユーザー名など）
+    pub creator: Option<String>,
+    /// アーカイブを作成した archrypto_core のバージョン
+    pub tool_version: Option<String>,
+    /// 格納したファイルのツリーチェックサム（`compute_tree_checksum` と同じ形式の SHA-256）
+    pub input_checksum: Option<[u8; 32]>,
+    /// 利用者が定義したメタデータ（JSON 文字列。暗号化されません）
+    pub metadata: Option<String>,
+}
+
+impl ArchiveHeader {
+    /// 現在のバージョンの空のヘッダを作成します.
+    pub fn new() -> Self {
+        ArchiveHeader {
+            version: CURRENT_VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// ヘッダをバイト列にエンコードします.
+    ///
+    /// バージョン 0（旧形式）のヘッダは空のバイト列になります。
+    pub fn encode(&self) -> Vec<u8> {
+        if self.version == 0 {
+            return Vec::new();
+        }
+        let mut fields = Vec::new();
+        if let Some(comment) = &self.comment {
+            push_field(&mut fields, TAG_COMMENT, comment.as_bytes());
+        }
+        if let Some(key_id) = &self.recipient_key_id {
+            push_field(&mut fields, TAG_RECIPIENT_KEY_ID, key_id.as_bytes());
+        }
+        if let Some(created_at) = self.created_at {
+            let secs = created_at.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+            push_field(&mut fields, TAG_CREATED_AT, &secs.to_be_bytes());
+        }
+        if let Some(creator) = &self.creator {
+            push_field(&mut fields, TAG_CREATOR, creator.as_bytes());
+        }
+        if let Some(tool_version) = &self.tool_version {
+            push_field(&mut fields, TAG_TOOL_VERSION, tool_version.as_bytes());
+        }
+        if let Some(input_checksum) = &self.input_checksum {
+            push_field(&mut fields, TAG_INPUT_CHECKSUM, input_checksum);
+        }
+        if let Some(metadata) = &self.metadata {
+            push_field(&mut fields, TAG_METADATA, metadata.as_bytes());
+        }
+
+        let mut encoded = Vec::with_capacity(FIXED_LEN + fields.len());
+        encoded.extend_from_slice(MAGIC);
+        encoded.extend_from_slice(&self.version.to_be_bytes());
+        encoded.extend_from_slice(&(fields.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(&fields);
+        encoded
+    }
+
+    /// バイト列の先頭からヘッダを読み取ります.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - `.acrp` ファイルの内容。
+    ///
+    /// # Returns
+    ///
+    /// ヘッダと、ヘッダ部分のバイト数（= nonce の開始位置）を返します。
+    /// 旧形式のファイルの場合はバージョン 0 のヘッダと 0 を返します。
+    ///
+    /// # Errors
+    ///
+    /// ヘッダが途中で途切れている場合や、フィールドの形式が不正な場合にエラーを返します。
+    pub fn parse(data: &[u8]) -> Result<(ArchiveHeader, usize)> {
+        if !data.starts_with(MAGIC) {
+            return Ok((ArchiveHeader::default(), 0));
+        }
+        let mut reader = data;
+        let header = Self::read_from(&mut reader)?;
+        Ok((header, data.len() - reader.len()))
+    }
+
+    /// リーダーの先頭からヘッダを読み取ります.
+    ///
+    /// 旧形式のファイルの場合はバージョン 0 のヘッダを返します（この場合、先頭の数バイトは消費されます）。
+    ///
+    /// # Errors
+    ///
+    /// 読み込みに失敗した場合や、ヘッダの形式が不正な場合にエラーを返します。
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<ArchiveHeader> {
+        let mut magic = [0u8; 4];
+        if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+            return Ok(ArchiveHeader::default());
+        }
+        let mut fixed = [0u8; FIXED_LEN - 4];
+        reader
+            .read_exact(&mut fixed)
+            .map_err(|_| anyhow!("Archive header is truncated"))?;
+        let version = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let fields_len = u32::from_be_bytes([fixed[2], fixed[3], fixed[4], fixed[5]]);
+        if version == 0 || version > CURRENT_VERSION {
+            return Err(anyhow!("Unsupported archive version: {}", version));
+        }
+        if fields_len > MAX_FIELDS_LEN {
+            return Err(anyhow!("Archive header is too large: {} bytes", fields_len));
+        }
+        let mut fields = vec![0u8; fields_len as usize];
+        reader
+            .read_exact(&mut fields)
+            .map_err(|_| anyhow!("Archive header is truncated"))?;
+
+        let mut header = ArchiveHeader {
+            version,
+            ..Default::default()
+        };
+        let mut rest = fields.as_slice();
+        while !rest.is_empty() {
+            if rest.len() < 5 {
+                return Err(anyhow!("Archive header field is truncated"));
+            }
+            let tag = rest[0];
+            let len = u32::from_be_bytes([rest[1], rest[2], rest[3], rest[4]]) as usize;
+            let value = rest
+                .get(5..5 + len)
+                .ok_or_else(|| anyhow!("Archive header field is truncated"))?;
+            // 未知のフィールドは後方互換のため読み飛ばす
+            match tag {
+                TAG_COMMENT => header.comment = Some(String::from_utf8(value.to_vec())?),
+                TAG_RECIPIENT_KEY_ID => header.recipient_key_id = Some(String::from_utf8(value.to_vec())?),
+                TAG_CREATED_AT => {
+                    let secs: [u8; 8] = value
+                        .try_into()
+                        .map_err(|_| anyhow!("Invalid creation time in archive header"))?;
+                    header.created_at = Some(UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(secs)));
+                }
+                TAG_CREATOR => header.creator = Some(String::from_utf8(value.to_vec())?),
+                TAG_TOOL_VERSION => header.tool_version = Some(String::from_utf8(value.to_vec())?),
+                TAG_INPUT_CHECKSUM => {
+                    let checksum: [u8; 32] = value
+                        .try_into()
+                        .map_err(|_| anyhow!("Invalid input checksum in archive header"))?;
+                    header.input_checksum = Some(checksum);
+                }
+                TAG_METADATA => header.metadata = Some(String::from_utf8(value.to_vec())?),
+                _ => {}
+            }
+            rest = &rest[5 + len..];
+        }
+        Ok(header)
+    }
+}
+
+/// `tag | len | value` 形式のフィールドを追加します.
+fn push_field(buffer: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    buffer.push(tag);
+    buffer.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
+
+/// コメントを `MAX_COMMENT_LEN` バイト以内に収まるよう、文字境界で切り詰めます.
+///
+/// # Returns
+///
+/// 切り詰めたコメントと、切り詰めが発生したかどうかを返します。
+pub fn truncate_comment(comment: &str) -> (String, bool) {
+    if comment.len() <= MAX_COMMENT_LEN {
+        return (comment.to_string(), false);
+    }
+    let mut end = MAX_COMMENT_LEN;
+    while !comment.is_char_boundary(end) {
+        end -= 1;
+    }
+    (comment[..end].to_string(), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_header() -> ArchiveHeader {
+        ArchiveHeader {
+            comment: Some("nightly backup".to_string()),
+            recipient_key_id: Some("SHA256:00:11:22".to_string()),
+            created_at: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            creator: Some("backup.sh".to_string()),
+            tool_version: Some("0.1.0".to_string()),
+            input_checksum: Some([0xab; 32]),
+            metadata: Some(r#"{"host":"db1"}"#.to_string()),
+            ..ArchiveHeader::new()
+        }
+    }
+
+    #[test]
+    fn encode_and_parse_round_trip_every_field() {
+        let header = full_header();
+        let mut data = header.encode();
+        let header_len = data.len();
+        data.extend_from_slice(b"nonce follows");
+
+        let (parsed, len) = ArchiveHeader::parse(&data).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(len, header_len);
+        assert_eq!(ArchiveHeader::read_from(&mut &data[..]).unwrap(), header);
+    }
+
+    #[test]
+    fn parse_treats_data_without_magic_as_legacy_version_0() {
+        let (parsed, len) = ArchiveHeader::parse(b"\x01\x02\x03 legacy nonce").unwrap();
+        assert_eq!(parsed, ArchiveHeader::default());
+        assert_eq!(len, 0);
+        assert!(ArchiveHeader::default().encode().is_empty());
+    }
+
+    #[test]
+    fn parse_skips_unknown_fields() {
+        let mut fields = Vec::new();
+        push_field(&mut fields, 0xfe, b"from a newer version");
+        push_field(&mut fields, TAG_COMMENT, b"kept");
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&CURRENT_VERSION.to_be_bytes());
+        data.extend_from_slice(&(fields.len() as u32).to_be_bytes());
+        data.extend_from_slice(&fields);
+
+        let (parsed, len) = ArchiveHeader::parse(&data).unwrap();
+        assert_eq!(parsed.comment.as_deref(), Some("kept"));
+        assert_eq!(len, data.len());
+    }
+
+    #[test]
+    fn parse_rejects_unsupported_version_and_truncation() {
+        let mut data = full_header().encode();
+        assert!(ArchiveHeader::parse(&data[..data.len() - 1]).is_err());
+        data[4..6].copy_from_slice(&(CURRENT_VERSION + 1).to_be_bytes());
+        assert!(ArchiveHeader::parse(&data).is_err());
+    }
+
+    #[test]
+    fn truncate_comment_respects_char_boundaries() {
+        let (comment, truncated) = truncate_comment(&"あ".repeat(100));
+        assert!(truncated);
+        assert!(comment.len() <= MAX_COMMENT_LEN);
+        assert_eq!(comment, "あ".repeat(MAX_COMMENT_LEN / 3));
+        assert_eq!(truncate_comment("short"), ("short".to_string(), false));
+    }
+}
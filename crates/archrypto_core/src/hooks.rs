@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use anyhow::{anyhow, Context, Result};
+
+use crate::ArchiveStats;
+
+/// 圧縮後フックに統計情報を JSON で渡す環境変数名.
+const HOOK_STATS_ENV: &str = "ARCHRYPT_STATS";
+
+/// 圧縮前フックを実行します.
+///
+/// コマンド文字列を空白で区切り、`{files}` だけの引数は対象パスの一覧に展開します。
+/// `{files}` を含まない場合は、対象パスをコマンドの末尾に引数として追加します。
+///
+/// # Arguments
+///
+/// * `hook` - 実行するコマンド文字列（例: `"clamscan {files}"`）。
+/// * `target_pathes` - 圧縮対象のパスのリスト。
+///
+/// # Errors
+///
+/// コマンドを起動できない場合や、終了コードが 0 以外の場合にエラーを返します。
+pub(crate) fn run_pre_compress_hook(hook: &str, target_pathes: &[PathBuf]) -> Result<()> {
+    let files: Vec<String> = target_pathes.iter().map(|path| path.display().to_string()).collect();
+    let mut args = Vec::new();
+    let mut has_files = false;
+    for word in hook.split_whitespace() {
+        if word == "{files}" {
+            args.extend(files.iter().cloned());
+            has_files = true;
+        } else {
+            args.push(word.to_string());
+        }
+    }
+    if !has_files {
+        args.extend(files);
+    }
+    run_hook("pre-compress", hook, &args, None)
+}
+
+/// 圧縮後フックを実行します.
+///
+/// コマンド文字列を空白で区切り、各引数の `{output}` を出力ファイルのパスに置き換えます。
+/// 統計情報は JSON 形式で環境変数 `ARCHRYPT_STATS` に設定します。
+///
+/// # Arguments
+///
+/// * `hook` - 実行するコマンド文字列（例: `"aws s3 cp {output} s3://bucket/"`）。
+/// * `output_path` - 作成した `.acrp` ファイルのパス。
+/// * `stats` - 圧縮処理の統計情報。
+///
+/// # Errors
+///
+/// コマンドを起動できない場合や、終了コードが 0 以外の場合にエラーを返します。
+pub(crate) fn run_post_compress_hook(hook: &str, output_path: &Path, stats: &ArchiveStats) -> Result<()> {
+    let output = output_path.display().to_string();
+    let args: Vec<String> = hook
+        .split_whitespace()
+        .map(|word| word.replace("{output}", &output))
+        .collect();
+    let stats_json = serde_json::to_string(stats)?;
+    run_hook("post-compress", hook, &args, Some(&stats_json))
+}
+
+/// 展開後フックを実行します.
+///
+/// コマンド文字列を空白で区切り、各引数の `{output_dir}` を展開先のディレクトリに置き換えます。
+/// `{output_dir}` を含まない場合は、展開先をコマンドの末尾に引数として追加します。
+/// 統計情報は JSON 形式で環境変数 `ARCHRYPT_STATS` に設定します。
+///
+/// # Arguments
+///
+/// * `hook` - 実行するコマンド文字列（例: `"bash deploy.sh {output_dir}"`）。
+/// * `output_dir` - 展開先のディレクトリ。
+/// * `stats` - 展開処理の統計情報。
+///
+/// # Errors
+///
+/// コマンドを起動できない場合や、終了コードが 0 以外の場合にエラーを返します。
+pub(crate) fn run_post_extract_hook(hook: &str, output_dir: &Path, stats: &ArchiveStats) -> Result<()> {
+    let output_dir = output_dir.display().to_string();
+    let mut args: Vec<String> = hook
+        .split_whitespace()
+        .map(|word| word.replace("{output_dir}", &output_dir))
+        .collect();
+    if !hook.contains("{output_dir}") {
+        args.push(output_dir);
+    }
+    let stats_json = serde_json::to_string(stats)?;
+    run_hook("post-extract", hook, &args, Some(&stats_json))
+}
+
+/// 引数リストの先頭をコマンドとして、標準入出力を引き継いで実行します.
+fn run_hook(name: &str, hook: &str, args: &[String], stats_json: Option<&str>) -> Result<()> {
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("The {} hook is empty", name))?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    if let Some(stats_json) = stats_json {
+        command.env(HOOK_STATS_ENV, stats_json);
+    }
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run {} hook: {}", name, hook))?;
+    if !status.success() {
+        return Err(anyhow!("The {} hook failed ({}): {}", name, status, hook));
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use crate::decrypt_archive_entries;
+use crate::manifest::{read_manifest_json, to_hex, EMBEDDED_MANIFEST_NAME};
+
+/// `verify_archive_integrity` の照合結果.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// マニフェストに記録された全てのエントリがアーカイブに存在する場合は true。
+    pub all_entries_present: bool,
+    /// マニフェストに記録されているが、アーカイブに存在しないエントリ
+    pub missing_entries: Vec<String>,
+    /// 内容の SHA-256 がマニフェストと一致しないエントリ
+    pub hash_mismatches: Vec<String>,
+    /// アーカイブに存在するが、マニフェストに記録されていないエントリ
+    pub unexpected_entries: Vec<String>,
+    /// いずれかの照合に失敗した場合は true。1バイトでも内容が異なれば true になります。
+    pub tampered: bool,
+}
+
+/// `.acrp` ファイルを復号し、`CompressOptions::emit_manifest` で書き出したマニフェストJSONと照合します.
+///
+/// 各エントリの有無と内容の SHA-256 を確認し、作成後にアーカイブが改ざんされていないかを調べます。
+/// アーカイブはディスクには展開せず、メモリ上で照合します。
+/// 埋め込みマニフェスト（`MANIFEST.sha256`）はマニフェストJSONに記録されないため、想定外のエントリには含めません。
+///
+/// # Arguments
+///
+/// * `archive` - 照合する `.acrp` ファイルのパス。
+/// * `manifest` - アーカイブの作成時に書き出したマニフェストJSON（`<アーカイブ>.manifest.json`）のパス。
+/// * `private_key` - 復号に使用する秘密鍵ファイルのパス。
+///
+/// # Returns
+///
+/// 照合結果を返します。改ざんを検出してもエラーにはせず、`IntegrityReport::tampered` を true にします。
+///
+/// # Errors
+///
+/// ファイルの読み込み、マニフェストのパース、秘密鍵のパース、またはアーカイブの復号に失敗した場合にエラーを返します。
+pub fn verify_archive_integrity(
+    archive: impl AsRef<Path>,
+    manifest: impl AsRef<Path>,
+    private_key: impl AsRef<Path>,
+) -> Result<IntegrityReport> {
+    let manifest = read_manifest_json(manifest.as_ref())?;
+    let entries = decrypt_archive_entries(archive.as_ref(), private_key.as_ref())?;
+    let digests: HashMap<&str, String> = entries
+        .iter()
+        .map(|(name, data)| (name.as_str(), to_hex(&Sha256::digest(data))))
+        .collect();
+
+    let mut report = IntegrityReport::default();
+    for entry in &manifest.entries {
+        match digests.get(entry.path.as_str()) {
+            None => report.missing_entries.push(entry.path.clone()),
+            Some(actual) if !actual.eq_ignore_ascii_case(&entry.sha256) => report.hash_mismatches.push(entry.path.clone()),
+            Some(_) => {}
+        }
+    }
+    let recorded: HashSet<&str> = manifest.entries.iter().map(|entry| entry.path.as_str()).collect();
+    report.unexpected_entries = entries
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| *name != EMBEDDED_MANIFEST_NAME && !recorded.contains(name))
+        .map(str::to_string)
+        .collect();
+    report.all_entries_present = report.missing_entries.is_empty();
+    report.tampered =
+        !report.all_entries_present || !report.hash_mismatches.is_empty() || !report.unexpected_entries.is_empty();
+    Ok(report)
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::{ZipArchive, ZipWriter};
+
+use crate::manifest::to_hex;
+
+/// 再開用ジャーナルを保存するディレクトリの、ホームディレクトリからの相対パス.
+const JOURNAL_DIR: &str = ".archrypt/journal";
+
+/// ジャーナルに記録した、ZIPへの格納が完了した1つのファイル.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// 格納したファイルのパス
+    pub path: PathBuf,
+    /// ファイルの内容の SHA-256（16進数文字列）
+    pub sha256: String,
+    /// ファイルのバイト数
+    pub size: u64,
+    /// 格納した時点のファイルの更新日時。取得できなかった場合は None。
+    pub modified: Option<SystemTime>,
+}
+
+/// 中断した `compress_files` を再開するためのジャーナル（`CompressOptions::resume`）.
+///
+/// 出力パスごとに `~/.archrypt/journal/<出力パスのハッシュ>.jsonl` を作成し、ZIPへの格納が完了したファイルを1行ずつ記録します。
+/// 各ファイルは同じ名前のディレクトリに1エントリだけのZIP（ステージング）として圧縮してから出力のZIPにコピーするため、
+/// 次回同じ出力パスで実行した際は、記録されたファイルを読み込み・圧縮・ハッシュ計算し直さずにステージングからコピーします。
+/// ステージングには暗号化前の圧縮データが含まれるため、ディレクトリは所有者のみが読み書きできるパーミッションで作成します。
+pub struct Journal {
+    /// ジャーナルファイル（`<hash>.jsonl`）のパス
+    path: PathBuf,
+    /// ステージングのZIPを置くディレクトリ（`<hash>/`）
+    staging_dir: PathBuf,
+    /// 追記用に開いたジャーナルファイル
+    file: File,
+    /// 記録済みのエントリ（同じパスが複数回記録されている場合は最後のもの）
+    entries: HashMap<PathBuf, JournalEntry>,
+}
+
+impl Journal {
+    /// `output` への圧縮に対応するジャーナルを `~/.archrypt/journal` に開きます. 存在しない場合は作成します。
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - 圧縮の出力先パス（`compress_files` の `output_crypted`）。
+    ///
+    /// # Errors
+    ///
+    /// ホームディレクトリが分からない場合、またはジャーナルの読み込み・作成に失敗した場合にエラーを返します。
+    pub fn open(output: impl AsRef<Path>) -> Result<Journal> {
+        let home = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .filter(|home| !home.is_empty())
+            .ok_or_else(|| anyhow!("Cannot determine the home directory for the resume journal"))?;
+        Self::open_in(&PathBuf::from(home).join(JOURNAL_DIR), output.as_ref())
+    }
+
+    /// `dir` にある、`output` への圧縮に対応するジャーナルを開きます.
+    ///
+    /// 中断によって途中で途切れた行は無視します。
+    pub(crate) fn open_in(dir: &Path, output: &Path) -> Result<Journal> {
+        create_private_dir(dir)?;
+        let name = to_hex(&Sha256::digest(std::path::absolute(output)?.as_os_str().as_encoded_bytes())[..8]);
+        let path = dir.join(format!("{}.jsonl", name));
+        let staging_dir = dir.join(name);
+        create_private_dir(&staging_dir)?;
+
+        let mut entries = HashMap::new();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        for line in contents.lines() {
+            if let Ok(entry) = serde_json::from_str::<JournalEntry>(line) {
+                entries.insert(entry.path.clone(), entry);
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        // 途中で途切れた行の後ろに続けて書き込まないよう、改行で終わっていなければ改行を追加する
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+        debug!("Opened resume journal {} with {} entries", path.display(), entries.len());
+        Ok(Journal { path, staging_dir, file, entries })
+    }
+
+    /// ジャーナルファイルのパスを返します.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 記録済みのエントリの数を返します.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 記録済みのエントリがなければ true を返します.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// ZIPへの格納が完了したファイルをジャーナルに追記します.
+    ///
+    /// # Errors
+    ///
+    /// ジャーナルファイルへの書き込みに失敗した場合にエラーを返します。
+    pub fn record_entry(&mut self, entry: JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        // 中断されても行が混ざらないよう、1行を1回の書き込みで追記する
+        self.file.write_all(line.as_bytes())?;
+        self.entries.insert(entry.path.clone(), entry);
+        Ok(())
+    }
+
+    /// 圧縮が完了したため、ジャーナルファイルとステージングを削除します.
+    ///
+    /// # Errors
+    ///
+    /// ファイルまたはディレクトリの削除に失敗した場合にエラーを返します。
+    pub fn complete(self) -> Result<()> {
+        drop(self.file);
+        remove_if_exists(fs::remove_file(&self.path))?;
+        remove_if_exists(fs::remove_dir_all(&self.staging_dir))?;
+        debug!("Completed resume journal {}", self.path.display());
+        Ok(())
+    }
+
+    /// `path` のステージングのZIPのパスを返します.
+    fn staging_path(&self, path: &Path) -> PathBuf {
+        let name = to_hex(&Sha256::digest(path.as_os_str().as_encoded_bytes())[..16]);
+        self.staging_dir.join(format!("{}.zip", name))
+    }
+
+    /// `path` が記録済みで、サイズと更新日時が記録時から変わっていなければ、ステージングのZIPを `zip` にコピーします.
+    ///
+    /// ステージングのZIPが `entry_name` の1エントリだけを含まない場合（エントリ名の決め方を変えた場合など）はコピーしません。
+    ///
+    /// # Returns
+    ///
+    /// コピーした場合は記録済みのエントリを返し、コピーしなかった場合は None を返します。
+    ///
+    /// # Errors
+    ///
+    /// `zip` への書き込みに失敗した場合にエラーを返します。
+    pub(crate) fn copy_staged<W: Write + Seek>(
+        &self,
+        zip: &mut ZipWriter<W>,
+        path: &Path,
+        entry_name: &str,
+        size: u64,
+        modified: Option<SystemTime>,
+    ) -> Result<Option<&JournalEntry>> {
+        let Some(entry) = self.entries.get(path) else {
+            return Ok(None);
+        };
+        if entry.size != size || entry.modified != modified {
+            debug!("{} changed since it was journaled; adding it again", path.display());
+            return Ok(None);
+        }
+        let Some(staged) = File::open(self.staging_path(path)).ok().and_then(|file| ZipArchive::new(file).ok()) else {
+            return Ok(None);
+        };
+        if staged.len() != 1 || staged.name_for_index(0) != Some(entry_name) {
+            return Ok(None);
+        }
+        zip.merge_archive(staged)?;
+        Ok(Some(entry))
+    }
+
+    /// `path` のステージングのZIPを作成します. `finish_staged` で完了してください。
+    ///
+    /// # Errors
+    ///
+    /// ファイルの作成に失敗した場合にエラーを返します。
+    pub(crate) fn create_staged(&self, path: &Path) -> Result<ZipWriter<BufWriter<File>>> {
+        Ok(ZipWriter::new(BufWriter::new(File::create(self.staging_path(path))?)))
+    }
+
+    /// `create_staged` で作成したステージングのZIPを完了し、ジャーナルに記録してから `zip` にコピーします.
+    ///
+    /// # Errors
+    ///
+    /// ステージングのZIPの書き出し、ジャーナルへの記録、または `zip` への書き込みに失敗した場合にエラーを返します。
+    pub(crate) fn finish_staged<W: Write + Seek>(
+        &mut self,
+        staged: ZipWriter<BufWriter<File>>,
+        zip: &mut ZipWriter<W>,
+        entry: JournalEntry,
+    ) -> Result<()> {
+        staged.finish()?.into_inner().map_err(io::IntoInnerError::into_error)?;
+        let staging_path = self.staging_path(&entry.path);
+        self.record_entry(entry)?;
+        zip.merge_archive(ZipArchive::new(File::open(staging_path)?)?)?;
+        Ok(())
+    }
+}
+
+/// 16進数文字列の SHA-256 をバイト列に変換します. 形式が不正な場合は None を返します。
+pub(crate) fn digest_from_hex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (byte, chunk) in digest.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// 所有者のみが読み書きできるディレクトリを作成します（既に存在する場合は何もしません）.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+/// 削除対象が存在しなかった場合のエラーを無視します.
+fn remove_if_exists(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64) -> JournalEntry {
+        JournalEntry { path: PathBuf::from(path), sha256: "00".repeat(32), size, modified: Some(SystemTime::UNIX_EPOCH) }
+    }
+
+    #[test]
+    fn reopened_journal_keeps_recorded_entries_and_ignores_a_torn_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.acrp");
+        let mut journal = Journal::open_in(dir.path(), &output).unwrap();
+        assert!(journal.is_empty());
+        journal.record_entry(entry("a.txt", 1)).unwrap();
+        journal.record_entry(entry("b.txt", 2)).unwrap();
+        let journal_path = journal.path().to_path_buf();
+        drop(journal);
+        // 書き込みの途中で中断された行
+        OpenOptions::new().append(true).open(&journal_path).unwrap().write_all(b"{\"path\":\"c.t").unwrap();
+
+        let mut journal = Journal::open_in(dir.path(), &output).unwrap();
+        assert_eq!(journal.len(), 2);
+        assert_eq!(journal.entries.get(Path::new("b.txt")), Some(&entry("b.txt", 2)));
+        journal.record_entry(entry("c.txt", 3)).unwrap();
+        drop(journal);
+        assert_eq!(Journal::open_in(dir.path(), &output).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn journals_are_separate_per_output_and_complete_removes_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut journal = Journal::open_in(dir.path(), &dir.path().join("one.acrp")).unwrap();
+        journal.record_entry(entry("a.txt", 1)).unwrap();
+        assert!(Journal::open_in(dir.path(), &dir.path().join("two.acrp")).unwrap().is_empty());
+
+        let journal_path = journal.path().to_path_buf();
+        journal.complete().unwrap();
+        assert!(!journal_path.exists());
+        assert!(!journal_path.with_extension("").exists());
+        assert!(Journal::open_in(dir.path(), &dir.path().join("one.acrp")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn digest_from_hex_parses_only_sha256_hex() {
+        let hex = to_hex(&Sha256::digest(b"archrypt"));
+        assert_eq!(digest_from_hex(&hex).map(|digest| to_hex(&digest)), Some(hex));
+        assert_eq!(digest_from_hex("abcd"), None);
+        assert_eq!(digest_from_hex(&"zz".repeat(32)), None);
+    }
+}
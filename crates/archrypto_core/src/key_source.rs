@@ -0,0 +1,82 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::path::PathBuf;
+use anyhow::Result;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+
+use crate::{load_private_key, load_public_key};
+
+/// 暗号化に使用する公開鍵の取得元.
+#[derive(Debug, Clone)]
+pub enum PublicKeySource {
+    /// PEM 形式の公開鍵（または X.509 証明書）ファイルから読み込みます。
+    FromPath(PathBuf),
+    /// 読み込み済みの公開鍵をそのまま使用します。PEM のパースは行いません。
+    InMemory(RsaPublicKey),
+}
+
+impl PublicKeySource {
+    /// 公開鍵を返します. `FromPath` の場合はファイルを読み込みます。
+    ///
+    /// # Errors
+    ///
+    /// ファイルの読み込み、または公開鍵のパースに失敗した場合にエラーを返します。
+    pub fn load(&self) -> Result<Cow<'_, RsaPublicKey>> {
+        match self {
+            PublicKeySource::FromPath(path) => Ok(Cow::Owned(load_public_key(path)?)),
+            PublicKeySource::InMemory(key) => Ok(Cow::Borrowed(key)),
+        }
+    }
+}
+
+impl fmt::Display for PublicKeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublicKeySource::FromPath(path) => write!(f, "{}", path.display()),
+            PublicKeySource::InMemory(_) => write!(f, "(in-memory public key)"),
+        }
+    }
+}
+
+/// 復号に使用する秘密鍵の取得元.
+///
+/// `Debug` では秘密鍵の内容を表示しません。
+#[derive(Clone)]
+pub enum PrivateKeySource {
+    /// PKCS#8 PEM 形式の秘密鍵ファイルから読み込みます。
+    FromPath(PathBuf),
+    /// 読み込み済みの秘密鍵をそのまま使用します。PEM のパースは行いません。
+    InMemory(Box<RsaPrivateKey>),
+}
+
+impl PrivateKeySource {
+    /// 秘密鍵を返します. `FromPath` の場合はファイルを読み込みます。
+    ///
+    /// # Errors
+    ///
+    /// ファイルの読み込み、または秘密鍵のパースに失敗した場合にエラーを返します。
+    pub fn load(&self) -> Result<Cow<'_, RsaPrivateKey>> {
+        match self {
+            PrivateKeySource::FromPath(path) => Ok(Cow::Owned(load_private_key(path)?)),
+            PrivateKeySource::InMemory(key) => Ok(Cow::Borrowed(key.as_ref())),
+        }
+    }
+}
+
+impl fmt::Debug for PrivateKeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrivateKeySource::FromPath(path) => f.debug_tuple("FromPath").field(path).finish(),
+            PrivateKeySource::InMemory(_) => f.debug_tuple("InMemory").field(&"<redacted>").finish(),
+        }
+    }
+}
+
+impl fmt::Display for PrivateKeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrivateKeySource::FromPath(path) => write!(f, "{}", path.display()),
+            PrivateKeySource::InMemory(_) => write!(f, "(in-memory private key)"),
+        }
+    }
+}
@@ -1,19 +1,519 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, canonicalize, create_dir_all, File};
-use std::io::{BufReader, BufWriter, Write, Read, copy};
-use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::io::{self, BufRead, BufReader, BufWriter, Write, Read, Seek, SeekFrom, copy};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use zip::{ZipArchive,write::{SimpleFileOptions, ZipWriter}};
-use rsa::{RsaPrivateKey,RsaPublicKey,pkcs8::DecodePrivateKey, pkcs8::DecodePublicKey,Pkcs1v15Encrypt,rand_core::OsRng};
-use aes_gcm::{Aes256Gcm, Nonce}; // AES-GCM
-use aes_gcm::aead::{generic_array::{GenericArray,typenum::U12,typenum::U32},Aead, AeadCore, KeyInit,Payload}; // AES-GCMのユーティリティ
-use anyhow::{anyhow, Ok, Result};
-use walkdir::WalkDir;
-use indicatif::{ProgressBar, ProgressStyle};
+use rsa::{RsaPrivateKey,RsaPublicKey,traits::PublicKeyParts,pkcs8::DecodePrivateKey, pkcs8::DecodePublicKey,pkcs8::EncodePrivateKey,pkcs8::EncodePublicKey,Oaep,Pkcs1v15Encrypt,rand_core::{OsRng, RngCore}};
+use sha2::{Digest, Sha256};
+use aes_gcm::Aes256Gcm; // AES-GCM
+use aes_gcm::aead::{AeadCore, KeyInit}; // AES-GCMのユーティリティ
+use anyhow::{anyhow, Context, Ok, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ignore::{overrides::OverrideBuilder, DirEntry, WalkBuilder};
+use log::{debug, trace, warn};
 use tempfile::NamedTempFile;
 
+mod archive_diff;
+mod armor;
+mod bench;
+mod certificate;
+mod content_type;
+mod dedup;
+mod diagnostics;
+mod entropy;
+mod error;
+mod gc;
+mod gcm_stream;
+mod header;
+mod hooks;
+mod integrity;
+mod journal;
+mod key_source;
+mod listing;
+mod manifest;
+mod merge;
+mod output_name;
+mod passphrase;
+mod selftest;
+pub mod pipeline;
+mod ssh;
+mod target_manifest;
+mod timefmt;
+mod tree_checksum;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+mod progress;
+mod retry;
+mod revocation;
+
+pub use archive_diff::{diff_archives, ArchiveDiffEntry, ChangeType};
+pub use bench::{run_benchmark, BenchResult};
+pub use certificate::{certificate_pem, create_self_signed_rsa_cert};
+pub use diagnostics::{decrypt_with_diagnostics, DiagnosticDecryptResult};
+pub use entropy::entropy_available;
+pub use error::{ArchryptoError, KeyType};
+pub use gc::{gc_temp_files, stale_temp_files, TEMP_FILE_PREFIX};
+pub use header::{ArchiveHeader, MAX_COMMENT_LEN};
+pub use integrity::{verify_archive_integrity, IntegrityReport};
+pub use journal::{Journal, JournalEntry};
+pub use key_source::{PrivateKeySource, PublicKeySource};
+pub use listing::{list_archive_entries, ArchiveEntry};
+pub use manifest::{manifest_json_path, ManifestEntry, ManifestJson, ManifestWriter, EMBEDDED_MANIFEST_NAME, MANIFEST_JSON_VERSION};
+pub use merge::{merge_archives, ConflictPolicy};
+pub use output_name::expand_output_template;
+pub use passphrase::{read_passphrase, PassphraseSource};
+pub use revocation::{check_revocation, CrlRevocationChecker, RevocationChecker};
+pub use selftest::{run_selftest, SelfTestReport, SelfTestStep};
+pub use ssh::import_ssh_pubkey;
+pub use target_manifest::{Manifest, ManifestTarget};
+pub use timefmt::format_iso8601;
+pub use tree_checksum::compute_tree_checksum;
+
 const EXTENTION: &str = "acrp";
-const PROGRESS_SETTING: &str = "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})";
-const PROGRESS_BAR_CHAR: &str = "#>-";
+/// ヘッダに記録する archrypto_core のバージョン
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// ディレクトリごとの除外ルールを記述するファイル名（`.gitignore` と同じ書式）
+pub const IGNORE_FILE_NAME: &str = ".archryptignore";
+
+/// 展開先に同名のファイルが既に存在する場合の挙動を表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    /// 既存のファイルを上書きします（従来の挙動）。
+    #[default]
+    Overwrite,
+    /// 既存のファイルを残し、そのエントリの書き出しをスキップします。
+    Skip,
+    /// エラーを返して展開を中断します。
+    Error,
+    /// 使用されていない別名で書き出します。別名の付け方は `ExtractOptions::rename_style` に従います。
+    Rename,
+}
+
+/// `OverwritePolicy::Rename` で別名を付ける方法を表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenameStyle {
+    /// `output.txt.1`, `output.txt.2` のように、ファイル名の末尾に連番を付けます（従来の挙動）。
+    #[default]
+    Counter,
+    /// `output (1).txt`, `output (2).txt` のように、拡張子の前に括弧付きの連番を付けます（macOS の Finder や Windows と同じ形式）。
+    Finder,
+}
+
+/// `OverwritePolicy::Rename` で別名を探す連番の上限.
+pub const MAX_RENAME_ATTEMPTS: u32 = 9999;
+
+/// `extract_files` の展開オプション.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// true の場合、ディレクトリ構造を取り除き、全てのファイルを出力ディレクトリ直下に展開します。
+    /// 同名のファイルが衝突した場合は `overwrite_policy` に従います。
+    pub flatten: bool,
+    /// 展開先に同名のファイルが存在する場合の挙動。
+    pub overwrite_policy: OverwritePolicy,
+    /// `OverwritePolicy::Rename` で付ける別名の形式（デフォルトは `RenameStyle::Counter`）。
+    pub rename_style: RenameStyle,
+    /// true の場合、復号とZIPの解析のみを行い、展開先のパスを表示するだけでファイルは書き出しません。
+    /// 復号処理（RSA + AES）のコストは通常通り発生します。
+    pub dry_run: bool,
+    /// ファイルの書き出しに使用するスレッド数（0 と 1 は同じ意味で、メインスレッドのみで書き出します）。
+    ///
+    /// 2 以上の場合、ZIPエントリの読み出しとディレクトリの作成はメインスレッドで順に行い、
+    /// ファイルの書き出しのみを指定した数のワーカースレッドで並列に行います。
+    pub extract_threads: usize,
+    /// true の場合、`output_dir` を無視し、`PathStyle::Absolute` で格納したエントリを元の絶対パス
+    /// （ルートディレクトリからのパス）に書き出します。既存のシステム上のファイルを上書きし得るため注意してください。
+    /// `flatten` とは併用できません。
+    pub extract_to_original_paths: bool,
+    /// `extract_to_original_paths` で展開する前に、処理を続けるかを確認するコールバック。
+    /// 確認メッセージを受け取り、false を返すと中断します。None の場合は確認しません。
+    pub on_confirm: Option<fn(&str) -> bool>,
+    /// 展開の完了後に実行するコマンド（例: `"bash deploy.sh {output_dir}"`）。`{output_dir}` は展開先のディレクトリに
+    /// 置き換えられ、指定がなければ展開先を末尾に追加します。統計情報は環境変数 `ARCHRYPT_STATS` に JSON で渡されます。
+    /// `dry_run` の場合は実行しません。
+    pub post_extract_hook: Option<String>,
+    /// true の場合、展開が途中で失敗したときも、それまでの統計情報で `post_extract_hook` を実行します。
+    /// フックの失敗は警告として表示し、展開のエラーを返します。
+    pub post_extract_hook_on_error: bool,
+    /// `CompressOptions::emit_manifest` で書き出したマニフェストJSONのパス。指定した場合、展開を始める前に
+    /// マニフェストの全てのエントリが復号したアーカイブに存在し、SHA-256 が一致するかを確認します。
+    pub verify_manifest: Option<PathBuf>,
+    /// 進捗バーの表示形式（`indicatif` のテンプレート、例: `"{spinner} {bytes}/{total_bytes}"`）。
+    /// None の場合、または不正なテンプレートの場合はデフォルトの表示形式を使用します。
+    pub progress_template: Option<String>,
+    /// 進捗バーの塗りつぶしに使用する文字（例: `"=>-"`）。None の場合はデフォルトの `"#>-"` を使用します。
+    pub progress_chars: Option<String>,
+    /// 復号に使用する秘密鍵の取得元。指定した場合、`extract_files` の `private_key_path` の代わりに使用します。
+    pub private_key_source: Option<PrivateKeySource>,
+}
+
+impl ExtractOptions {
+    /// 読み込み済みの秘密鍵を復号に使用するよう設定します.
+    ///
+    /// 秘密鍵ファイルの読み込みと PEM のパースを行わないため、HSM や Web リクエストなどから取得した鍵を
+    /// 一時ファイルに書き出さずに使用できます。`extract_files` の `private_key_path` は無視されます。
+    pub fn with_private_key_object(mut self, key: RsaPrivateKey) -> Self {
+        self.private_key_source = Some(PrivateKeySource::InMemory(Box::new(key)));
+        self
+    }
+
+    /// 使用する秘密鍵の取得元を返します. `private_key_source` が未指定の場合は `private_key_path` です。
+    fn private_key_source(&self, private_key_path: &Path) -> PrivateKeySource {
+        self.private_key_source
+            .clone()
+            .unwrap_or_else(|| PrivateKeySource::FromPath(private_key_path.to_path_buf()))
+    }
+}
+
+/// 圧縮時にサイズなどの制限を超えたファイルが見つかった場合の挙動を表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitPolicy {
+    /// 該当ファイルをアーカイブに含めずに処理を続行します。
+    Skip,
+    /// エラーを返して圧縮を中断します。
+    #[default]
+    Error,
+}
+
+/// ZIPエントリ名に圧縮対象のパスをどのように含めるかを表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathStyle {
+    /// 対象のベース名のみを使用します（従来の挙動）。異なるディレクトリの同名ファイルは衝突します。
+    #[default]
+    BaseName,
+    /// 全ての対象の親ディレクトリに共通する祖先ディレクトリからの相対パスを使用します。
+    RelativeToCommonAncestor,
+    /// 対象の絶対パスを使用します。ルート（`/` やドライブ名）は取り除きます。
+    Absolute,
+}
+
+/// UTF-8 として解釈できないファイル名（Unix では任意のバイト列を使用できます）の扱いを表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonUtf8Policy {
+    /// 不正なバイトを `U+FFFD` に置き換えたエントリ名で格納します（従来の挙動）。展開後のファイル名は元と異なります。
+    #[default]
+    Lossy,
+    /// エラーを返して圧縮を中断します（`ArchryptoError::NonUtf8Filename`）。
+    Error,
+    /// 該当ファイルをアーカイブに含めずに処理を続行します（デバッグログにのみ記録します）。
+    Skip,
+}
+
+/// 暗号化に使用できる RSA 鍵の最小のビット数.
+pub const MIN_RSA_KEY_BITS: usize = 2048;
+
+/// 暗号化時の RSA 鍵のビット数の検証方法を表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeySizeOverride {
+    /// `MIN_RSA_KEY_BITS` 未満の鍵をエラーにします（デフォルト）。
+    #[default]
+    Enforce,
+    /// 鍵のビット数を検証しません。512 ビットなどの小さい鍵でテストを高速化するためのもので、本番環境での使用はサポートしません。
+    /// `test-utils` フィーチャーが有効な場合のみ利用できます。
+    #[cfg(any(test, feature = "test-utils"))]
+    InsecureTestMode,
+}
+
+/// 暗号化したアーカイブの出力形式を表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// バイナリの `.acrp` 形式（従来の形式）。
+    #[default]
+    Binary,
+    /// `-----BEGIN ARCHRYPT ARCHIVE-----` で囲んだ Base64 テキスト。メールや環境変数など ASCII のみを扱う経路で使用します。
+    /// 展開時は自動的に判別します。
+    Base64Armored,
+}
+
+/// 出力ファイルの名前の決め方を表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputNaming {
+    /// 指定した出力パスに書き出します（従来の挙動）。
+    #[default]
+    Specified,
+    /// 暗号化したアーカイブの SHA-256 の先頭8桁を `<hash>.acrp` として、指定した出力パスと同じディレクトリに書き出します。
+    /// 同じ内容をアーカイブ済みかをファイル名で判別できます。
+    ContentHash,
+}
+
+/// `compress_files` の圧縮オプション.
+#[derive(Debug, Clone)]
+pub struct CompressOptions {
+    /// 1ファイルあたりの最大サイズ（バイト）。None の場合は制限しません。
+    pub max_file_size: Option<u64>,
+    /// `max_file_size` を超えるファイルが見つかった場合の挙動。
+    pub limit_policy: LimitPolicy,
+    /// `LimitPolicy::Skip` でファイルをスキップした際に、対象のパスと理由を受け取るコールバック。
+    pub on_skip: Option<fn(&Path, &str)>,
+    /// 出力ファイルが既に存在する場合や、機密性の高いパス（`/etc` や `~/.ssh`, `~/.gnupg`）を
+    /// 圧縮する場合に、処理を続けるかを確認するコールバック。確認メッセージを受け取り、false を返すと中断します。
+    /// None の場合は確認しません。
+    pub on_confirm: Option<fn(&str) -> bool>,
+    /// true の場合、ディレクトリ走査時にシンボリックリンクを辿ります（デフォルトは false）。
+    ///
+    /// false の場合、ディレクトリ配下のシンボリックリンクはアーカイブに含まれません。
+    /// true の場合でも循環するシンボリックリンクは走査時に検出し、エラーとして返します。
+    pub follow_symlinks: bool,
+    /// ディレクトリ走査の最大の深さ。対象ディレクトリ直下のファイルの深さが 1 になります。
+    /// None の場合は制限しません。
+    pub max_depth: Option<usize>,
+    /// ディレクトリ走査の最小の深さ。これより浅い位置にあるファイルはアーカイブに含まれません。
+    pub min_depth: Option<usize>,
+    /// `.acrp` ヘッダに保存するコメント。暗号化されず、秘密鍵なしで読み取れます。
+    /// `MAX_COMMENT_LEN` バイトを超える場合は切り詰め、`ArchiveStats::comment_truncated` を true にします。
+    pub comment: Option<String>,
+    /// `.acrp` ヘッダに保存する作成者。コメントと同じく暗号化されません。
+    pub creator: Option<String>,
+    /// アーカイブに保存する、利用者が定義した JSON のメタデータ（プロジェクトコードや保存期間など）。
+    ///
+    /// デフォルトではコメントと同じく `.acrp` ヘッダに平文で保存され、秘密鍵なしで `inspect_archive` から読み取れます。
+    pub metadata: Option<serde_json::Value>,
+    /// true の場合、`metadata` をヘッダではなく暗号化したZIPのアーカイブコメントに保存します（デフォルトは false）。
+    /// 読み取るには `read_encrypted_metadata` で復号する必要があります。
+    pub encrypt_metadata: bool,
+    /// false の場合、ディレクトリ走査時に `.` で始まる隠しファイル・ディレクトリを除外します（デフォルトは true）。
+    pub include_hidden: bool,
+    /// true の場合、ディレクトリ走査時に `.gitignore`（およびグローバル設定、`.git/info/exclude`）の
+    /// 除外ルールに従います。Git リポジトリの外でも `.gitignore` を適用します。
+    pub use_gitignore: bool,
+    /// true の場合、各ディレクトリの `.archryptignore`（`.gitignore` と同じ書式）の除外ルールに従います
+    /// （デフォルトは true）。`.archryptignore` 自体はアーカイブに含まれません。
+    pub use_archryptignore: bool,
+    /// 全てのディレクトリ走査に適用する、`.gitignore` 書式のグローバルな除外ルールファイル。
+    pub global_ignore_file: Option<PathBuf>,
+    /// true の場合、格納した全ファイルの SHA-256 を `sha256sum` 互換の形式で記録した
+    /// `MANIFEST.sha256` をZIP内に埋め込みます（デフォルトは false）。
+    /// 展開時はマニフェストが存在すれば各ファイルのハッシュを照合します。
+    pub embed_manifest: bool,
+    /// true の場合、対象パスが空でもエラーにせず、エントリを持たないアーカイブを作成します（デフォルトは false）。
+    pub allow_empty: bool,
+    /// アーカイブに格納するファイル数の上限。None の場合は制限しません。
+    ///
+    /// 指定した場合、進捗バーの総数を求めるための事前の走査は行わず、上限値を総数として表示します。
+    pub max_entries: Option<usize>,
+    /// ZIPエントリ名に対象のパスをどのように含めるか（デフォルトはベース名のみ）。
+    pub path_style: PathStyle,
+    /// 対象ごとにディレクトリ走査の設定を上書きする設定（`Manifest::load` で読み込んだ対象など）。
+    /// `ManifestTarget::path` が圧縮対象のパスと一致する場合に適用します。
+    pub target_overrides: Vec<ManifestTarget>,
+    /// true の場合、ZIPを一時ファイルに書き出してから暗号化します（デフォルトは true）。
+    ///
+    /// false の場合は一時ファイルを使わず、作成中のZIPをそのまま暗号化して書き出すため、ディスクへの書き込みが半分になります。
+    /// どちらの場合もZIP全体をメモリに保持することはありません。
+    /// `embed_input_checksum` が true の場合は、ZIPの完成後にチェックサムを計算する必要があるため常に一時ファイルを使用します。
+    pub use_temp_file: bool,
+    /// 暗号化したアーカイブの出力形式（デフォルトはバイナリ）。
+    pub output_format: OutputFormat,
+    /// 圧縮前に実行するコマンド（例: `"clamscan {files}"`）。`{files}` は対象パスの一覧に展開され、
+    /// 指定がなければ対象パスを末尾に追加します。終了コードが 0 以外の場合は圧縮を中止します。
+    pub pre_compress_hook: Option<String>,
+    /// `.acrp` の書き出し後に実行するコマンド（例: `"aws s3 cp {output} s3://bucket/"`）。
+    /// `{output}` は出力ファイルのパスに置き換えられ、統計情報は環境変数 `ARCHRYPT_STATS` に JSON で渡されます。
+    pub post_compress_hook: Option<String>,
+    /// 暗号化に使用する公開鍵のビット数の検証方法。
+    pub key_size_override: KeySizeOverride,
+    /// true の場合、格納したファイルのツリーチェックサム（`compute_tree_checksum` と同じ形式）をヘッダに保存します。
+    /// `inspect_archive` で復号せずに読み取り、元のディレクトリから再計算した値と比較できます。
+    pub embed_input_checksum: bool,
+    /// true の場合、内容（SHA-256）が同じファイルを1度だけ格納します（デフォルトは false）。
+    ///
+    /// 2つ目以降のファイルは内容を持たないエントリとして格納し、最初のエントリ名を独自のZIP拡張フィールドに記録します。
+    /// 展開時は参照先のエントリの内容を書き出します。各ファイルを格納前に1度読み込んでハッシュを計算するため読み込み量が増え、
+    /// 格納した重複のないファイルの数に比例してメモリ使用量も増えます。
+    pub deduplicate: bool,
+    /// 出力ファイルの名前の決め方（デフォルトは指定した出力パス）。
+    pub output_naming: OutputNaming,
+    /// UTF-8 として解釈できないファイル名の扱い（デフォルトは `NonUtf8Policy::Lossy`）。
+    pub non_utf8_policy: NonUtf8Policy,
+    /// 出力ファイルやZIPの一時ファイルの作成が `NotFound` / `WouldBlock` で失敗した場合に再試行する回数
+    /// （デフォルトは 0）。NFS や SMB などで一時的に失敗する場合に使用します。
+    pub retry_count: u32,
+    /// 最初の再試行までの待機時間（デフォルトは 500 ミリ秒）。再試行のたびに2倍にします。
+    pub retry_delay: Duration,
+    /// true の場合、全ての対象のエントリ名を先に集めてから、エントリ名の順にZIPへ格納します（デフォルトは false）。
+    ///
+    /// ディレクトリ走査の順序に依存せず、同じ入力から同じ順序のZIPを作成します。
+    pub sort_entries: bool,
+    /// true の場合、全てのエントリの更新日時を現在時刻ではなく固定値（1980-01-01 00:00:00）にします（デフォルトは false）。
+    ///
+    /// `sort_entries` と併用すると、同じ入力から同じ内容のZIPを作成できます。ただし、暗号化には毎回異なる
+    /// AES 鍵と Nonce を使用するため、暗号化後の `.acrp` のバイト列は一致しません。
+    pub fixed_timestamps: bool,
+    /// 証明書失効リスト（CRL）のパス。指定した場合、公開鍵として指定した証明書のシリアル番号が CRL に
+    /// 記載されていれば暗号化を中止します（`ArchryptoError::KeyRevoked`）。公開鍵は X.509 証明書である必要があります。
+    pub check_revocation: Option<PathBuf>,
+    /// true の場合、各ファイルの格納にかかった時間を計測し、時間が長い順に `SLOWEST_FILES_LEN` 件を
+    /// `ArchiveStats::slowest_files` に記録します（デフォルトは false）。
+    pub record_timings: bool,
+    /// true の場合、`compress_files` でアーカイブの書き出し後に、格納した各エントリのパス・サイズ・SHA-256・更新日時を
+    /// 記録した `<出力>.manifest.json` を書き出します（デフォルトは false）。このファイルは暗号化しないため、
+    /// エントリ名などを秘匿する必要がある場合は使用しないでください。
+    pub emit_manifest: bool,
+    /// 進捗バーの表示形式（`indicatif` のテンプレート、例: `"{spinner} {bytes}/{total_bytes}"`）。
+    /// None の場合、または不正なテンプレートの場合はデフォルトの表示形式を使用します。
+    pub progress_template: Option<String>,
+    /// 進捗バーの塗りつぶしに使用する文字（例: `"=>-"`）。None の場合はデフォルトの `"#>-"` を使用します。
+    pub progress_chars: Option<String>,
+    /// 暗号化に使用する公開鍵の取得元。指定した場合、`compress_files` の `public_key_path` の代わりに使用します。
+    pub public_key_source: Option<PublicKeySource>,
+    /// true の場合、各ファイルの先頭のマジックバイトから MIME タイプを判定し、ZIP拡張フィールド（`0xAC01`）に記録します
+    /// （デフォルトは false）。判定できないファイル（テキストファイルなど）には記録しません。
+    /// 記録した MIME タイプは `list_archive_entries` で読み取れます。
+    pub detect_content_type: bool,
+    /// true の場合、`compress_files` で格納が完了したファイルを出力パスごとの `Journal` に記録し、中断後に同じ出力パスで
+    /// 再実行した際は、記録済みで変更されていないファイルを読み込み・圧縮し直さずに前回の結果を使用します（デフォルトは false）。
+    /// 再開する場合は、中断したときと同じ対象パスと圧縮オプションを指定してください。ジャーナルは圧縮の完了時に削除します。
+    /// `compress_to_writer` では出力パスがないため無視します。
+    pub resume: bool,
+}
+
+impl CompressOptions {
+    /// 読み込み済みの公開鍵を暗号化に使用するよう設定します.
+    ///
+    /// 公開鍵ファイルの読み込みと PEM のパースを行わないため、HSM や Web リクエストなどから取得した鍵を
+    /// 一時ファイルに書き出さずに使用できます。`compress_files` の `public_key_path` は無視されます。
+    /// `check_revocation` は証明書ファイルを必要とするため併用できません。
+    pub fn with_public_key_object(mut self, key: RsaPublicKey) -> Self {
+        self.public_key_source = Some(PublicKeySource::InMemory(key));
+        self
+    }
+
+    /// 使用する公開鍵の取得元を返します. `public_key_source` が未指定の場合は `public_key_path` です。
+    fn public_key_source(&self, public_key_path: &Path) -> PublicKeySource {
+        self.public_key_source
+            .clone()
+            .unwrap_or_else(|| PublicKeySource::FromPath(public_key_path.to_path_buf()))
+    }
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        CompressOptions {
+            max_file_size: None,
+            limit_policy: LimitPolicy::default(),
+            on_skip: None,
+            on_confirm: None,
+            follow_symlinks: false,
+            max_depth: None,
+            min_depth: None,
+            comment: None,
+            creator: None,
+            metadata: None,
+            encrypt_metadata: false,
+            include_hidden: true,
+            use_gitignore: false,
+            use_archryptignore: true,
+            global_ignore_file: None,
+            embed_manifest: false,
+            allow_empty: false,
+            max_entries: None,
+            path_style: PathStyle::default(),
+            target_overrides: Vec::new(),
+            use_temp_file: true,
+            output_format: OutputFormat::default(),
+            pre_compress_hook: None,
+            post_compress_hook: None,
+            key_size_override: KeySizeOverride::default(),
+            embed_input_checksum: false,
+            deduplicate: false,
+            output_naming: OutputNaming::default(),
+            non_utf8_policy: NonUtf8Policy::default(),
+            retry_count: 0,
+            retry_delay: Duration::from_millis(500),
+            sort_entries: false,
+            fixed_timestamps: false,
+            check_revocation: None,
+            record_timings: false,
+            emit_manifest: false,
+            progress_template: None,
+            progress_chars: None,
+            public_key_source: None,
+            detect_content_type: false,
+            resume: false,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl CompressOptions {
+    /// テスト用の圧縮オプションを作成します.
+    ///
+    /// 鍵のビット数を検証しない（`KeySizeOverride::InsecureTestMode`）ほかは `Default` と同じです。
+    /// `test_utils::generate_insecure_test_keypair` の小さい鍵と組み合わせて使用します。
+    pub fn for_testing() -> Self {
+        CompressOptions {
+            key_size_override: KeySizeOverride::InsecureTestMode,
+            ..Default::default()
+        }
+    }
+}
+
+/// アーカイブを復号できる受信者（暗号化に使用した公開鍵）の情報.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipientInfo {
+    /// 公開鍵のフィンガープリント（`SHA256:xx:xx:...`）。暗号化時に記録されていない場合は None。
+    pub key_id: Option<String>,
+    /// RSA で暗号化された AES 鍵のバイト数（= RSA 鍵のモジュラスのバイト数）
+    pub key_size_bytes: u16,
+}
+
+/// 圧縮・展開処理の結果の統計情報.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ArchiveStats {
+    /// 処理対象となったファイル数（ディレクトリを除く）
+    pub files: usize,
+    /// 展開時は実際にディスクへ書き出したファイル数、圧縮時はアーカイブに格納したファイル数
+    pub files_written: usize,
+    /// 制限や上書きポリシーによりスキップしたファイル数
+    pub files_skipped: usize,
+    /// 処理対象となったファイルの非圧縮時の合計バイト数
+    pub total_bytes: u64,
+    /// `CompressOptions::resume` で、中断した前回の実行の結果をジャーナルから再利用したファイル数
+    pub files_resumed: usize,
+    /// 圧縮時は暗号化に使用した公開鍵、展開時は復号に使用した秘密鍵のビット数
+    pub key_bits: Option<usize>,
+    /// 圧縮時は暗号化に使用した公開鍵、展開時は復号に使用した秘密鍵のフィンガープリント（`SHA256:xx:xx:...`）
+    pub key_fingerprint: Option<String>,
+    /// 圧縮時に、`CompressOptions::comment` が `MAX_COMMENT_LEN` バイトを超えたため切り詰めた場合は true
+    pub comment_truncated: bool,
+    /// `compress_files` などで書き出したアーカイブのパス。`OutputNaming::ContentHash` の場合は指定した出力パスと異なります。
+    #[serde(skip)]
+    pub output_path: Option<PathBuf>,
+    /// `CompressOptions::record_timings` が true の場合に、格納にかかった時間が長い順に最大 `SLOWEST_FILES_LEN` 件のファイル
+    #[serde(skip)]
+    pub slowest_files: Vec<FileTimingRecord>,
+    /// `CompressOptions::emit_manifest` が true の場合に、格納したエントリの情報を集めたマニフェスト
+    #[serde(skip)]
+    pub manifest: Option<ManifestWriter>,
+    /// `OverwritePolicy::Rename` で別名で書き出したファイルの、エントリ名と実際に書き出したパス
+    #[serde(skip)]
+    pub renamed_files: Vec<(String, PathBuf)>,
+}
+
+/// `ArchiveStats::slowest_files` に記録するファイルの数.
+pub const SLOWEST_FILES_LEN: usize = 10;
+
+/// 1つのファイルをアーカイブに格納するのにかかった時間の記録.
+#[derive(Debug, Clone)]
+pub struct FileTimingRecord {
+    /// 格納したファイルのパス
+    pub path: PathBuf,
+    /// ファイルを開く直前の時刻
+    pub start: Instant,
+    /// ZIPへの書き込みが終わった時刻
+    pub end: Instant,
+    /// 格納したファイルのバイト数
+    pub bytes: u64,
+}
+
+impl FileTimingRecord {
+    /// 格納にかかった時間を返します.
+    pub fn elapsed(&self) -> Duration {
+        self.end.duration_since(self.start)
+    }
+}
 
 /// 指定されたファイルまたはディレクトリ群をZIP圧縮し、
 /// さらに指定した公開鍵を用いて暗号化した結果を output_crypted に保存します.
@@ -24,211 +524,1935 @@ const PROGRESS_BAR_CHAR: &str = "#>-";
 /// # Arguments
 ///
 /// * `output_crypted` - 暗号化後のZIPファイルの出力先パス。拡張子は ".acrp" である必要があります。
-/// * `public_key_path` - 暗号化に使用する公開鍵ファイルのパス。
+///   `OutputNaming::ContentHash` の場合は、このパスのディレクトリに内容のハッシュから決めた名前で書き出します。
+/// * `public_key_path` - 暗号化に使用する公開鍵ファイルのパス。`opts.public_key_source` を指定した場合は無視します。
 /// * `target_pathes` - 圧縮対象となるファイルまたはディレクトリのパスのリスト。
+/// * `opts` - 圧縮オプション。
+///
+/// # Returns
+///
+/// 格納したファイル数や書き出したアーカイブのパスなどの統計情報を返します。
 ///
 /// # Errors
 ///
 /// * output_crypted の拡張子が ".acrp" でない場合。
+/// * `opts.allow_empty` が false で、対象パスが空の場合（`ArchryptoError::NoTargetPaths`）。
+/// * 対象パスが互いに重複している場合（`ArchryptoError::OverlappingPaths` / `ArchryptoError::DuplicatePaths`）。
+/// * `LimitPolicy::Error` でサイズ上限を超えるファイルがあった場合（`ArchryptoError::FileTooLarge`）。
+/// * `max_entries` を超えるファイルを格納しようとした場合（`ArchryptoError::MaxEntriesExceeded`）。
+/// * 出力先のディレクトリが存在しないか、書き込めない場合（`ArchryptoError::OutputNotWritable`）。
+/// * `on_confirm` の確認で中断された場合。
+/// * 公開鍵のビット数が `MIN_RSA_KEY_BITS` 未満の場合（`ArchryptoError::KeyTooSmall`）。
+/// * `pre_compress_hook` / `post_compress_hook` の実行に失敗した場合、または終了コードが 0 以外の場合。
 /// * 各ファイル・ディレクトリの読み込み、ZIP圧縮、暗号化処理、または進捗バーの更新に失敗した場合にエラーを返します。
 pub fn compress_files(
-    output_crypted: &PathBuf,
-    public_key_path: &PathBuf,
-    target_pathes: &[PathBuf],
-) -> Result<()> {
+    output_crypted: impl AsRef<Path>,
+    public_key_path: impl AsRef<Path>,
+    target_pathes: &[impl AsRef<Path>],
+    opts: &CompressOptions,
+) -> Result<ArchiveStats> {
+    let output_crypted = output_crypted.as_ref();
+    let public_key_path = public_key_path.as_ref();
+    let target_pathes: &[PathBuf] = &target_pathes.iter().map(|path| path.as_ref().to_path_buf()).collect::<Vec<_>>();
     // 出力拡張子チェック
     if !validate_extension(output_crypted)? {
         return Err(anyhow!("outputpath extention does not \".{}\"", EXTENTION));
     }
+    // 同じファイルが二重に格納されないよう、対象パスの重複をチェック
+    validate_target_paths(target_pathes, opts)?;
+    // 圧縮に時間をかけた後で失敗しないよう、出力先に書き込めるかを先に確認
+    check_output_writable(output_crypted)?;
+    if let Some(confirm) = opts.on_confirm {
+        confirm_compress(output_crypted, target_pathes, confirm)?;
+    }
+    debug!("compress_files: {} target paths -> {}", target_pathes.len(), output_crypted.display());
+    let public_key_source = opts.public_key_source(public_key_path);
+    let public_key = &*prepare_compress(&public_key_source, target_pathes, opts)?;
     
-    // 圧縮対象の総ファイル数 + 暗号化工程用に1件追加して進捗バーを作成
-    let total_files = count_files_in_paths(target_pathes)?;
-    let pb = ProgressBar::new(u64::try_from(total_files + 1)?);
-    pb.set_style(
-        ProgressStyle::with_template(PROGRESS_SETTING)
-            .unwrap()
-            .progress_chars(PROGRESS_BAR_CHAR),
+    // 圧縮対象の総バイト数で進捗バーを作成し、読み込んだバイト数と転送速度を表示
+    let pb = progress::new_bytes_progress_bar(
+        expected_total_bytes(target_pathes, opts)?,
+        opts.progress_template.as_deref(),
+        opts.progress_chars.as_deref(),
     );
 
+    // 途中で失敗した場合に不完全な出力ファイルが残らないよう、出力先と同じディレクトリの一時ファイルに書き出してから名前を変更する
+    let output_dir = output_crypted.parent().unwrap_or(Path::new(""));
+    let output_temp = retry::retry_io(opts.retry_count, opts.retry_delay, "Creating the output file", || gc::new_output_temp_file(output_dir))?;
+    // 中断した前回の実行を再開できるよう、格納が完了したファイルをジャーナルに記録する
+    let mut journal = opts.resume.then(|| Journal::open(output_crypted)).transpose()?;
+    let (writer, mut stats) = write_encrypted_archive(BufWriter::new(output_temp), public_key, target_pathes, opts, &pb, journal.as_mut())?;
+    let output_temp = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    let content_hash_path;
+    let output_path: &Path = match opts.output_naming {
+        OutputNaming::Specified => output_crypted,
+        OutputNaming::ContentHash => {
+            let mut hasher = manifest::HashingWriter::new(io::sink());
+            copy(&mut output_temp.reopen()?, &mut hasher)?;
+            content_hash_path = output_name::content_hash_path(&hasher.finalize_hex(), output_dir);
+            &content_hash_path
+        }
+    };
+    output_temp.persist(output_path).map_err(|e| e.error)?;
+    debug!("Wrote {}", output_path.display());
+    if let Some(manifest) = &stats.manifest {
+        let manifest_path = manifest_json_path(output_path);
+        manifest.write_json(&manifest_path)?;
+        debug!("Wrote {}", manifest_path.display());
+    }
+    if let Some(journal) = journal {
+        journal.complete()?;
+    }
+    pb.finish();
+    println!("Complete!");
+    println!("{}", canonicalize(output_path)?.display());
+    if let Some(hook) = &opts.post_compress_hook {
+        hooks::run_post_compress_hook(hook, output_path, &stats)?;
+    }
+    stats.output_path = Some(output_path.to_path_buf());
+    Ok(stats)
+}
+
+/// 対象パス群をZIP圧縮した一時ファイルを作成します（暗号化は行いません）.
+///
+/// # Arguments
+///
+/// * `target_pathes` - 圧縮対象となるファイルまたはディレクトリのパスのリスト。
+/// * `opts` - 圧縮オプション。
+/// * `pb` - 読み込んだバイト数だけ進める進捗バー。
+/// * `journal` - 格納したファイルを記録する再開用のジャーナル。None の場合は記録しません。
+///
+/// # Returns
+///
+/// ZIPを書き出した一時ファイルと、統計情報を返します。
+///
+/// # Errors
+///
+/// 各ファイル・ディレクトリの読み込み、またはZIP圧縮に失敗した場合にエラーを返します。
+fn build_zip(
+    target_pathes: &[PathBuf],
+    opts: &CompressOptions,
+    pb: &progress::ProgressBar,
+    journal: Option<&mut Journal>,
+) -> Result<(NamedTempFile, ArchiveStats)> {
     // 一時ZIPファイルをシステム一時ディレクトリに作成
-    let mut temp_zip_file = NamedTempFile::new()?;
-    {
-        let writer = BufWriter::new(temp_zip_file.as_file_mut());
-        let mut zip = ZipWriter::new(writer);
-        let options = SimpleFileOptions::default();
+    let mut temp_zip_file = retry::retry_io(opts.retry_count, opts.retry_delay, "Creating the temporary ZIP file", gc::new_temp_file)?;
+    debug!("Writing ZIP to temporary file {}", temp_zip_file.path().display());
+    let (writer, stats) = write_zip(ZipWriter::new(BufWriter::new(temp_zip_file.as_file_mut())), target_pathes, opts, pb, journal)?;
+    writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    Ok((temp_zip_file, stats))
+}
+
+/// 対象パス群をZIP圧縮して `zip` に書き出します.
+///
+/// `ZipWriter::new_stream` で作成した `zip` を渡すと、シークせずに先頭から順に書き出します（データディスクリプタを使用します）。
+/// `journal` を指定した場合は、格納したファイルを記録し、記録済みのファイルは前回の結果を使用します（`CompressOptions::resume`）。
+///
+/// # Returns
+///
+/// ZIPの書き出しを完了した `zip` の内側の Writer と、統計情報を返します。
+///
+/// # Errors
+///
+/// 各ファイル・ディレクトリの読み込み、またはZIP圧縮に失敗した場合にエラーを返します。
+fn write_zip<W: Write + Seek>(
+    mut zip: ZipWriter<W>,
+    target_pathes: &[PathBuf],
+    opts: &CompressOptions,
+    pb: &progress::ProgressBar,
+    mut journal: Option<&mut Journal>,
+) -> Result<(W, ArchiveStats)> {
+    let mut stats = ArchiveStats::default();
+    let mut manifest = ManifestWriter::new();
+    // シンボリックリンク等により同じ実体に複数の経路で到達した場合、最初の1回のみ格納する
+    let mut visited = HashSet::new();
+    // opts.deduplicate が true の場合に、格納済みのファイルの内容のハッシュと最初のエントリ名を記録する
+    let mut stored_digests = HashMap::new();
+    // opts.sort_entries が true の場合に、格納するエントリ名とファイルのパスを集めておき、最後に名前順で格納する
+    let mut sorted_entries: Vec<(String, PathBuf)> = Vec::new();
+    let writer = {
+        let options = SimpleFileOptions::default().last_modified_time(entry_timestamp(opts));
         
+        let ancestor = match opts.path_style {
+            PathStyle::RelativeToCommonAncestor => {
+                let parents = target_pathes
+                    .iter()
+                    .map(|target| Ok(normalize_absolute(target)?.parent().map(Path::to_path_buf).unwrap_or_default()))
+                    .collect::<Result<Vec<_>>>()?;
+                Some(common_ancestor(&parents))
+            }
+            _ => None,
+        };
+
         // 各対象パスごとに処理
         for target in target_pathes {
             if target.is_file() {
-                let mut file = File::open(target)?;
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
-        
-                // ファイル名を安全に取得（非UTF-8は to_string_lossy で変換）
-                let file_name = target.file_name().unwrap().to_string_lossy();
-                zip.start_file(&file_name, options)?;
-                zip.write_all(&buffer)?;
-                pb.inc(1);
+                if !mark_visited(&mut visited, target)? {
+                    continue;
+                }
+                // ファイル名を取得（非UTF-8は opts.non_utf8_policy に従う）
+                let entry_name = target_entry_name(target, opts.path_style, ancestor.as_deref())?;
+                let Some(file_name) = entry_name_str(&entry_name, opts.non_utf8_policy)? else {
+                    skip_non_utf8_file(target, pb, &mut stats)?;
+                    continue;
+                };
+                if opts.sort_entries {
+                    sorted_entries.push((file_name.into_owned(), target.clone()));
+                } else {
+                    add_file_to_zip(&mut zip, target, &file_name, options, opts, pb, &mut stats, &mut manifest, &mut stored_digests, journal.as_deref_mut())?;
+                }
             } else if target.is_dir() {
-                // ディレクトリの場合は、ディレクトリ自体の名前をベースとして利用
-                let base_name = target
-                    .file_name()
-                    .ok_or_else(|| anyhow!("Failed to get directory name"))?
-                    .to_string_lossy()
-                    .to_string();
+                // ディレクトリの場合は、ディレクトリ自体のエントリ名をベースとして利用
+                let base_name = target_entry_name(target, opts.path_style, ancestor.as_deref())?;
     
-                // WalkDirで再帰的にファイルを追加
-                for entry in WalkDir::new(target) {
+                // 再帰的にファイルを追加
+                for entry in walk_dir(target, opts)? {
                     let entry = entry?;
-                    if entry.file_type().is_file() {
+                    if is_file_entry(&entry) && mark_visited(&mut visited, entry.path())? {
                         // 対象ディレクトリを除いた相対パスを取得
                         let relative_path = entry.path()
                             .strip_prefix(target)
                             .map_err(|_| anyhow!("Failed to strip prefix"))?;
                         let zip_entry_path = Path::new(&base_name).join(relative_path);
-                        let relative_path_str = zip_entry_path.to_string_lossy();
+                        let Some(relative_path_str) = entry_name_str(&zip_entry_path, opts.non_utf8_policy)? else {
+                            skip_non_utf8_file(entry.path(), pb, &mut stats)?;
+                            continue;
+                        };
                                     
-                        zip.start_file(&relative_path_str, options)?;
-                        let mut file = File::open(entry.path())?;
-                        let mut buffer = Vec::new();
-                        file.read_to_end(&mut buffer)?;
-                        zip.write_all(&buffer)?;
-                        pb.inc(1);
+                        if opts.sort_entries {
+                            sorted_entries.push((relative_path_str.into_owned(), entry.path().to_path_buf()));
+                        } else {
+                            add_file_to_zip(&mut zip, entry.path(), &relative_path_str, options, opts, pb, &mut stats, &mut manifest, &mut stored_digests, journal.as_deref_mut())?;
+                        }
                     }
                 }
             } else {
                 return Err(anyhow!("Target path is neither file nor directory: {:?}", target.display()));
             }
         }
-        zip.finish()?;
+        sorted_entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (entry_name, path) in &sorted_entries {
+            add_file_to_zip(&mut zip, path, entry_name, options, opts, pb, &mut stats, &mut manifest, &mut stored_digests, journal.as_deref_mut())?;
+        }
+        if opts.embed_manifest {
+            zip.start_file(EMBEDDED_MANIFEST_NAME, options)?;
+            zip.write_all(manifest.to_sha256sum().as_bytes())?;
+        }
+        if let (Some(metadata), true) = (&opts.metadata, opts.encrypt_metadata) {
+            zip.set_comment(metadata.to_string());
+        }
+        zip.finish()?
+    };
+    if opts.emit_manifest {
+        stats.manifest = Some(manifest);
     }
-    // 暗号化処理：一時ZIPファイルのパスを用いて暗号化処理を実行
-    encrypt_file_with_public_key(temp_zip_file.path(), public_key_path, output_crypted)?;
-    pb.inc(1);
-    pb.finish();
-    println!("Complete!");
-    println!("{}", canonicalize(output_crypted)?.display());
+    Ok((writer, stats))
+}
+
+/// ZIPエントリに設定する更新日時を返します. `opts.fixed_timestamps` の場合は ZIP で表現できる最小の日時です。
+fn entry_timestamp(opts: &CompressOptions) -> zip::DateTime {
+    if opts.fixed_timestamps {
+        zip::DateTime::default()
+    } else {
+        zip::DateTime::default_for_write()
+    }
+}
+
+/// ZIPエントリ名とするパスを文字列に変換します.
+///
+/// UTF-8 として解釈できない場合は `policy` に従い、`NonUtf8Policy::Skip` の場合は None を返します。
+///
+/// # Errors
+///
+/// `NonUtf8Policy::Error` で UTF-8 として解釈できない場合（`ArchryptoError::NonUtf8Filename`）にエラーを返します。
+fn entry_name_str(entry_name: &Path, policy: NonUtf8Policy) -> Result<Option<std::borrow::Cow<'_, str>>> {
+    if let Some(name) = entry_name.to_str() {
+        return Ok(Some(name.into()));
+    }
+    match policy {
+        NonUtf8Policy::Lossy => Ok(Some(entry_name.to_string_lossy())),
+        NonUtf8Policy::Error => Err(ArchryptoError::NonUtf8Filename { name: entry_name.as_os_str().to_owned() }.into()),
+        NonUtf8Policy::Skip => Ok(None),
+    }
+}
+
+/// UTF-8 として解釈できない名前のファイルをスキップしたことを、統計情報と進捗バーに反映します.
+fn skip_non_utf8_file(path: &Path, pb: &progress::ProgressBar, stats: &mut ArchiveStats) -> Result<()> {
+    debug!("Skipping {:?}: the file name is not valid UTF-8", path);
+    stats.files += 1;
+    stats.files_skipped += 1;
+    pb.inc(fs::metadata(path)?.len());
     Ok(())
 }
 
-/// 指定された暗号化ZIPファイルを復号し、
-/// 出力ディレクトリに展開します。
+/// 出力ファイルを作成できるかを、圧縮を始める前に確認します.
 ///
-/// 復号化したZIPファイルは一時ファイルまたはインメモリバッファを用いて処理されます。
+/// 出力先のディレクトリが存在し、一時ファイルを作成できること、出力ファイル自体を書き込みで開けることを確認します。
+/// 確認のために新たに作成した出力ファイルは削除し、既存の出力ファイルの内容は変更しません。
+fn check_output_writable(output: &Path) -> Result<()> {
+    let not_writable = || ArchryptoError::OutputNotWritable { path: output.to_path_buf() };
+    let parent = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if !parent.is_dir() {
+        return Err(not_writable().into());
+    }
+    NamedTempFile::new_in(parent).map_err(|_| not_writable())?;
+    let existed = output.exists();
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(output)
+        .map_err(|_| not_writable())?;
+    if !existed {
+        fs::remove_file(output)?;
+    }
+    Ok(())
+}
+
+/// 出力ファイルの上書きと、機密性の高いパスの圧縮について `confirm` で確認します.
+///
+/// # Errors
+///
+/// `confirm` が false を返した場合にエラーを返します。
+fn confirm_compress(output: &Path, targets: &[PathBuf], confirm: fn(&str) -> bool) -> Result<()> {
+    if output.exists() && !confirm(&format!("Output file exists: {} — overwrite?", output.display())) {
+        return Err(anyhow!("Aborted: not overwriting {}", output.display()));
+    }
+    for target in targets {
+        if is_sensitive_path(target)?
+            && !confirm(&format!("{} may contain sensitive data — compress it?", target.display()))
+        {
+            return Err(anyhow!("Aborted: not compressing {}", target.display()));
+        }
+    }
+    Ok(())
+}
+
+/// `/etc` 配下や、ホームディレクトリの `.ssh`, `.gnupg` 配下のパスであれば true を返します.
+fn is_sensitive_path(path: &Path) -> Result<bool> {
+    let path = normalize_absolute(path)?;
+    let mut sensitive_dirs = vec![PathBuf::from("/etc")];
+    if let Some(home) = std::env::var_os("HOME").filter(|home| !home.is_empty()) {
+        let home = PathBuf::from(home);
+        sensitive_dirs.push(home.join(".ssh"));
+        sensitive_dirs.push(home.join(".gnupg"));
+    }
+    Ok(sensitive_dirs.iter().any(|dir| path.starts_with(dir)))
+}
+
+/// `path_style` に従い、圧縮対象自体のZIPエントリ名を返します.
+///
+/// ディレクトリの場合は、配下のファイルのエントリ名の先頭に付けるパスになります。
+/// `ancestor` は `PathStyle::RelativeToCommonAncestor` の場合に使用する共通の祖先ディレクトリです。
+fn target_entry_name(target: &Path, path_style: PathStyle, ancestor: Option<&Path>) -> Result<PathBuf> {
+    let base_name = || {
+        target
+            .file_name()
+            .map(PathBuf::from)
+            .ok_or_else(|| anyhow!("Failed to get target name: {}", target.display()))
+    };
+    match (path_style, ancestor) {
+        (PathStyle::RelativeToCommonAncestor, Some(ancestor)) => {
+            let absolute = normalize_absolute(target)?;
+            match absolute.strip_prefix(ancestor) {
+                std::result::Result::Ok(relative) if !relative.as_os_str().is_empty() => Ok(relative.to_path_buf()),
+                _ => base_name(),
+            }
+        }
+        (PathStyle::Absolute, _) => {
+            let absolute: PathBuf = normalize_absolute(target)?
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect();
+            if absolute.as_os_str().is_empty() {
+                return Err(anyhow!("Failed to get target name: {}", target.display()));
+            }
+            Ok(absolute)
+        }
+        _ => base_name(),
+    }
+}
+
+/// パスを絶対パスに変換し、`.` と `..` を字句的に取り除きます.
+///
+/// シンボリックリンクは解決しないため、エントリ名にはユーザーが指定した名前がそのまま使われます。
+fn normalize_absolute(path: &Path) -> Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in std::path::absolute(path)?.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    Ok(normalized)
+}
+
+/// 全てのパスに共通する最も深い祖先ディレクトリを、コンポーネント単位で比較して返します.
+///
+/// パスが空の場合や共通する祖先がない場合（ドライブが異なる場合など）は空のパスを返します。
+fn common_ancestor(paths: &[PathBuf]) -> PathBuf {
+    let Some((first, rest)) = paths.split_first() else {
+        return PathBuf::new();
+    };
+    let mut common: Vec<Component> = first.components().collect();
+    for path in rest {
+        let matched = common
+            .iter()
+            .zip(path.components())
+            .take_while(|(a, b)| *a == b)
+            .count();
+        common.truncate(matched);
+    }
+    common.into_iter().collect()
+}
+
+/// 圧縮オプションから `.acrp` の平文ヘッダを作成します.
+///
+/// コメントが `MAX_COMMENT_LEN` バイトを超える場合は切り詰め、警告をログに記録します。
+///
+/// # Returns
+///
+/// 作成したヘッダと、コメントを切り詰めたかどうかを返します。
+fn build_header(opts: &CompressOptions) -> (ArchiveHeader, bool) {
+    let mut header = ArchiveHeader::new();
+    let mut comment_truncated = false;
+    if let Some(comment) = &opts.comment {
+        let (comment, truncated) = header::truncate_comment(comment);
+        if truncated {
+            warn!("Comment exceeds {} bytes and was truncated", MAX_COMMENT_LEN);
+        }
+        comment_truncated = truncated;
+        header.comment = Some(comment);
+    }
+    header.creator = opts.creator.clone();
+    if !opts.encrypt_metadata {
+        header.metadata = opts.metadata.as_ref().map(serde_json::Value::to_string);
+    }
+    (header, comment_truncated)
+}
+
+/// 圧縮を始める前に、公開鍵を読み込んで検証し、`pre_compress_hook` を実行します.
+///
+/// `compress_files` と `compress_to_writer` で共通の処理です。OS の乱数生成器のエントロピーが不足している場合は警告を表示します。
 ///
 /// # Arguments
 ///
-/// * `input_encrypted_file` - 暗号化されたZIPファイルのパス。拡張子は ".acrp" である必要があります。
-/// * `private_key_path` - 復号に使用する秘密鍵ファイルのパス。
-/// * `output_dir` - 展開先のディレクトリパス。
+/// * `public_key_source` - 暗号化に使用する公開鍵の取得元。
+/// * `target_pathes` - 圧縮対象となるファイルまたはディレクトリのパスのリスト（`pre_compress_hook` に渡します）。
+/// * `opts` - 圧縮オプション。
+///
+/// # Returns
+///
+/// 読み込んだ公開鍵を返します。
 ///
 /// # Errors
 ///
-/// * 入力ファイルの拡張子が正しくない場合、
-/// * 復号化処理、ZIP解凍、またはファイル書き出しに失敗した場合にエラーを返します。
-pub fn extract_files(
-    input_encrypted_file: &Path,
-    private_key_path: &PathBuf,
-    output_dir: &Path,
-) -> Result<()> {
-    if !validate_extension(input_encrypted_file)? {
-        return Err(anyhow!("inputpath extention does not \".{}\"", EXTENTION));
+/// * 公開鍵の読み込みに失敗した場合、またはビット数が `MIN_RSA_KEY_BITS` 未満の場合（`ArchryptoError::KeyTooSmall`）。
+/// * `opts.check_revocation` の確認で失効していた場合、または公開鍵が証明書ファイルで指定されていない場合。
+/// * `pre_compress_hook` の実行に失敗した場合、または終了コードが 0 以外の場合。
+fn prepare_compress<'a>(
+    public_key_source: &'a PublicKeySource,
+    target_pathes: &[PathBuf],
+    opts: &CompressOptions,
+) -> Result<std::borrow::Cow<'a, RsaPublicKey>> {
+    // 起動直後などでエントロピーが不足していると乱数の生成で長時間待つことがあるため、警告のみ記録する（表示は呼び出し側に任せる）
+    if !entropy_available() {
+        warn!("The OS random number generator reports low entropy; generating the encryption key may block");
     }
-    let pb = ProgressBar::new(u64::try_from(1)?);
-    pb.set_style(
-        ProgressStyle::with_template(PROGRESS_SETTING)
-            .unwrap()
-            .progress_chars(PROGRESS_BAR_CHAR),
-    );
-    pb.enable_steady_tick(Duration::from_millis(100));
+    // 圧縮に時間をかけた後で失敗しないよう、公開鍵を先に読み込んで検証
+    let started = Instant::now();
+    let public_key = public_key_source.load()?;
+    debug!("Loaded public key {} in {:?}", public_key_source, started.elapsed());
+    check_key_size(&public_key, opts.key_size_override)?;
+    if let Some(crl_path) = &opts.check_revocation {
+        let PublicKeySource::FromPath(certificate_path) = public_key_source else {
+            return Err(anyhow!("check_revocation requires the public key to be given as a certificate file"));
+        };
+        revocation::check_revocation(certificate_path, crl_path)?;
+    }
+    if let Some(hook) = &opts.pre_compress_hook {
+        hooks::run_pre_compress_hook(hook, target_pathes)?;
+    }
+    Ok(public_key)
+}
 
-    // 復号処理：暗号化されたZIPファイルを復号し、Vec<u8>として取得
-    let decrypted_zip = decrypt_zip_with_rsa(input_encrypted_file, private_key_path)?;
-    
-    // 一時ファイルに復号結果を書き出す
-    let mut temp_zip_file = NamedTempFile::new()?;
-    temp_zip_file.as_file_mut().write_all(&decrypted_zip)?;
-    
-    // ZIPファイル内のファイル総数をカウントして進捗バーの総数を設定
-    let total_files = count_files_in_zip(&temp_zip_file)?;
-    pb.inc(1);
-    pb.set_length(u64::try_from(total_files)? + 1);
+/// 対象パス群をZIP圧縮して公開鍵で暗号化し、`.acrp` 形式のデータを `writer` に順に書き出します.
+///
+/// ヘッダ, Nonce, AES鍵のサイズ, 暗号化されたAES鍵を書き出し、続けてZIPデータを暗号化しながら書き出します。
+/// AES-GCM の認証タグは最後に書き出します。`opts.output_format` が `Base64Armored` の場合は ASCII アーマーに変換しながら書き出します。
+///
+/// `opts.use_temp_file` が true の場合はZIPを一時ファイルに書き出してから暗号化します。
+/// false の場合は作成中のZIPを一時ファイルを介さずにそのまま暗号化して書き出すため、ZIP全体をディスクにもメモリにも保持しません。
+/// ただし `opts.embed_input_checksum` が true の場合は、ヘッダに記録するチェックサムをZIPの完成後に計算する必要があるため、
+/// `use_temp_file` に関わらず一時ファイルを使用します。
+/// `journal` を指定した場合は、格納したファイルを記録し、記録済みのファイルは前回の結果を使用します（`CompressOptions::resume`）。
+///
+/// # Returns
+///
+/// `writer` と統計情報を返します。
+///
+/// # Errors
+///
+/// 各ファイル・ディレクトリの読み込み、ZIP圧縮、暗号化処理、または `writer` への書き込みに失敗した場合にエラーを返します。
+/// 一時ファイルを使用しない場合、途中で失敗すると `writer` には不完全なデータが書き出されています。
+fn write_encrypted_archive<W: Write>(
+    writer: W,
+    public_key: &RsaPublicKey,
+    target_pathes: &[PathBuf],
+    opts: &CompressOptions,
+    pb: &progress::ProgressBar,
+    journal: Option<&mut Journal>,
+) -> Result<(W, ArchiveStats)> {
+    let started = Instant::now();
+    let (mut header, comment_truncated) = build_header(opts);
+    let output = ArchiveOutput::new(writer, opts.output_format)?;
+    let (encryptor, stats) = if opts.use_temp_file || opts.embed_input_checksum {
+        let (temp_zip_file, stats) = build_zip(target_pathes, opts, pb, journal)?;
+        debug!(
+            "Built ZIP data: {} bytes, {} files written, {} skipped, {} input bytes in {:?}",
+            temp_zip_file.as_file().metadata()?.len(),
+            stats.files_written,
+            stats.files_skipped,
+            stats.total_bytes,
+            started.elapsed()
+        );
+        if opts.embed_input_checksum {
+            header.input_checksum = Some(tree_checksum::zip_tree_checksum(temp_zip_file.reopen()?)?);
+        }
+        // 暗号化処理：一時ファイルのZIPデータを公開鍵で暗号化しながら出力
+        let mut encryptor = start_encrypted_archive(output, public_key, &header)?;
+        copy(&mut temp_zip_file.reopen()?, &mut encryptor)?;
+        (encryptor, stats)
+    } else {
+        // 暗号化処理：作成中のZIPデータを公開鍵で暗号化しながら出力（ZIPはシークせずに先頭から順に書き出す）
+        let encryptor = start_encrypted_archive(output, public_key, &header)?;
+        let (writer, stats) = write_zip(ZipWriter::new_stream(BufWriter::new(encryptor)), target_pathes, opts, pb, journal)?;
+        let encryptor = writer.into_inner().into_inner().map_err(io::IntoInnerError::into_error)?;
+        debug!(
+            "Built and encrypted ZIP data without a temporary file: {} files written, {} skipped, {} input bytes",
+            stats.files_written,
+            stats.files_skipped,
+            stats.total_bytes
+        );
+        (encryptor, stats)
+    };
+    let writer = encryptor.finish()?.finish()?;
+    debug!("Encrypted archive ({:?}) in {:?}", opts.output_format, started.elapsed());
+    // 誤ってテスト用の鍵などを使用していないか呼び出し側で確認できるよう、使用した鍵のビット数とフィンガープリントを返す
+    let mut stats = stats;
+    stats.key_bits = Some(key_bits(public_key));
+    stats.key_fingerprint = Some(public_key_fingerprint(public_key)?);
+    stats.comment_truncated = comment_truncated;
+    Ok((writer, stats))
+}
 
-    let file = File::open(&temp_zip_file)?;
-    let reader = BufReader::new(file);
-    let mut archive = ZipArchive::new(reader)?;
+/// 暗号化した `.acrp` の書き出し先. `OutputFormat` に応じてそのまま、または ASCII アーマーに変換して書き出します.
+enum ArchiveOutput<W: Write> {
+    Binary(W),
+    Armored(armor::ArmorWriter<W>),
+}
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let outpath = output_dir.join(file.name());
-    
-        if file.name().ends_with('/') {
-            create_dir_all(&outpath)?;
-        } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    create_dir_all(p)?;
-                }
+impl<W: Write> ArchiveOutput<W> {
+    /// `format` の出力形式で `inner` に書き出す出力先を作成します.
+    fn new(inner: W, format: OutputFormat) -> io::Result<Self> {
+        match format {
+            OutputFormat::Binary => std::result::Result::Ok(ArchiveOutput::Binary(inner)),
+            OutputFormat::Base64Armored => std::result::Result::Ok(ArchiveOutput::Armored(armor::ArmorWriter::new(inner)?)),
+        }
+    }
+
+    /// ASCII アーマーの終了行などの残りを書き出し、`inner` を返します.
+    fn finish(self) -> io::Result<W> {
+        match self {
+            ArchiveOutput::Binary(mut inner) => {
+                inner.flush()?;
+                std::result::Result::Ok(inner)
             }
-            let mut outfile = File::create(&outpath)?;
-            copy(&mut file, &mut outfile)?;
+            ArchiveOutput::Armored(armored) => armored.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for ArchiveOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ArchiveOutput::Binary(inner) => inner.write(buf),
+            ArchiveOutput::Armored(armored) => armored.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ArchiveOutput::Binary(inner) => inner.flush(),
+            ArchiveOutput::Armored(armored) => armored.flush(),
         }
-        pb.inc(1);
     }
+}
+
+/// 指定されたファイルまたはディレクトリ群をZIP圧縮して公開鍵で暗号化し、
+/// `.acrp` 形式のデータを `writer` に書き出します.
+///
+/// HTTP レスポンスなど、ファイル以外の出力先に直接書き出す場合に使用します。
+/// エントロピーの警告、鍵の検証（`check_revocation` を含む）と `pre_compress_hook` の実行は `compress_files` と共通です。
+/// ヘッダ, Nonce, AES鍵のサイズ, 暗号化されたAES鍵を先に書き出し、続けて暗号化したZIPデータを
+/// 順に書き出すため、暗号文全体をメモリに保持しません。AES-GCM の認証タグは最後に書き出します。
+///
+/// 出力パスがないため、`on_confirm` による確認、`output_naming`、`post_compress_hook` は適用しません。
+/// `opts.emit_manifest` のマニフェストはファイルに書き出さず、`ArchiveStats::manifest` で返します。
+/// `opts.check_revocation` を指定した場合、公開鍵が証明書ファイルで与えられていないためエラーになります。
+///
+/// # Arguments
+///
+/// * `writer` - 暗号化したデータの書き出し先。
+/// * `public_key` - 暗号化に使用する公開鍵。
+/// * `targets` - 圧縮対象となるファイルまたはディレクトリのパスのリスト。
+/// * `opts` - 圧縮オプション。
+///
+/// # Returns
+///
+/// 格納したファイル数などの統計情報を返します。
+///
+/// # Errors
+///
+/// `compress_files` と同じ条件に加え、`writer` への書き込みに失敗した場合にエラーを返します。
+pub fn compress_to_writer<W: Write>(
+    writer: W,
+    public_key: &RsaPublicKey,
+    targets: &[PathBuf],
+    opts: &CompressOptions,
+) -> Result<ArchiveStats> {
+    validate_target_paths(targets, opts)?;
+    let public_key_source = PublicKeySource::InMemory(public_key.clone());
+    let public_key = &*prepare_compress(&public_key_source, targets, opts)?;
+
+    let pb = progress::new_bytes_progress_bar(
+        expected_total_bytes(targets, opts)?,
+        opts.progress_template.as_deref(),
+        opts.progress_chars.as_deref(),
+    );
+    let (_, stats) = write_encrypted_archive(writer, public_key, targets, opts, &pb, None)?;
     pb.finish();
-    println!("Complete!");
-    println!("{}", canonicalize(output_dir)?.display());
+    Ok(stats)
+}
+
+/// 従来の `&PathBuf` を受け取るシグネチャで、デフォルトの `CompressOptions` を使用して `compress_files` を呼び出します.
+///
+/// `compress_files` は全てのパスを `impl AsRef<Path>` で受け取るため、`&PathBuf` をそのまま渡せます。
+///
+/// # Errors
+///
+/// `compress_files` と同じ条件でエラーを返します。
+#[deprecated(since = "0.1.0", note = "use `compress_files`, which takes `impl AsRef<Path>` for every path")]
+pub fn compress_files_pathbuf(output_crypted: &PathBuf, public_key_path: &PathBuf, target_pathes: &[PathBuf]) -> Result<()> {
+    compress_files(output_crypted, public_key_path, target_pathes, &CompressOptions::default())?;
     Ok(())
 }
 
-/// 指定されたZIPファイル（未暗号化）の公開鍵による暗号化を行い、
-/// 結果を encrypted_path に保存します.
+/// 作成者をヘッダに記録して `compress_files` で圧縮・暗号化します.
+///
+/// `creator` は `opts.creator` より優先されます。ヘッダには作成した archrypto_core のバージョンも記録され、
+/// `inspect_archive` で確認できます。
+///
+/// # Arguments
+///
+/// * `output_crypted` - 暗号化後のZIPファイルの出力先パス。拡張子は ".acrp" である必要があります。
+/// * `public_key_path` - 暗号化に使用する公開鍵ファイルのパス。
+/// * `target_pathes` - 圧縮対象となるファイルまたはディレクトリのパスのリスト。
+/// * `opts` - 圧縮オプション。
+/// * `creator` - アーカイブの作成者。None の場合は `opts.creator` を使用します。
+///
+/// # Errors
+///
+/// `compress_files` と同じ条件でエラーを返します。
+pub fn compress_files_with_metadata(
+    output_crypted: impl AsRef<Path>,
+    public_key_path: impl AsRef<Path>,
+    target_pathes: &[impl AsRef<Path>],
+    opts: &CompressOptions,
+    creator: Option<&str>,
+) -> Result<ArchiveStats> {
+    let opts = CompressOptions {
+        creator: creator.map(str::to_string).or_else(|| opts.creator.clone()),
+        ..opts.clone()
+    };
+    compress_files(output_crypted, public_key_path, target_pathes, &opts)
+}
+
+/// 既存のZIPファイルを展開・再圧縮せず、そのまま `.acrp` 形式に暗号化します.
+///
+/// ZipCrypto や WinZip AES でエントリごとに暗号化されたZIPも、内部を変更せずに
+/// RSA と AES-256-GCM による外側の暗号化を追加できます。
+///
+/// # Arguments
+///
+/// * `zip_path` - 暗号化するZIPファイルのパス。
+/// * `public_key_path` - 暗号化に使用する公開鍵ファイルのパス。
+/// * `output_crypted` - 暗号化後のファイルの出力先パス。拡張子は ".acrp" である必要があります。
+///
+/// # Returns
+///
+/// ZIPの中央ディレクトリから求めたファイル数と非圧縮時の合計バイト数を返します。
+///
+/// # Errors
+///
+/// * output_crypted の拡張子が ".acrp" でない場合。
+/// * `zip_path` がZIPファイルとして読み込めない場合。
+/// * 出力先のディレクトリが存在しないか、書き込めない場合（`ArchryptoError::OutputNotWritable`）。
+/// * 公開鍵のビット数が `MIN_RSA_KEY_BITS` 未満の場合（`ArchryptoError::KeyTooSmall`）。
+/// * 暗号化処理、またはファイルの読み書きに失敗した場合にエラーを返します。
+pub fn compress_preencrypted_zip(
+    zip_path: impl AsRef<Path>,
+    public_key_path: impl AsRef<Path>,
+    output_crypted: impl AsRef<Path>,
+) -> Result<ArchiveStats> {
+    let zip_path = zip_path.as_ref();
+    let public_key_path = public_key_path.as_ref();
+    let output_crypted = output_crypted.as_ref();
+    if !validate_extension(output_crypted)? {
+        return Err(anyhow!("outputpath extention does not \".{}\"", EXTENTION));
+    }
+    check_output_writable(output_crypted)?;
+    let public_key = load_public_key(public_key_path)?;
+    check_key_size(&public_key, KeySizeOverride::Enforce)?;
+
+    let zip_data = fs::read(zip_path)?;
+    // ZIPの内部は変更しないが、ZIP以外のファイルを誤って包まないよう中央ディレクトリを読み込んで確認する
+    let mut archive = ZipArchive::new(io::Cursor::new(zip_data.as_slice()))
+        .map_err(|e| anyhow!("{:?} is not a valid ZIP file: {}", zip_path, e))?;
+    let mut stats = ArchiveStats::default();
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        if !entry.is_dir() {
+            stats.files += 1;
+            stats.total_bytes += entry.size();
+        }
+    }
+    stats.files_written = stats.files;
+
+    let encrypted = encrypt_with_public_key(&zip_data, &public_key, &ArchiveHeader::new())?;
+    File::create(output_crypted)?.write_all(&encrypted)?;
+    stats.output_path = Some(output_crypted.to_path_buf());
+    Ok(stats)
+}
+
+/// イテレータで与えられたファイルまたはディレクトリ群を `compress_files` で圧縮・暗号化します.
+///
+/// `read_paths_from_reader` と組み合わせて、標準入力などから読み取ったパスをそのまま渡せます。
+///
+/// # Arguments
+///
+/// * `output_crypted` - 暗号化後のZIPファイルの出力先パス。拡張子は ".acrp" である必要があります。
+/// * `public_key_path` - 暗号化に使用する公開鍵ファイルのパス。
+/// * `target_pathes` - 圧縮対象となるパスのイテレータ。
+/// * `opts` - 圧縮オプション。
+///
+/// # Errors
+///
+/// * イテレータがエラーを返した場合。
+/// * その他 `compress_files` と同じ条件でエラーを返します。
+pub fn compress_from_iter<I>(
+    output_crypted: impl AsRef<Path>,
+    public_key_path: impl AsRef<Path>,
+    target_pathes: I,
+    opts: &CompressOptions,
+) -> Result<ArchiveStats>
+where
+    I: IntoIterator<Item = Result<PathBuf>>,
+{
+    let target_pathes = target_pathes.into_iter().collect::<Result<Vec<_>>>()?;
+    compress_files(output_crypted, public_key_path, &target_pathes, opts)
+}
+
+/// 1つのファイルを指定したエントリ名でZIPに追加します.
+///
+/// `opts.max_file_size` を超えるファイルは `opts.limit_policy` に従ってスキップまたはエラーになります。
+///
+/// # Arguments
+///
+/// * `zip` - 追加先の ZipWriter。
+/// * `path` - 追加するファイルのパス。
+/// * `entry_name` - ZIP内でのエントリ名。
+/// * `options` - ZIPエントリのオプション。
+/// * `opts` - 圧縮オプション。
+/// * `pb` - 読み込んだバイト数だけ進める進捗バー。
+/// * `stats` - 処理結果を加算する統計情報。
+/// * `manifest` - `opts.embed_manifest` または `opts.emit_manifest` が true の場合に、格納したファイルを追加するマニフェスト。
+/// * `stored_digests` - `opts.deduplicate` が true の場合に、格納済みのファイルの内容のハッシュと最初のエントリ名を記録するマップ。
+/// * `journal` - 格納したファイルを記録する再開用のジャーナル。記録済みで変更されていないファイルは前回の結果をコピーします。
+///
+/// # Errors
+///
+/// * ファイルの読み込みまたはZIPへの書き込みに失敗した場合。
+/// * `LimitPolicy::Error` でサイズ上限を超えた場合（`ArchryptoError::FileTooLarge`）。
+/// * 格納済みのファイル数が `opts.max_entries` に達している場合（`ArchryptoError::MaxEntriesExceeded`）。
+#[allow(clippy::too_many_arguments)]
+fn add_file_to_zip<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    path: &Path,
+    entry_name: &str,
+    options: SimpleFileOptions,
+    opts: &CompressOptions,
+    pb: &progress::ProgressBar,
+    stats: &mut ArchiveStats,
+    manifest: &mut ManifestWriter,
+    stored_digests: &mut HashMap<[u8; 32], String>,
+    mut journal: Option<&mut Journal>,
+) -> Result<()> {
+    stats.files += 1;
+    let started = Instant::now();
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let size = metadata.len();
+    let records_manifest = opts.embed_manifest || opts.emit_manifest;
+    // 事前の走査を行わない場合は総バイト数が不明なため、ファイルを開くごとに進捗バーの総数を増やす
+    if opts.max_entries.is_some() {
+        pb.inc_length(size);
+    }
+    if let Some(limit) = opts.max_file_size {
+        if size > limit {
+            match opts.limit_policy {
+                LimitPolicy::Skip => {
+                    if let Some(on_skip) = opts.on_skip {
+                        on_skip(path, &format!("file size {} bytes exceeds the limit of {} bytes", size, limit));
+                    }
+                    stats.files_skipped += 1;
+                    pb.inc(size);
+                    return Ok(());
+                }
+                LimitPolicy::Error => {
+                    return Err(ArchryptoError::FileTooLarge { path: path.to_path_buf(), size, limit }.into());
+                }
+            }
+        }
+    }
+
+    if let Some(limit) = opts.max_entries {
+        if stats.files_written >= limit {
+            return Err(ArchryptoError::MaxEntriesExceeded { limit }.into());
+        }
+    }
+
+    if let Some(journal) = journal.as_deref_mut() {
+        // 中断した前回の実行で格納済みのファイルは、読み込み・圧縮・ハッシュ計算をせずに前回の結果をコピーする
+        if let Some(entry) = journal.copy_staged(zip, path, entry_name, size, metadata.modified().ok())? {
+            trace!("Resumed {} as {} from the journal", path.display(), entry_name);
+            if opts.deduplicate {
+                if let Some(digest) = journal::digest_from_hex(&entry.sha256) {
+                    stored_digests.entry(digest).or_insert_with(|| entry_name.to_string());
+                }
+            }
+            if records_manifest {
+                manifest.add(entry_name, size, entry.sha256.clone(), metadata.modified().ok());
+            }
+            stats.files_written += 1;
+            stats.files_resumed += 1;
+            stats.total_bytes += size;
+            pb.inc(size);
+            if opts.record_timings {
+                record_timing(stats, path, started, size);
+            }
+            return Ok(());
+        }
+    }
+
+    if opts.deduplicate {
+        let digest = dedup::hash_file(path)?;
+        if let Some(source) = stored_digests.get(&digest) {
+            // 同じ内容のファイルを格納済みの場合は、内容を持たず最初のエントリを参照するエントリを追加する
+            zip.start_file(entry_name, dedup::reference_options(source, entry_timestamp(opts))?)?;
+            if records_manifest {
+                manifest.add(entry_name, size, manifest::to_hex(&digest), metadata.modified().ok());
+            }
+            stats.files_written += 1;
+            stats.total_bytes += size;
+            pb.inc(size);
+            if opts.record_timings {
+                record_timing(stats, path, started, size);
+            }
+            return Ok(());
+        }
+        stored_digests.insert(digest, entry_name.to_string());
+    }
+
+    trace!("Adding {} as {} ({} bytes)", path.display(), entry_name, size);
+    let content_type = opts.detect_content_type.then(|| content_type::detect(path)).transpose()?.flatten();
+    let mut reader = progress::wrap_read(pb, file);
+    let written = match journal {
+        Some(journal) => {
+            // 再開に備えて1エントリだけのZIPに圧縮してジャーナルに記録し、そこから出力のZIPにコピーする
+            let mut staged = journal.create_staged(path)?;
+            let (written, sha256) = write_file_entry(&mut staged, path, entry_name, content_type.as_deref(), options, opts, &mut reader, true)?;
+            let sha256 = sha256.unwrap_or_default();
+            if records_manifest {
+                manifest.add(entry_name, written, sha256.clone(), metadata.modified().ok());
+            }
+            let entry = JournalEntry { path: path.to_path_buf(), sha256, size: written, modified: metadata.modified().ok() };
+            journal.finish_staged(staged, zip, entry)?;
+            written
+        }
+        None => {
+            let (written, sha256) = write_file_entry(zip, path, entry_name, content_type.as_deref(), options, opts, &mut reader, records_manifest)?;
+            if let Some(sha256) = sha256 {
+                manifest.add(entry_name, written, sha256, metadata.modified().ok());
+            }
+            written
+        }
+    };
+    stats.files_written += 1;
+    stats.total_bytes += written;
+    if opts.record_timings {
+        record_timing(stats, path, started, written);
+    }
+    Ok(())
+}
+
+/// ZIPにエントリを開始し、`reader` から読み込んだファイルの内容を書き込みます.
+///
+/// `content_type` を指定した場合は、MIME タイプを拡張フィールドに記録したオプションでエントリを開始します。
+///
+/// # Returns
+///
+/// 書き込んだバイト数と、`hash` が true の場合は内容の SHA-256（16進数文字列）を返します。
+///
+/// # Errors
+///
+/// ファイルの読み込みまたはZIPへの書き込みに失敗した場合にエラーを返します。
+#[allow(clippy::too_many_arguments)]
+fn write_file_entry<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    path: &Path,
+    entry_name: &str,
+    content_type: Option<&str>,
+    options: SimpleFileOptions,
+    opts: &CompressOptions,
+    reader: &mut impl Read,
+    hash: bool,
+) -> Result<(u64, Option<String>)> {
+    // ファイル全体をメモリに読み込まず、io::copy で固定サイズのバッファを介してZIPに書き込む。
+    // 進捗バーは読み込んだバイト数だけ進めるため、大きなファイルでも進捗が分かる
+    match content_type {
+        Some(content_type) => {
+            trace!("Detected content type of {}: {}", path.display(), content_type);
+            zip.start_file(entry_name, content_type::entry_options(content_type, entry_timestamp(opts))?)?;
+        }
+        None => zip.start_file(entry_name, options)?,
+    }
+    if !hash {
+        return Ok((copy(reader, zip)?, None));
+    }
+    let mut writer = manifest::HashingWriter::new(&mut *zip);
+    let written = copy(reader, &mut writer)?;
+    Ok((written, Some(writer.finalize_hex())))
+}
+
+/// ファイルの格納にかかった時間を `stats.slowest_files` に追加し、時間が長い順に `SLOWEST_FILES_LEN` 件を残します.
+fn record_timing(stats: &mut ArchiveStats, path: &Path, start: Instant, bytes: u64) {
+    stats.slowest_files.push(FileTimingRecord { path: path.to_path_buf(), start, end: Instant::now(), bytes });
+    stats.slowest_files.sort_by_key(|record| std::cmp::Reverse(record.elapsed()));
+    stats.slowest_files.truncate(SLOWEST_FILES_LEN);
+}
+
+/// 指定された暗号化ZIPファイルを復号し、
+/// 出力ディレクトリに展開します。
+///
+/// 復号化したZIPファイルは一時ファイルまたはインメモリバッファを用いて処理されます。
+///
+/// # Arguments
+///
+/// * `input_encrypted_file` - 暗号化されたZIPファイルのパス。拡張子は ".acrp" である必要があります。
+/// * `private_key_path` - 復号に使用する秘密鍵ファイルのパス。`opts.private_key_source` を指定した場合は無視します。
+/// * `output_dir` - 展開先のディレクトリパス。`opts.extract_to_original_paths` が true の場合は無視します。
+/// * `opts` - 展開オプション。
+///
+/// # Returns
+///
+/// 展開したファイル数などの統計情報を返します。`dry_run` の場合 `files_written` は 0 になります。
+///
+/// # Errors
+///
+/// * 入力ファイルの拡張子が正しくない場合、
+/// * `validate_archive_structure` でファイルの構造が不正と判定された場合、
+/// * 復号化処理、ZIP解凍、またはファイル書き出しに失敗した場合、
+/// * `OverwritePolicy::Error` で既存ファイルと衝突した場合、
+/// * `OverwritePolicy::Rename` で `MAX_RENAME_ATTEMPTS` 個の別名が全て使用されていた場合
+///   （`ArchryptoError::TooManyConflicts`）、
+/// * `MANIFEST.sha256` が埋め込まれており、ファイルのハッシュが一致しない場合
+///   （`ArchryptoError::ManifestMismatch`）、
+/// * `opts.verify_manifest` のマニフェストのエントリが存在しないか、ハッシュが一致しない場合
+///   （`ArchryptoError::ManifestVerificationFailed`）、
+/// * `post_extract_hook` の実行に失敗した場合、または終了コードが 0 以外の場合にエラーを返します。
+///   展開とフックの両方が失敗した場合は、展開のエラーにフックのエラーを文脈として付け加えて返します。
+pub fn extract_files(
+    input_encrypted_file: impl AsRef<Path>,
+    private_key_path: impl AsRef<Path>,
+    output_dir: impl AsRef<Path>,
+    opts: &ExtractOptions,
+) -> Result<ArchiveStats> {
+    let input_encrypted_file = input_encrypted_file.as_ref();
+    let private_key_path = private_key_path.as_ref();
+    let output_dir = output_dir.as_ref();
+    if !validate_extension(input_encrypted_file)? {
+        return Err(anyhow!("inputpath extention does not \".{}\"", EXTENTION));
+    }
+    if opts.extract_to_original_paths && opts.flatten {
+        return Err(anyhow!("flatten cannot be combined with extract_to_original_paths"));
+    }
+    // 秘密鍵の読み込みや RSA の復号の前に、明らかに壊れたファイルを検出する
+    validate_archive_structure(input_encrypted_file)?;
+    // 元の絶対パスに展開する場合は、出力ディレクトリの代わりにルートディレクトリを基準にする
+    let output_dir = if opts.extract_to_original_paths {
+        Path::new(std::path::MAIN_SEPARATOR_STR)
+    } else {
+        output_dir
+    };
+    let mut stats = ArchiveStats::default();
+    let mut result = extract_archive_entries(input_encrypted_file, private_key_path, output_dir, opts, &mut stats);
+    if let Some(hook) = &opts.post_extract_hook {
+        // 展開に失敗した場合は、post_extract_hook_on_error が指定されたときのみ途中までの統計情報で実行する
+        if !opts.dry_run && (result.is_ok() || opts.post_extract_hook_on_error) {
+            if let Err(e) = hooks::run_post_extract_hook(hook, output_dir, &stats) {
+                if result.is_ok() {
+                    return Err(e);
+                }
+                // 展開のエラーを呼び出し側で判別できるよう、フックのエラーは文脈として付け加える
+                result = result.with_context(|| format!("post_extract_hook also failed ({:#})", e));
+            }
+        }
+    }
+    result.map(|()| stats)
+}
+
+/// 従来の `&PathBuf` の秘密鍵パスを受け取るシグネチャで、デフォルトの `ExtractOptions` を使用して `extract_files` を呼び出します.
+///
+/// `extract_files` は全てのパスを `impl AsRef<Path>` で受け取るため、`&Path` と `&PathBuf` のどちらもそのまま渡せます。
+///
+/// # Errors
+///
+/// `extract_files` と同じ条件でエラーを返します。
+#[deprecated(since = "0.1.0", note = "use `extract_files`, which takes `impl AsRef<Path>` for every path")]
+pub fn extract_files_pathbuf(input_encrypted_file: &Path, private_key_path: &PathBuf, output_dir: &Path) -> Result<()> {
+    extract_files(input_encrypted_file, private_key_path, output_dir, &ExtractOptions::default())?;
+    Ok(())
+}
+
+/// 暗号化ZIPファイルを復号し、各エントリを `output_dir` に展開します（`extract_files` の本体）.
+///
+/// 展開に失敗した場合でも、それまでに展開したファイルの統計情報は `stats` に残ります。
+fn extract_archive_entries(
+    input_encrypted_file: &Path,
+    private_key_path: &Path,
+    output_dir: &Path,
+    opts: &ExtractOptions,
+    stats: &mut ArchiveStats,
+) -> Result<()> {
+    // 復号が終わるまでは、暗号化ファイルの読み込みバイト数で進捗を表示する
+    let read_pb = progress::new_bytes_progress_bar(
+        fs::metadata(input_encrypted_file)?.len(),
+        opts.progress_template.as_deref(),
+        opts.progress_chars.as_deref(),
+    );
+    read_pb.enable_steady_tick(Duration::from_millis(100));
+
+    // 復号処理：暗号化されたZIPファイルを復号し、Vec<u8>として取得
+    debug!("extract_files: {} -> {}", input_encrypted_file.display(), output_dir.display());
+    let decrypted_zip = decrypt_zip_with_rsa(input_encrypted_file, &opts.private_key_source(private_key_path), &read_pb, stats)?;
+    read_pb.finish_and_clear();
+    
+    // 一時ファイルに復号結果を書き出す
+    let mut temp_zip_file = gc::new_temp_file()?;
+    temp_zip_file.as_file_mut().write_all(&decrypted_zip)?;
+    debug!("Wrote {} bytes of decrypted ZIP data to {}", decrypted_zip.len(), temp_zip_file.path().display());
+    
+    // ZIPファイル内のファイル総数をカウントして進捗バーの総数を設定
+    let mut total_files = count_files_in_zip(&temp_zip_file)?;
+    debug!("ZIP contains {} entries", total_files);
+
+    let file = File::open(&temp_zip_file)?;
+    let reader = BufReader::new(file);
+    let mut archive = ZipArchive::new(reader)?;
+    if let Some(manifest_path) = &opts.verify_manifest {
+        manifest::verify_manifest_json(&mut archive, manifest_path)?;
+        debug!("Verified {} against {}", input_encrypted_file.display(), manifest_path.display());
+    }
+
+    // マニフェストが埋め込まれている場合は、展開した各ファイルのハッシュを照合する
+    let expected_hashes = match archive.by_name(EMBEDDED_MANIFEST_NAME) {
+        std::result::Result::Ok(mut manifest_file) => {
+            let mut content = String::new();
+            manifest_file.read_to_string(&mut content)?;
+            total_files -= 1;
+            Some(manifest::parse(&content)?)
+        }
+        Err(zip::result::ZipError::FileNotFound) => None,
+        Err(e) => return Err(e.into()),
+    };
+    if let (true, false, Some(confirm)) = (opts.extract_to_original_paths, opts.dry_run, opts.on_confirm) {
+        if !confirm(&format!("Extract {} files to their original absolute paths?", total_files)) {
+            return Err(anyhow!("Aborted: not extracting to the original paths"));
+        }
+    }
+    let mut seen_entries = HashSet::new();
+    let pb = progress::new_progress_bar(
+        u64::try_from(total_files)?,
+        opts.progress_template.as_deref(),
+        opts.progress_chars.as_deref(),
+    );
+
+    // 同じパスへの書き出しがワーカーで完了する前に重複して割り当てられないよう、書き出し予定のパスを記録する
+    let mut reserved_paths = HashSet::new();
+    thread::scope(|scope| -> Result<()> {
+        let threads = opts.extract_threads.max(1);
+        let (sender, workers) = if threads > 1 && !opts.dry_run {
+            let (sender, receiver) = mpsc::sync_channel::<(PathBuf, Vec<u8>)>(threads * 2);
+            let receiver = Arc::new(Mutex::new(receiver));
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    let receiver = Arc::clone(&receiver);
+                    scope.spawn(move || write_worker(&receiver))
+                })
+                .collect();
+            (Some(sender), workers)
+        } else {
+            (None, Vec::new())
+        };
+        let mut deferred_writes: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+
+        for i in 0..archive.len() {
+            let file = archive.by_index(i)?;
+            let name = file.name().to_string();
+            // 重複排除したエントリは内容を持たないため、参照先のエントリの内容を読み出す
+            let mut file = match dedup::source_entry(file.extra_data()) {
+                Some(source) => {
+                    drop(file);
+                    archive.by_name(&source)?
+                }
+                None => file,
+            };
+            let is_dir = name.ends_with('/');
+            if expected_hashes.is_some() && name == EMBEDDED_MANIFEST_NAME {
+                continue;
+            }
+
+            // 展開先のディレクトリ外に書き出さないよう、エントリ名を相対パスに正規化する
+            let entry_path = sanitize_entry_name(&name)?;
+            trace!("Extracting entry {}", name);
+            if entry_path.as_os_str().is_empty() {
+                continue;
+            }
+            // flatten 指定時はディレクトリエントリを無視し、ファイル名のみを使用
+            let outpath = if opts.flatten {
+                if is_dir {
+                    continue;
+                }
+                let file_name = entry_path
+                    .file_name()
+                    .ok_or_else(|| anyhow!("Invalid entry name: {}", name))?
+                    .to_owned();
+                output_dir.join(file_name)
+            } else {
+                output_dir.join(&entry_path)
+            };
+            if !outpath.starts_with(output_dir) {
+                return Err(ArchryptoError::UnsafeEntryPath { entry: name.clone() }.into());
+            }
+    
+            if is_dir {
+                if !opts.dry_run {
+                    create_dir_all(&outpath)?;
+                }
+            } else {
+                stats.files += 1;
+                stats.total_bytes += file.size();
+                if !opts.dry_run {
+                    if let Some(p) = outpath.parent() {
+                        if !p.exists() {
+                            create_dir_all(p)?;
+                        }
+                    }
+                }
+                let expected_hash = match &expected_hashes {
+                    Some(hashes) => {
+                        seen_entries.insert(name.clone());
+                        Some(hashes.get(&name).ok_or_else(|| ArchryptoError::ManifestMismatch {
+                            entry: name.clone(),
+                        })?)
+                    }
+                    None => None,
+                };
+                let original_outpath = outpath.clone();
+                let Some(outpath) = resolve_overwrite(outpath, opts.overwrite_policy, opts.rename_style, &reserved_paths)? else {
+                    stats.files_skipped += 1;
+                    pb.inc(1);
+                    continue;
+                };
+                if outpath != original_outpath {
+                    stats.renamed_files.push((name.clone(), outpath.clone()));
+                }
+                // 既に書き出し予定のパス（`OverwritePolicy::Overwrite` での同名のエントリ）は、
+                // ワーカー間で同じファイルに同時に書き込まないよう、ワーカーの終了後にメインスレッドで順に書き出す
+                let already_reserved = !reserved_paths.insert(outpath.clone());
+                let actual_hash = if opts.dry_run {
+                    pb.suspend(|| println!("{}", outpath.display()));
+                    // dry_run でもマニフェストとの照合は行う
+                    let mut writer = manifest::HashingWriter::new(io::sink());
+                    copy(&mut file, &mut writer)?;
+                    writer.finalize_hex()
+                } else if let Some(sender) = &sender {
+                    // エントリの読み出しはメインスレッドで行い、書き出しのみワーカーに任せる
+                    let mut writer = manifest::HashingWriter::new(Vec::with_capacity(file.size() as usize));
+                    copy(&mut file, &mut writer)?;
+                    let (data, hash) = writer.into_parts();
+                    if expected_hash.is_some_and(|expected| *expected != hash) {
+                        return Err(ArchryptoError::ManifestMismatch { entry: name.clone() }.into());
+                    }
+                    if already_reserved {
+                        deferred_writes.push((outpath, data));
+                    } else {
+                        sender
+                            .send((outpath, data))
+                            .map_err(|_| anyhow!("Extraction worker stopped unexpectedly"))?;
+                    }
+                    stats.files_written += 1;
+                    hash
+                } else {
+                    let mut writer = manifest::HashingWriter::new(File::create(&outpath)?);
+                    copy(&mut file, &mut writer)?;
+                    stats.files_written += 1;
+                    writer.finalize_hex()
+                };
+                if expected_hash.is_some_and(|expected| *expected != actual_hash) {
+                    return Err(ArchryptoError::ManifestMismatch { entry: name.clone() }.into());
+                }
+            }
+            pb.inc(1);
+        }
+
+        // 送信側を閉じてワーカーに終了を伝え、書き出しエラーがあれば返す
+        drop(sender);
+        for worker in workers {
+            worker.join().map_err(|_| anyhow!("Extraction worker panicked"))??;
+        }
+        // アーカイブ内の順に書き出し、単一スレッドでの展開と同じく後のエントリの内容を残す
+        for (outpath, data) in deferred_writes {
+            File::create(&outpath)?.write_all(&data)?;
+        }
+        Ok(())
+    })?;
+    // マニフェストに記載されているがアーカイブに存在しないファイルも不一致として扱う
+    if let Some(hashes) = &expected_hashes {
+        if let Some(missing) = hashes.keys().find(|name| !seen_entries.contains(*name)) {
+            return Err(ArchryptoError::ManifestMismatch { entry: missing.clone() }.into());
+        }
+    }
+    pb.finish();
+    debug!(
+        "Extracted {} files ({} written, {} skipped, {} bytes)",
+        stats.files,
+        stats.files_written,
+        stats.files_skipped,
+        stats.total_bytes
+    );
+    if !opts.dry_run {
+        println!("Complete!");
+        println!("{}", canonicalize(output_dir)?.display());
+    }
+    Ok(())
+}
+
+/// メモリ上のデータを1つのエントリとしてZIP圧縮し、公開鍵で暗号化した `.acrp` 形式のバイト列を返します.
+///
+/// ファイルシステムを使用しないため、進捗バーも表示しません。
+///
+/// # Arguments
+///
+/// * `entry_name` - ZIP内でのエントリ名。
+/// * `data` - 圧縮するデータ。
+/// * `public_key` - 暗号化に使用する公開鍵。
+///
+/// # Errors
+///
+/// ZIP圧縮または暗号化処理に失敗した場合にエラーを返します。
+pub fn compress_bytes(entry_name: &str, data: &[u8], public_key: &RsaPublicKey) -> Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(io::Cursor::new(Vec::new()));
+    zip.start_file(entry_name, SimpleFileOptions::default())?;
+    zip.write_all(data)?;
+    let zip_data = zip.finish()?.into_inner();
+    encrypt_with_public_key(&zip_data, public_key, &ArchiveHeader::new())
+}
+
+/// `.acrp` 形式のバイト列を秘密鍵で復号し、ZIP内の全てのファイルをメモリ上に展開します.
+///
+/// # Arguments
+///
+/// * `archive` - `.acrp` 形式のバイト列。
+/// * `private_key` - 復号に使用する秘密鍵。
+///
+/// # Returns
+///
+/// エントリ名と内容の組のリストを、ZIP内の順序で返します（ディレクトリを除く）。
+///
+/// # Errors
+///
+/// 復号またはZIPの解析に失敗した場合にエラーを返します。
+pub fn extract_bytes(archive: &[u8], private_key: &RsaPrivateKey) -> Result<Vec<(String, Vec<u8>)>> {
+    let zip_data = decrypt_with_private_key(archive, private_key)?;
+    let mut zip = ZipArchive::new(io::Cursor::new(zip_data))?;
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        // 重複排除したエントリは、先に格納されている参照先のエントリの内容を複製する
+        let data = match dedup::source_entry(file.extra_data()) {
+            Some(source) => entries
+                .iter()
+                .find(|(entry_name, _)| *entry_name == source)
+                .map(|(_, data)| data.clone())
+                .ok_or_else(|| anyhow!("Entry {} refers to a missing entry: {}", name, source))?,
+            None => {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                data
+            }
+        };
+        entries.push((name, data));
+    }
+    Ok(entries)
+}
+
+/// ZIPエントリ名を、展開先ディレクトリからの安全な相対パスに変換します.
+///
+/// `\` は `/` として扱い、先頭の `/`（UNC の `\\server\share` を含む）を取り除きます。
+/// Windows のドライブ指定（`C:`）は `C` という名前のディレクトリとして扱い、
+/// `.` は無視し、`..` は直前の要素を取り除きます。
+/// そのため `C:\evil.exe` は `C/evil.exe` に展開されます。
+///
+/// # Arguments
+///
+/// * `name` - ZIPエントリ名。
+///
+/// # Errors
+///
+/// `..` によりエントリ名の先頭より上の階層を指している場合（`ArchryptoError::UnsafeEntryPath`）にエラーを返します。
+fn sanitize_entry_name(name: &str) -> Result<PathBuf> {
+    let mut components: Vec<&str> = Vec::new();
+    for component in name.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.pop().is_none() {
+                    return Err(ArchryptoError::UnsafeEntryPath { entry: name.to_string() }.into());
+                }
+            }
+            // ドライブ指定（`C:`）はドライブ名のディレクトリとして扱う
+            drive if drive.len() == 2 && drive.ends_with(':') && drive.as_bytes()[0].is_ascii_alphabetic() => {
+                components.push(&drive[..1]);
+            }
+            component => components.push(component),
+        }
+    }
+    Ok(components.iter().collect())
+}
+
+/// 展開のワーカースレッド. チャネルが閉じられるまで受け取ったデータをファイルに書き出します.
+///
+/// # Errors
+///
+/// ファイルの書き出しに失敗した場合にエラーを返します。
+fn write_worker(receiver: &Mutex<mpsc::Receiver<(PathBuf, Vec<u8>)>>) -> Result<()> {
+    loop {
+        let received = receiver
+            .lock()
+            .map_err(|_| anyhow!("Extraction worker queue is poisoned"))?
+            .recv();
+        let std::result::Result::Ok((outpath, data)) = received else {
+            return Ok(());
+        };
+        File::create(&outpath)?.write_all(&data)?;
+    }
+}
+
+/// `.acrp` ファイルの平文ヘッダを読み取ります.
+///
+/// 復号は行わないため、秘密鍵は不要です。ヘッダを持たない旧形式のファイルの場合は
+/// バージョン 0 の空のヘッダを返します。
+///
+/// # Arguments
+///
+/// * `archive_path` - `.acrp` ファイルのパス。
+///
+/// # Errors
+///
+/// ファイルの読み込みに失敗した場合、またはヘッダの形式が不正な場合にエラーを返します。
+pub fn inspect_archive(archive_path: &Path) -> Result<ArchiveHeader> {
+    let mut reader = armor::open_archive(archive_path)?;
+    ArchiveHeader::read_from(&mut reader)
+}
+
+/// `.acrp` ファイルを復号し、指定したエントリの内容を、ディスクに展開せずに返します.
+///
+/// # Arguments
+///
+/// * `archive` - `.acrp` ファイルのパス。
+/// * `private_key` - 復号に使用する秘密鍵ファイルのパス。
+/// * `entry_name` - 取り出すエントリの名前（ZIP内のパスと完全に一致する必要があります）。
+///
+/// # Errors
+///
+/// ファイルの読み込み、秘密鍵のパース、復号に失敗した場合、またはエントリが存在しない場合にエラーを返します。
+pub fn cat_file(archive: impl AsRef<Path>, private_key: impl AsRef<Path>, entry_name: &str) -> Result<Vec<u8>> {
+    decrypt_archive_entries(archive.as_ref(), private_key.as_ref())?
+        .into_iter()
+        .find(|(name, _)| name == entry_name)
+        .map(|(_, data)| data)
+        .ok_or_else(|| anyhow!("Entry not found in archive: {}", entry_name))
+}
+
+/// `.acrp` ファイルを復号し、glob パターンに一致する全てのエントリの名前と内容を、アーカイブ内の順に返します.
+///
+/// パターンは `.gitignore` と同じ書式で、`/` を含まないパターン（例: `*.json`）は任意の階層のファイル名に一致します。
+///
+/// # Arguments
+///
+/// * `archive` - `.acrp` ファイルのパス。
+/// * `private_key` - 復号に使用する秘密鍵ファイルのパス。
+/// * `pattern` - エントリ名と照合する glob パターン。
+///
+/// # Errors
+///
+/// パターンが不正な場合、またはファイルの読み込み、秘密鍵のパース、復号に失敗した場合にエラーを返します。
+/// 一致するエントリがない場合は空のリストを返します。
+pub fn cat_files_matching(
+    archive: impl AsRef<Path>,
+    private_key: impl AsRef<Path>,
+    pattern: &str,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut builder = OverrideBuilder::new("");
+    builder.add(pattern)?;
+    let matcher = builder.build()?;
+    Ok(decrypt_archive_entries(archive.as_ref(), private_key.as_ref())?
+        .into_iter()
+        .filter(|(name, _)| matcher.matched(name, false).is_whitelist())
+        .collect())
+}
+
+/// `.acrp` ファイルを復号し、全てのファイルのエントリ名と内容をメモリ上に読み出します.
+fn decrypt_archive_entries(archive: &Path, private_key: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let private_key = load_private_key(private_key)?;
+    extract_bytes(&fs::read(archive)?, &private_key)
+}
+
+/// `CompressOptions::encrypt_metadata` で暗号化して保存したメタデータを、アーカイブを復号して読み取ります.
+///
+/// ファイルは展開しません。ヘッダに平文で保存したメタデータは `inspect_archive` で読み取れます。
+///
+/// # Arguments
+///
+/// * `archive_path` - `.acrp` ファイルのパス。
+/// * `private_key_path` - 復号に使用する秘密鍵ファイルのパス。
+///
+/// # Returns
+///
+/// 暗号化したメタデータがない場合は None を返します。
+///
+/// # Errors
+///
+/// ファイルの読み込み、秘密鍵のパース、復号に失敗した場合、またはメタデータが JSON として不正な場合にエラーを返します。
+pub fn read_encrypted_metadata(
+    archive_path: impl AsRef<Path>,
+    private_key_path: impl AsRef<Path>,
+) -> Result<Option<serde_json::Value>> {
+    let private_key = load_private_key(private_key_path.as_ref())?;
+    let zip_data = decrypt_with_private_key(&fs::read(archive_path.as_ref())?, &private_key)?;
+    let zip = ZipArchive::new(io::Cursor::new(zip_data))?;
+    if zip.comment().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(zip.comment())?))
+}
+
+/// `.acrp` ファイルを復号できる受信者の一覧を返します.
+///
+/// 復号は行わないため、秘密鍵は不要です。現在の形式では受信者は常に1人です。
+/// 旧形式のファイルや、鍵IDを記録する前のバージョンで作成したファイルでは `key_id` が None になります。
+///
+/// # Arguments
+///
+/// * `path` - `.acrp` ファイルのパス。
+///
+/// # Errors
+///
+/// ファイルの読み込みに失敗した場合、またはヘッダの形式が不正な場合にエラーを返します。
+pub fn list_recipients(path: &Path) -> Result<Vec<RecipientInfo>> {
+    let mut reader = armor::open_archive(path)?;
+    let header = ArchiveHeader::read_from(&mut reader)?;
+    if header.version == 0 {
+        // 旧形式はヘッダを持たないため、先頭から読み直す
+        reader.seek(SeekFrom::Start(0))?;
+    }
+    let mut nonce_and_size = [0u8; 14];
+    reader
+        .read_exact(&mut nonce_and_size)
+        .map_err(|_| anyhow!("Archive is truncated"))?;
+    let key_size_bytes = u16::from_be_bytes([nonce_and_size[12], nonce_and_size[13]]);
+    Ok(vec![RecipientInfo {
+        key_id: header.recipient_key_id,
+        key_size_bytes,
+    }])
+}
+
+/// 暗号化された AES 鍵のサイズとして妥当な範囲（RSA 1024〜8192 ビット）.
+const PLAUSIBLE_KEY_SIZES: std::ops::RangeInclusive<usize> = 128..=1024;
+/// AES-GCM の認証タグのバイト数.
+const AES_GCM_TAG_LEN: u64 = 16;
+
+/// `.acrp` ファイルを復号せずに、バイナリの構造が妥当かを確認します.
+///
+/// ヘッダ、Nonce、暗号化された AES 鍵のサイズのみを読み込み、時間のかかる RSA の復号の前に明らかに壊れたファイルを検出します。
+/// マジックバイトを持たない旧形式のファイルは、Nonce から始まるものとして確認します。
+///
+/// # Arguments
+///
+/// * `path` - `.acrp` ファイルのパス。
+///
+/// # Errors
+///
+/// * ヘッダのバージョンがサポートされていない場合（`ArchryptoError::UnsupportedVersion`）。
+/// * ヘッダの形式が不正な場合（`ArchryptoError::CorruptedHeader`）。
+/// * 暗号化された AES 鍵のサイズが 128〜1024 バイトの範囲にない場合（`ArchryptoError::ImplausibleKeySize`）。
+/// * ファイルが Nonce、鍵のサイズ、暗号化された AES 鍵、認証タグを含む長さに満たない場合（`ArchryptoError::TruncatedArchive`）。
+/// * ファイルの読み込みに失敗した場合にエラーを返します。
+pub fn validate_archive_structure(path: &Path) -> Result<()> {
+    let mut reader = armor::open_archive(path)?;
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut prefix = [0u8; 6];
+    if reader.read_exact(&mut prefix).is_ok() && prefix.starts_with(header::MAGIC) {
+        let version = u16::from_be_bytes([prefix[4], prefix[5]]);
+        if version == 0 || version > header::CURRENT_VERSION {
+            return Err(ArchryptoError::UnsupportedVersion { version }.into());
+        }
+    }
+    reader.seek(SeekFrom::Start(0))?;
+    let header = ArchiveHeader::read_from(&mut reader)
+        .map_err(|e| ArchryptoError::CorruptedHeader { reason: e.to_string() })?;
+    if header.version == 0 {
+        // 旧形式はヘッダを持たないため、先頭から読み直す
+        reader.seek(SeekFrom::Start(0))?;
+    }
+    let header_len = reader.stream_position()?;
+
+    let mut nonce_and_size = [0u8; 14];
+    if reader.read_exact(&mut nonce_and_size).is_err() {
+        return Err(ArchryptoError::TruncatedArchive { len, min: header_len + 14 + AES_GCM_TAG_LEN }.into());
+    }
+    let key_size = u16::from_be_bytes([nonce_and_size[12], nonce_and_size[13]]) as usize;
+    if !PLAUSIBLE_KEY_SIZES.contains(&key_size) {
+        return Err(ArchryptoError::ImplausibleKeySize { key_size }.into());
+    }
+    let min = header_len + 14 + key_size as u64 + AES_GCM_TAG_LEN;
+    if len < min {
+        return Err(ArchryptoError::TruncatedArchive { len, min }.into());
+    }
+    Ok(())
+}
+
+/// 公開鍵のフィンガープリントを `SHA256:xx:xx:...` の形式で返します.
+///
+/// DER 形式の SubjectPublicKeyInfo の SHA-256 を、コロン区切りの16進数で表します。
+///
+/// # Errors
+///
+/// 公開鍵のエンコードに失敗した場合にエラーを返します。
+pub fn public_key_fingerprint(public_key: &RsaPublicKey) -> Result<String> {
+    let der = public_key.to_public_key_der()?;
+    let digest = Sha256::digest(der.as_bytes());
+    let hex: Vec<String> = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("SHA256:{}", hex.join(":")))
+}
+
+/// 秘密鍵に対応する公開鍵のフィンガープリントを `SHA256:xx:xx:...` の形式で返します.
+///
+/// # Errors
+///
+/// 公開鍵のエンコードに失敗した場合にエラーを返します。
+pub fn private_key_fingerprint(private_key: &RsaPrivateKey) -> Result<String> {
+    public_key_fingerprint(&private_key.to_public_key())
+}
+
+/// 公開鍵と秘密鍵が対応するペアであるかを検証します.
+///
+/// ランダムな32バイトのデータを公開鍵（RSA-OAEP/SHA-256）で暗号化し、
+/// 秘密鍵で復号した結果が元のデータと一致するかを確認します。
+///
+/// # Arguments
+///
+/// * `public_key_path` - 公開鍵ファイルのパス（PEM形式）。
+/// * `private_key_path` - 秘密鍵ファイルのパス（PKCS#8 PEM形式）。
+///
+/// # Returns
+///
+/// 鍵ペアが一致すれば Ok(true)、一致しなければ Ok(false) を返します。
+///
+/// # Errors
+///
+/// 鍵ファイルの読み込みやパース、または暗号化に失敗した場合にエラーを返します。
+pub fn verify_keypair(public_key_path: &Path, private_key_path: &Path) -> Result<bool> {
+    let public_key = load_public_key(public_key_path)?;
+    let private_key = load_private_key(private_key_path)?;
+
+    let mut rng = OsRng;
+    let mut challenge = [0u8; 32];
+    rng.fill_bytes(&mut challenge);
+
+    let encrypted = public_key.encrypt(&mut rng, Oaep::new::<Sha256>(), &challenge)?;
+    // 鍵が一致しない場合、復号はパディングエラーとして失敗する
+    Ok(private_key
+        .decrypt(Oaep::new::<Sha256>(), &encrypted)
+        .is_ok_and(|decrypted| decrypted == challenge))
+}
+
+/// 公開鍵で実際に暗号化できるかを確認するため、ランダムな32バイトのデータを暗号化します.
+///
+/// データは RSA-OAEP/SHA-256 で暗号化します。秘密鍵を持つ側で `test_decrypt` に暗号文を渡して復号し、
+/// 元のデータと比較することで、アーカイブを作成せずに鍵ペアを確認できます。
+///
+/// # Arguments
+///
+/// * `public_key_path` - 公開鍵ファイルのパス（PEM形式）。
+///
+/// # Returns
+///
+/// 暗号化したデータと、Base64 でエンコードした暗号文の組を返します。
+///
+/// # Errors
+///
+/// 公開鍵の読み込みやパース、または暗号化に失敗した場合にエラーを返します。
+pub fn test_encrypt(public_key_path: &Path) -> Result<(Vec<u8>, String)> {
+    let public_key = load_public_key(public_key_path)?;
+    let mut rng = OsRng;
+    let mut payload = vec![0u8; 32];
+    rng.fill_bytes(&mut payload);
+    let encrypted = public_key.encrypt(&mut rng, Oaep::new::<Sha256>(), &payload)?;
+    Ok((payload, STANDARD.encode(encrypted)))
+}
+
+/// `test_encrypt` で作成した Base64 の暗号文を秘密鍵で復号します.
+///
+/// # Arguments
+///
+/// * `private_key_path` - 秘密鍵ファイルのパス（PKCS#8 PEM形式）。
+/// * `ciphertext` - `test_encrypt` が返した Base64 の暗号文。
+///
+/// # Returns
+///
+/// 復号したデータを返します。
+///
+/// # Errors
+///
+/// 秘密鍵の読み込みやパース、Base64 のデコードに失敗した場合、
+/// または復号に失敗した場合（暗号化に使用した公開鍵と対応しない場合を含む）にエラーを返します。
+pub fn test_decrypt(private_key_path: &Path, ciphertext: &str) -> Result<Vec<u8>> {
+    let private_key = load_private_key(private_key_path)?;
+    let encrypted = STANDARD
+        .decode(ciphertext.trim())
+        .map_err(|e| anyhow!("Invalid Base64 ciphertext: {}", e))?;
+    private_key
+        .decrypt(Oaep::new::<Sha256>(), &encrypted)
+        .map_err(|_| anyhow!("Failed to decrypt the ciphertext; the private key does not match the public key used to encrypt it"))
+}
+
+/// PEM形式（SubjectPublicKeyInfo）の公開鍵ファイルを読み込みます.
+///
+/// PEM形式の X.509 証明書（`-----BEGIN CERTIFICATE-----`）の場合は、証明書に含まれる公開鍵を読み込みます。
+///
+/// # Arguments
+///
+/// * `public_key_path` - 公開鍵ファイルのパス。
+///
+/// # Errors
+///
+/// ファイルの読み込み、または公開鍵のパースに失敗した場合にエラーを返します。
+pub fn load_public_key(public_key_path: &Path) -> Result<RsaPublicKey> {
+    let public_key_pem = fs::read_to_string(public_key_path)?;
+    if public_key_pem.contains("-----BEGIN CERTIFICATE-----") {
+        return revocation::public_key_from_certificate_pem(&public_key_pem);
+    }
+    Ok(RsaPublicKey::from_public_key_pem(&public_key_pem)?)
+}
+
+/// RSA 鍵（公開鍵・秘密鍵のいずれも可）のモジュラスのビット数を返します.
+pub fn key_bits(key: &impl PublicKeyParts) -> usize {
+    key.n().bits()
+}
+
+/// 公開鍵のビット数が `MIN_RSA_KEY_BITS` 以上であることを確認します.
+///
+/// # Errors
+///
+/// `key_size_override` が `Enforce` で、ビット数が足りない場合に `ArchryptoError::KeyTooSmall` を返します。
+fn check_key_size(public_key: &RsaPublicKey, key_size_override: KeySizeOverride) -> Result<()> {
+    match key_size_override {
+        KeySizeOverride::Enforce => {}
+        #[cfg(any(test, feature = "test-utils"))]
+        KeySizeOverride::InsecureTestMode => return Ok(()),
+    }
+    let bits = key_bits(public_key);
+    if bits < MIN_RSA_KEY_BITS {
+        return Err(ArchryptoError::KeyTooSmall { bits, min: MIN_RSA_KEY_BITS }.into());
+    }
+    Ok(())
+}
+
+/// 公開鍵をPEM形式（SubjectPublicKeyInfo）でファイルに書き出します.
+///
+/// # Arguments
+///
+/// * `public_key` - 書き出す公開鍵。
+/// * `path` - 書き出し先のパス。
+///
+/// # Errors
+///
+/// 公開鍵のエンコード、またはファイルの書き込みに失敗した場合にエラーを返します。
+pub fn save_public_key(public_key: &RsaPublicKey, path: &Path) -> Result<()> {
+    fs::write(path, public_key_pem(public_key)?)?;
+    Ok(())
+}
+
+/// 公開鍵をPEM形式（SubjectPublicKeyInfo）の文字列に変換します.
+///
+/// # Arguments
+///
+/// * `public_key` - 変換する公開鍵。
+///
+/// # Errors
+///
+/// 公開鍵のエンコードに失敗した場合にエラーを返します。
+pub fn public_key_pem(public_key: &RsaPublicKey) -> Result<String> {
+    Ok(public_key.to_public_key_pem(rsa::pkcs8::LineEnding::LF)?)
+}
+
+/// 指定したビット数の RSA 鍵ペアを生成します.
+///
+/// # Arguments
+///
+/// * `bits` - 鍵のビット数。`MIN_RSA_KEY_BITS` 以上である必要があります。
+///
+/// # Returns
+///
+/// 生成した公開鍵と秘密鍵のペアを返します。
+///
+/// # Errors
+///
+/// `bits` が `MIN_RSA_KEY_BITS` 未満の場合（`ArchryptoError::KeyTooSmall`）、または鍵の生成に失敗した場合にエラーを返します。
+pub fn generate_rsa_keypair(bits: usize) -> Result<(RsaPublicKey, RsaPrivateKey)> {
+    if bits < MIN_RSA_KEY_BITS {
+        return Err(ArchryptoError::KeyTooSmall { bits, min: MIN_RSA_KEY_BITS }.into());
+    }
+    let private_key = RsaPrivateKey::new(&mut OsRng, bits)?;
+    Ok((RsaPublicKey::from(&private_key), private_key))
+}
+
+/// 秘密鍵を PKCS#8 PEM形式でファイルに書き出します.
+///
+/// # Arguments
+///
+/// * `private_key` - 書き出す秘密鍵。
+/// * `path` - 書き出し先のパス。
+///
+/// # Errors
+///
+/// 秘密鍵のエンコード、またはファイルの書き込みに失敗した場合にエラーを返します。
+pub fn save_private_key(private_key: &RsaPrivateKey, path: &Path) -> Result<()> {
+    private_key.write_pkcs8_pem_file(path, rsa::pkcs8::LineEnding::LF)?;
+    Ok(())
+}
+
+/// PKCS#8 PEM形式の秘密鍵ファイルを読み込みます.
+///
+/// # Arguments
+///
+/// * `private_key_path` - 秘密鍵ファイルのパス。
+///
+/// # Errors
+///
+/// ファイルの読み込み、または秘密鍵のパースに失敗した場合にエラーを返します。
+pub fn load_private_key(private_key_path: &Path) -> Result<RsaPrivateKey> {
+    let private_key_pem = fs::read_to_string(private_key_path)?;
+    Ok(RsaPrivateKey::from_pkcs8_pem(&private_key_pem)?)
+}
+
+/// 展開先パスに既存のファイルがある場合、`OverwritePolicy` に従って書き出し先を決定します.
+///
+/// # Arguments
+///
+/// * `outpath` - 本来の書き出し先パス。
+/// * `policy` - 既存ファイルと衝突した場合の挙動。
+/// * `rename_style` - `OverwritePolicy::Rename` で付ける別名の形式。
+/// * `reserved` - まだ書き出していないが、既に書き出し先として割り当てたパス。既存のファイルと同様に扱います。
+///
+/// # Returns
+///
+/// 書き出し先のパスを返します。書き出しをスキップする場合は `None` を返します。
+///
+/// # Errors
+///
+/// * `OverwritePolicy::Error` で既存ファイルと衝突した場合。
+/// * `OverwritePolicy::Rename` で使用されていない別名が見つからない場合（`ArchryptoError::TooManyConflicts`）。
+fn resolve_overwrite(
+    outpath: PathBuf,
+    policy: OverwritePolicy,
+    rename_style: RenameStyle,
+    reserved: &HashSet<PathBuf>,
+) -> Result<Option<PathBuf>> {
+    if !outpath.exists() && !reserved.contains(&outpath) {
+        return Ok(Some(outpath));
+    }
+    match policy {
+        OverwritePolicy::Overwrite => Ok(Some(outpath)),
+        OverwritePolicy::Skip => Ok(None),
+        OverwritePolicy::Error => Err(anyhow!("Output file already exists: {}", outpath.display())),
+        OverwritePolicy::Rename => next_available_path(&outpath, rename_style, reserved).map(Some),
+    }
+}
+
+/// `base` に `style` の形式で連番を付けた別名のうち、存在せず `reserved` にも含まれない最初のパスを返します.
+///
+/// # Errors
+///
+/// 連番が `MAX_RENAME_ATTEMPTS` に達しても見つからない場合に `ArchryptoError::TooManyConflicts` を返します。
+fn next_available_path(base: &Path, style: RenameStyle, reserved: &HashSet<PathBuf>) -> Result<PathBuf> {
+    for counter in 1..=MAX_RENAME_ATTEMPTS {
+        let candidate = match style {
+            RenameStyle::Counter => {
+                let mut candidate = base.as_os_str().to_owned();
+                candidate.push(format!(".{}", counter));
+                PathBuf::from(candidate)
+            }
+            RenameStyle::Finder => {
+                let mut file_name = base.file_stem().unwrap_or_default().to_owned();
+                file_name.push(format!(" ({})", counter));
+                if let Some(extension) = base.extension() {
+                    file_name.push(".");
+                    file_name.push(extension);
+                }
+                base.with_file_name(file_name)
+            }
+        };
+        if !candidate.exists() && !reserved.contains(&candidate) {
+            return Ok(candidate);
+        }
+    }
+    Err(ArchryptoError::TooManyConflicts { path: base.to_path_buf(), attempts: MAX_RENAME_ATTEMPTS }.into())
+}
+
+/// 指定されたZIPファイル（未暗号化）の公開鍵による暗号化を行い、
+/// 結果を encrypted_path に保存します.
+///
+/// # Arguments
+///
+/// * `input_zip` - 暗号化対象のZIPファイルのパス。
+/// * `public_key` - 暗号化に使用する公開鍵の取得元。
+/// * `encrypted_path` - 暗号化結果の出力パス。拡張子は ".acrp" である必要があります。
+/// * `header` - 出力ファイルの先頭に書き出す平文ヘッダ。AES-GCM の追加認証データとしても使用します。
+///   受信者の鍵ID（`recipient_key_id`）は公開鍵から計算して上書きします。
+/// * `opts` - 圧縮オプション。出力ファイルの作成の再試行（`retry_count` / `retry_delay`）に使用します。
+///
+/// # Errors
+///
+/// 暗号化処理に失敗した場合、またはファイル読み書きに失敗した場合にエラーを返します。
+fn encrypt_file_with_public_key(
+    input_zip: &Path,
+    public_key: &PublicKeySource,
+    encrypted_path: &Path,
+    header: &ArchiveHeader,
+    opts: &CompressOptions,
+) -> Result<()> {
+    // 公開鍵の読み込み（InMemory の場合はそのまま使用）
+    let public_key = &*public_key.load()?;
+
+    // ZIPファイルを読み込みながら暗号化して出力
+    let mut zip_file = File::open(input_zip)?;
+    let output = retry::retry_io(opts.retry_count, opts.retry_delay, "Creating the output file", || File::create(encrypted_path))?;
+    let mut encryptor = start_encrypted_archive(BufWriter::new(output), public_key, header)?;
+    let encrypted_bytes = copy(&mut zip_file, &mut encryptor)?;
+    encryptor.finish()?.into_inner().map_err(io::IntoInnerError::into_error)?;
+    debug!("Encrypted {} bytes of ZIP data from {} into {}", encrypted_bytes, input_zip.display(), encrypted_path.display());
+
+    Ok(())
+}
+
+/// ZIPデータを公開鍵で暗号化し、`.acrp` 形式のバイト列を返します.
+///
+/// # Arguments
+///
+/// * `zip_data` - 暗号化対象のZIPデータ。
+/// * `public_key` - 暗号化に使用する公開鍵。
+/// * `header` - 先頭に書き出す平文ヘッダ。受信者の鍵IDは公開鍵から計算して上書きし、
+///   作成日時とツールのバージョンが未設定の場合は現在時刻と archrypto_core のバージョンを設定します。
+///
+/// # Errors
+///
+/// 暗号化処理に失敗した場合にエラーを返します。
+fn encrypt_with_public_key(zip_data: &[u8], public_key: &RsaPublicKey, header: &ArchiveHeader) -> Result<Vec<u8>> {
+    let mut encryptor = start_encrypted_archive(Vec::with_capacity(zip_data.len() + 1024), public_key, header)?;
+    encryptor.write_all(zip_data)?;
+    Ok(encryptor.finish()?)
+}
+
+/// `.acrp` の暗号化を開始します.
+///
+/// AES 鍵と Nonce を生成し、ヘッダ, Nonce, AES鍵のサイズ, 公開鍵で暗号化したAES鍵の順に `writer` へ書き出します。
+/// 返したライターに書き込んだデータは AES-256-GCM で暗号化しながら `writer` に書き出され、
+/// `GcmEncryptWriter::finish` で認証タグを書き出すと `.acrp` 形式のデータが完成します。
 ///
 /// # Arguments
 ///
-/// * `input_zip` - 暗号化対象のZIPファイルのパス。
-/// * `public_key_path` - 暗号化に使用する公開鍵ファイルのパス。
-/// * `encrypted_path` - 暗号化結果の出力パス。拡張子は ".acrp" である必要があります。
+/// * `writer` - 暗号化したデータの書き出し先。
+/// * `public_key` - AES 鍵の暗号化に使用する公開鍵。
+/// * `header` - 先頭に書き出す平文ヘッダ。AES-GCM の追加認証データとしても使用します。受信者の鍵IDは公開鍵から計算して上書きし、
+///   作成日時とツールのバージョンが未設定の場合は現在時刻と archrypto_core のバージョンを設定します。
 ///
 /// # Errors
 ///
-/// 暗号化処理に失敗した場合、またはファイル読み書きに失敗した場合にエラーを返します。
-fn encrypt_file_with_public_key(
-    input_zip: &Path,
-    public_key_path: &Path,
-    encrypted_path: &Path,
-) -> Result<()> {
+/// AES 鍵の暗号化、または `writer` への書き込みに失敗した場合にエラーを返します。
+fn start_encrypted_archive<W: Write>(
+    mut writer: W,
+    public_key: &RsaPublicKey,
+    header: &ArchiveHeader,
+) -> Result<gcm_stream::GcmEncryptWriter<W>> {
     let mut rng = OsRng;
-        
-    // 公開鍵の読み込み
-    let public_key_pem = fs::read_to_string(public_key_path)?;
-    let public_key = RsaPublicKey::from_public_key_pem(&public_key_pem)?;
-
-    // ZIPファイルの読み込み
-    let mut zip_data = Vec::new();
-    let mut zip_file = File::open(input_zip)?;
-    zip_file.read_to_end(&mut zip_data)?;
+    let header = ArchiveHeader {
+        recipient_key_id: Some(public_key_fingerprint(public_key)?),
+        created_at: header.created_at.or_else(|| Some(SystemTime::now())),
+        tool_version: header.tool_version.clone().or_else(|| Some(TOOL_VERSION.to_string())),
+        ..header.clone()
+    };
 
     // AES-GCM用の鍵とNonceの生成
     let aes_key = Aes256Gcm::generate_key(&mut rng);
     let nonce = Aes256Gcm::generate_nonce(&mut rng);
-    
-    // AES-GCM によるZIPファイルの暗号化
-    let cipher = Aes256Gcm::new(&aes_key);
-    let encrypted_zip = cipher.encrypt(&nonce, Payload::from(zip_data.as_ref()))
-        .map_err(|e| anyhow!(e.to_string()))?;
     // 公開鍵によるAES鍵の暗号化
     let encrypted_key = public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &aes_key)?;
     let key_size = encrypted_key.len() as u16;
 
-    // 暗号化データの保存: Nonce, AES鍵のサイズ, 暗号化されたAES鍵, 暗号化ZIPデータの順に出力
-    let mut encrypted_file = File::create(encrypted_path)?;
-    encrypted_file.write_all(&nonce)?;
-    encrypted_file.write_all(&key_size.to_be_bytes())?;
-    encrypted_file.write_all(&encrypted_key)?;
-    encrypted_file.write_all(&encrypted_zip)?;
-
-    Ok(())
+    // 暗号化データ: ヘッダ, Nonce, AES鍵のサイズ, 暗号化されたAES鍵の順に出力し、暗号化ZIPデータが続く
+    let header_bytes = header.encode();
+    writer.write_all(&header_bytes)?;
+    writer.write_all(&nonce)?;
+    writer.write_all(&key_size.to_be_bytes())?;
+    writer.write_all(&encrypted_key)?;
+    // AES-GCM によるZIPデータの暗号化（ヘッダを追加認証データとして改ざんを検出可能にする）
+    Ok(gcm_stream::GcmEncryptWriter::new(writer, &aes_key, &nonce, &header_bytes))
 }
 
 /// 暗号化されたZIPファイルを復号し、その復号結果を Vec<u8> として返します.
@@ -236,62 +2460,74 @@ fn encrypt_file_with_public_key(
 /// # Arguments
 ///
 /// * `encrypted_path` - 暗号化されたZIPファイルのパス。
-/// * `private_key_path` - 復号に使用する秘密鍵ファイルのパス。
+/// * `private_key_source` - 復号に使用する秘密鍵の取得元。
+/// * `pb` - 読み込んだバイト数だけ進める進捗バー。
+/// * `stats` - 復号に使用する秘密鍵のビット数とフィンガープリントを記録する統計情報。
 ///
 /// # Errors
 ///
 /// ファイルの読み込み、秘密鍵のパース、暗号化・復号の各工程で失敗した場合にエラーを返します。
 fn decrypt_zip_with_rsa(
     encrypted_path: &Path,
-    private_key_path: &Path,
+    private_key_source: &PrivateKeySource,
+    pb: &progress::ProgressBar,
+    stats: &mut ArchiveStats,
 ) -> Result<Vec<u8>> {
     let mut encrypted_data = Vec::new();
-    File::open(encrypted_path)?.read_to_end(&mut encrypted_data)?;
-
-    // 秘密鍵の読み込み
-    let private_key_pem = fs::read_to_string(private_key_path)?;
-    let private_key = RsaPrivateKey::from_pkcs8_pem(&private_key_pem)?;
-
-    // 先頭からNonce（12バイト）を取得
-    let nonce = extract_nonce(&encrypted_data)?;
-
-    // RSAで暗号化されたAES鍵のサイズを取得
-    let key_size = u16::from_be_bytes([encrypted_data[12], encrypted_data[13]]) as usize;
-    let encrypted_key = &encrypted_data[14..14 + key_size];
+    progress::wrap_read(pb, File::open(encrypted_path)?).read_to_end(&mut encrypted_data)?;
 
-    // AES鍵の復号
-    let aes_key_bytes = private_key.decrypt(Pkcs1v15Encrypt, encrypted_key)?;
-    let aes_key: GenericArray<u8, U32> = GenericArray::clone_from_slice(&aes_key_bytes);
+    debug!("Read {} bytes from {}", encrypted_data.len(), encrypted_path.display());
 
-    // 残りの部分がAES-GCMで暗号化されたZIPデータ
-    let encrypted_zip = &encrypted_data[14 + key_size..];
+    // 秘密鍵の読み込み
+    let started = Instant::now();
+    let private_key = &*private_key_source.load()?;
+    debug!("Loaded private key {} in {:?}", private_key_source, started.elapsed());
+    stats.key_bits = Some(key_bits(private_key));
+    stats.key_fingerprint = Some(private_key_fingerprint(private_key)?);
 
-    // AES-GCMで復号
-    let cipher = Aes256Gcm::new_from_slice(&aes_key)?;
-    let decrypted_zip = cipher.decrypt(&nonce, encrypted_zip)
-        .map_err(|e| anyhow!("Decyption failed: {}", e.to_string()))?;
+    let started = Instant::now();
+    let zip_data = decrypt_with_private_key(&encrypted_data, private_key)?;
+    debug!("Decrypted {} bytes of ZIP data in {:?}", zip_data.len(), started.elapsed());
+    Ok(zip_data)
+}
 
-    Ok(decrypted_zip)
+/// `.acrp` 形式のバイト列を、RSA で復号済みの AES 鍵で復号し、ZIPデータを返します.
+///
+/// RSA による AES 鍵の復号を行わないため、HSM や鍵エスクローなど外部の仕組みで AES 鍵を復号する場合に使用します。
+/// AES 鍵の取得は呼び出し側の責任です。ヘッダと Nonce は通常の `.acrp` 形式から読み取り、
+/// ASCII アーマー形式のデータはデコードしてから復号します。
+///
+/// # Arguments
+///
+/// * `encrypted_data` - `.acrp` 形式のバイト列。
+/// * `aes_key` - アーカイブの暗号化に使用された AES-256 の鍵。
+///
+/// # Errors
+///
+/// データの形式が不正な場合、または AES-GCM の認証に失敗した場合（鍵の誤りを含む）に、原因を説明するエラーを返します。
+pub fn decrypt_zip_with_aes_key(encrypted_data: &[u8], aes_key: &[u8; 32]) -> Result<Vec<u8>> {
+    diagnostics::decrypt_with_aes_key(encrypted_data, aes_key).into_result()
 }
 
-/// 暗号化されたデータから、最初の12バイトをNonceとして取得します.
+/// `.acrp` 形式のバイト列を秘密鍵で復号し、ZIPデータを返します.
 ///
 /// # Arguments
 ///
-/// * `encrypted_data` - 暗号化されたデータのバイトスライス。
+/// * `encrypted_data` - `.acrp` 形式のバイト列。
+/// * `private_key` - 復号に使用する秘密鍵。
 ///
 /// # Errors
 ///
-/// データの長さが12バイト未満の場合にエラーを返します。
-fn extract_nonce(encrypted_data: &[u8]) -> Result<Nonce<U12>> {
-    let nonce_slice = encrypted_data
-        .get(0..12)
-        .ok_or_else(|| anyhow!("暗号化データが短すぎます。Nonceを取得できません。"))?;
-    Ok(Nonce::<U12>::clone_from_slice(nonce_slice))
+/// データの形式が不正な場合、または復号に失敗した場合に、原因を説明するエラーを返します。
+fn decrypt_with_private_key(encrypted_data: &[u8], private_key: &RsaPrivateKey) -> Result<Vec<u8>> {
+    // 失敗した場合は原因（鍵の不一致、ヘッダの破損、改ざんなど）に応じたメッセージのエラーにする
+    diagnostics::diagnose_decrypt(encrypted_data, private_key).into_result()
 }
 
 /// 指定されたパスの拡張子が、定数 EXTENTION で指定された文字列と一致するかをチェックします.
 ///
+/// 比較は ASCII の大文字・小文字を区別せずに行います（`archive.ACRP` も有効）。
+///
 /// # Arguments
 ///
 /// * `check_path` - 拡張子を検証する対象のパス。
@@ -300,62 +2536,297 @@ fn extract_nonce(encrypted_data: &[u8]) -> Result<Nonce<U12>> {
 ///
 /// 拡張子が一致すれば Ok(true)、一致しなければ Ok(false) を返します。
 fn validate_extension(check_path: &Path) -> Result<bool> {
-    let ext = check_path
+    Ok(check_path
         .extension()
-        .and_then(|ext| ext.to_string_lossy().to_lowercase().into());
-    // ここでは to_string_lossy() を利用して安全に文字列変換
-    if check_path.extension().and_then(|ext| Some(ext.to_string_lossy().to_string())) != Some(EXTENTION.to_string()) {
-        Ok(false)
-    } else {
-        Ok(true)
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(EXTENTION)))
+}
+
+/// 圧縮対象のパスが空でないこと、およびパス同士が重複していないことを検証します.
+///
+/// 各パスを `canonicalize` した上で、あるパスが別の対象ディレクトリの配下にある場合や、
+/// シンボリックリンク等により同じ実体を指している場合にエラーを返します。
+///
+/// # Arguments
+///
+/// * `target_pathes` - 圧縮対象となるファイルまたはディレクトリのパスのリスト。
+/// * `opts` - 圧縮オプション。`allow_empty` が true の場合は空のリストを許可します。
+///
+/// # Errors
+///
+/// * 対象パスが空の場合（`ArchryptoError::NoTargetPaths`）。
+/// * パスの正規化に失敗した場合。
+/// * 重複が見つかった場合、最初に見つかった組について
+///   `ArchryptoError::OverlappingPaths` または `ArchryptoError::DuplicatePaths` を返します。
+fn validate_target_paths(target_pathes: &[PathBuf], opts: &CompressOptions) -> Result<()> {
+    if target_pathes.is_empty() && !opts.allow_empty {
+        return Err(ArchryptoError::NoTargetPaths.into());
+    }
+    let mut canonical_pathes = target_pathes
+        .iter()
+        .map(|path| Ok((canonicalize(path)?, path)))
+        .collect::<Result<Vec<_>>>()?;
+    // 短いパス（祖先になり得るもの）から順に比較する
+    canonical_pathes.sort_by_key(|(canonical, _)| canonical.components().count());
+
+    for (i, (child, child_path)) in canonical_pathes.iter().enumerate() {
+        for (parent, parent_path) in &canonical_pathes[..i] {
+            if child == parent {
+                return Err(ArchryptoError::DuplicatePaths {
+                    first: parent_path.to_path_buf(),
+                    second: child_path.to_path_buf(),
+                }
+                .into());
+            }
+            if child.starts_with(parent) {
+                return Err(ArchryptoError::OverlappingPaths {
+                    parent: parent_path.to_path_buf(),
+                    child: child_path.to_path_buf(),
+                }
+                .into());
+            }
+        }
     }
+    Ok(())
 }
 
-/// 指定されたパス配下の全てのファイル数を再帰的にカウントして返します.
+/// リーダーから1行に1つずつパスを読み取るイテレータを作成します.
+///
+/// 空行と `#` で始まる行は読み飛ばします。`null_terminated` が true の場合は
+/// 改行ではなく NUL 文字（`find -print0` の出力形式）で区切り、空の要素のみを読み飛ばします。
 ///
 /// # Arguments
 ///
-/// * `path` - カウント対象のディレクトリまたはファイルのパス。
+/// * `reader` - パスのリストを読み取るリーダー（標準入力など）。
+/// * `null_terminated` - true の場合、NUL 文字区切りとして読み取ります。
 ///
-/// # Returns
+/// # Errors
+///
+/// イテレータの各要素は、読み込みに失敗した場合にエラーになります。
+pub fn read_paths_from_reader(reader: impl BufRead, null_terminated: bool) -> impl Iterator<Item = Result<PathBuf>> {
+    let delimiter = if null_terminated { b'\0' } else { b'\n' };
+    reader.split(delimiter).filter_map(move |item| {
+        let mut bytes = match item {
+            std::result::Result::Ok(bytes) => bytes,
+            Err(e) => return Some(Err(e.into())),
+        };
+        if !null_terminated {
+            if bytes.last() == Some(&b'\r') {
+                bytes.pop();
+            }
+            if bytes.first() == Some(&b'#') {
+                return None;
+            }
+        }
+        if bytes.is_empty() {
+            return None;
+        }
+        Some(path_from_bytes(bytes))
+    })
+}
+
+/// バイト列をパスに変換します. Unix 以外では UTF-8 として解釈できない場合にエラーを返します。
+fn path_from_bytes(bytes: Vec<u8>) -> Result<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(PathBuf::from(String::from_utf8(bytes)?))
+    }
+}
+
+/// 圧縮オプションに従って、指定されたディレクトリ配下のエントリを走査するイテレータを作成します.
+///
+/// 隠しファイルや `.gitignore`、`.archryptignore` の扱いは `ignore` クレートの `WalkBuilder` に委ねます。
+/// `opts.target_overrides` に `path` と一致する対象があれば、その最大の深さと除外パターンを適用します。
+///
+/// # Arguments
 ///
-/// パス配下に存在する全てのファイル数を返します。ディレクトリの場合は再帰的にカウントします。
+/// * `path` - 走査するディレクトリのパス。
+/// * `opts` - 圧縮オプション。
 ///
 /// # Errors
 ///
-/// ファイルシステムの読み込みに失敗した場合にエラーを返します。
-fn count_files(path: &Path) -> Result<usize> {
-    let mut count = 0;
-    for entry in WalkDir::new(path) {
-        let entry = entry?;
-        if entry.file_type().is_file() {
-            count += 1;
+/// グローバルな除外ルールファイルの読み込みや、除外パターンの解析に失敗した場合にエラーを返します。
+fn walk_dir(path: &Path, opts: &CompressOptions) -> Result<impl Iterator<Item = Result<DirEntry>>> {
+    let target_override = opts.target_overrides.iter().find(|target| target.path == path);
+    let max_depth = target_override.and_then(|target| target.max_depth).or(opts.max_depth);
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .standard_filters(false)
+        .follow_links(opts.follow_symlinks)
+        .max_depth(max_depth)
+        .hidden(!opts.include_hidden)
+        .parents(opts.use_gitignore)
+        .git_ignore(opts.use_gitignore)
+        .git_global(opts.use_gitignore)
+        .git_exclude(opts.use_gitignore)
+        .require_git(false);
+    if opts.use_archryptignore {
+        builder
+            .add_custom_ignore_filename(IGNORE_FILE_NAME)
+            .filter_entry(|entry| entry.file_name() != IGNORE_FILE_NAME);
+    }
+    if let Some(global_ignore_file) = &opts.global_ignore_file {
+        if let Some(e) = builder.add_ignore(global_ignore_file) {
+            return Err(e.into());
         }
     }
-    Ok(count)
+    if let Some(target_override) = target_override.filter(|target| !target.exclude_patterns.is_empty()) {
+        let mut overrides = OverrideBuilder::new(path);
+        for pattern in &target_override.exclude_patterns {
+            overrides.add(&format!("!{}", pattern))?;
+        }
+        builder.overrides(overrides.build()?);
+    }
+    let min_depth = opts.min_depth.unwrap_or(0);
+    Ok(builder
+        .build()
+        .filter(move |entry| entry.as_ref().map_or(true, |entry| entry.depth() >= min_depth))
+        .map(|entry| entry.map_err(anyhow::Error::from)))
+}
+
+/// 走査したエントリが通常のファイルかどうかを返します.
+fn is_file_entry(entry: &DirEntry) -> bool {
+    entry.file_type().is_some_and(|file_type| file_type.is_file())
+}
+
+/// ファイルの実体を訪問済みとして記録し、初めての訪問であれば true を返します.
+///
+/// パスは `canonicalize` した上で比較するため、シンボリックリンク等による別経路も同一とみなします。
+///
+/// # Errors
+///
+/// パスの正規化に失敗した場合にエラーを返します。
+fn mark_visited(visited: &mut HashSet<PathBuf>, path: &Path) -> Result<bool> {
+    Ok(visited.insert(canonicalize(path)?))
 }
 
-/// 複数の PathBuf に対して、各パス内のファイル数の総計を返します.
+/// 指定されたパス配下の全てのファイルの合計バイト数を再帰的に求めて返します.
+///
+/// `visited` に記録済みのファイルは含めません。
 ///
 /// # Arguments
 ///
-/// * `paths` - カウント対象の複数のパスのスライス。
+/// * `path` - 対象のディレクトリまたはファイルのパス。
+/// * `opts` - 走査方法を決める圧縮オプション。
+/// * `visited` - 集計済みのファイルの実体のパス。
 ///
 /// # Returns
 ///
-/// 指定された全てのパス内のファイル数の総計を返します。
+/// パス配下に存在する全てのファイルの合計バイト数を返します。ディレクトリの場合は再帰的に集計します。
+///
+/// # Errors
+///
+/// ファイルシステムの読み込みに失敗した場合にエラーを返します。
+fn count_bytes(path: &Path, opts: &CompressOptions, visited: &mut HashSet<PathBuf>) -> Result<u64> {
+    let mut total = 0;
+    for entry in walk_dir(path, opts)? {
+        let entry = entry?;
+        if is_file_entry(&entry) && mark_visited(visited, entry.path())? {
+            total += entry.metadata()?.len();
+        }
+    }
+    Ok(total)
+}
+
+/// 複数の PathBuf に対して、各パス内のファイルの合計バイト数の総計を返します.
+///
+/// 複数の経路から到達できる同じファイルは1件として数えます。
+///
+/// # Arguments
+///
+/// * `paths` - 対象の複数のパスのスライス。
+/// * `opts` - 走査方法を決める圧縮オプション。
 ///
 /// # Errors
 ///
-/// いずれかのパスでファイル数のカウントに失敗した場合、エラーを返します。
-fn count_files_in_paths(paths: &[PathBuf]) -> Result<usize> {
+/// いずれかのパスで集計に失敗した場合、エラーを返します。
+fn count_bytes_in_paths(paths: &[PathBuf], opts: &CompressOptions) -> Result<u64> {
     let mut total = 0;
+    let mut visited = HashSet::new();
     for path in paths {
-        total += count_files(path)?;
+        total += count_bytes(path, opts, &mut visited)?;
     }
     Ok(total)
 }
 
+/// 指定されたパス配下のファイル数（ディレクトリを除く）を返します.
+///
+/// GUI などで圧縮前に「N 個のファイルを圧縮します」と表示するためのもので、
+/// デフォルトの `CompressOptions` で `compress_files` が格納するファイルを数えます。
+///
+/// # Arguments
+///
+/// * `path` - 対象のディレクトリまたはファイルのパス。
+///
+/// # Errors
+///
+/// ファイルシステムの読み込みに失敗した場合にエラーを返します。
+pub fn count_files(path: &Path) -> Result<usize> {
+    count_files_in_paths(&[path.to_path_buf()])
+}
+
+/// 複数のパス配下のファイル数（ディレクトリを除く）の総計を返します.
+///
+/// 複数の経路から到達できる同じファイルは1件として数えます。
+///
+/// # Arguments
+///
+/// * `paths` - 対象の複数のパスのスライス。
+///
+/// # Errors
+///
+/// いずれかのパスで集計に失敗した場合、エラーを返します。
+pub fn count_files_in_paths(paths: &[PathBuf]) -> Result<usize> {
+    let opts = CompressOptions::default();
+    let mut count = 0;
+    let mut visited = HashSet::new();
+    for path in paths {
+        for entry in walk_dir(path, &opts)? {
+            let entry = entry?;
+            if is_file_entry(&entry) && mark_visited(&mut visited, entry.path())? {
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// 複数のパス配下のファイルの合計バイト数（非圧縮時）を返します.
+///
+/// 圧縮後のサイズはデータに依存するため、上限の目安として非圧縮時の合計を返します。
+/// デフォルトの `CompressOptions` で `compress_files` が格納するファイルを対象とし、
+/// 圧縮後の `ArchiveStats::total_bytes` と同じ値になります。
+///
+/// # Arguments
+///
+/// * `paths` - 対象の複数のパスのスライス。
+///
+/// # Errors
+///
+/// いずれかのパスで集計に失敗した場合、エラーを返します。
+pub fn estimate_compressed_size(paths: &[PathBuf]) -> Result<u64> {
+    count_bytes_in_paths(paths, &CompressOptions::default())
+}
+
+/// 進捗バーの総数として使用する、圧縮対象の合計バイト数を返します.
+///
+/// `opts.max_entries` が指定されている場合は、対象の走査を二重に行わないよう 0 を返します。
+/// この場合、進捗バーの総数は各ファイルを開くたびに増やします。
+///
+/// # Errors
+///
+/// 合計バイト数の集計に失敗した場合、エラーを返します。
+fn expected_total_bytes(paths: &[PathBuf], opts: &CompressOptions) -> Result<u64> {
+    match opts.max_entries {
+        Some(_) => Ok(0),
+        None => count_bytes_in_paths(paths, opts),
+    }
+}
+
 /// 指定されたZIPファイル内のファイル数（ディレクトリを除く）をカウントして返します.
 ///
 /// # Arguments
@@ -383,3 +2854,580 @@ fn count_files_in_zip(zip_path: &NamedTempFile) -> Result<usize> {
     }
     Ok(count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_entry_name_maps_drive_prefix_to_directory() {
+        assert_eq!(sanitize_entry_name("C:\\evil.exe").unwrap(), PathBuf::from("C/evil.exe"));
+        assert_eq!(sanitize_entry_name("c:/Windows/system32/evil.dll").unwrap(), PathBuf::from("c/Windows/system32/evil.dll"));
+    }
+
+    #[test]
+    fn sanitize_entry_name_treats_backslash_as_separator() {
+        assert_eq!(sanitize_entry_name("dir\\sub\\file.txt").unwrap(), PathBuf::from("dir/sub/file.txt"));
+        assert_eq!(sanitize_entry_name("dir\\sub/file.txt").unwrap(), PathBuf::from("dir/sub/file.txt"));
+    }
+
+    #[test]
+    fn sanitize_entry_name_resolves_parent_components() {
+        assert_eq!(sanitize_entry_name("a/b/../c.txt").unwrap(), PathBuf::from("a/c.txt"));
+        assert_eq!(sanitize_entry_name("a/./b/./c.txt").unwrap(), PathBuf::from("a/b/c.txt"));
+        for name in ["../evil.txt", "a/../../evil.txt", "..\\evil.txt", "C:\\..\\..\\evil.txt"] {
+            let err = sanitize_entry_name(name).unwrap_err();
+            assert!(
+                matches!(err.downcast_ref::<ArchryptoError>(), Some(ArchryptoError::UnsafeEntryPath { .. })),
+                "{} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn sanitize_entry_name_strips_absolute_prefixes() {
+        assert_eq!(sanitize_entry_name("/etc/passwd").unwrap(), PathBuf::from("etc/passwd"));
+        assert_eq!(sanitize_entry_name("//server/share/file").unwrap(), PathBuf::from("server/share/file"));
+        assert_eq!(sanitize_entry_name("\\\\server\\share\\file").unwrap(), PathBuf::from("server/share/file"));
+        assert!(sanitize_entry_name("/etc/passwd").unwrap().is_relative());
+    }
+
+    #[test]
+    fn validate_extension_ignores_ascii_case() {
+        assert!(validate_extension(Path::new("archive.acrp")).unwrap());
+        assert!(validate_extension(Path::new("archive.ACRP")).unwrap());
+        assert!(validate_extension(Path::new("dir/archive.Acrp")).unwrap());
+    }
+
+    #[test]
+    fn validate_extension_rejects_missing_or_wrong_extension() {
+        assert!(!validate_extension(Path::new("archive")).unwrap());
+        assert!(!validate_extension(Path::new("archive.zip")).unwrap());
+        assert!(!validate_extension(Path::new("archive.acrp.zip")).unwrap());
+        assert!(!validate_extension(Path::new(".acrp")).unwrap());
+    }
+
+    /// `root/a.txt`（深さ1）から `root/d1/d2/d3/d.txt`（深さ4）までの入れ子のディレクトリを作成します。
+    fn nested_tree() -> tempfile::TempDir {
+        let dir = tempfile::Builder::new().prefix("walk").tempdir().unwrap();
+        let deepest = dir.path().join("d1").join("d2").join("d3");
+        fs::create_dir_all(&deepest).unwrap();
+        fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        fs::write(dir.path().join("d1").join("b.txt"), b"b").unwrap();
+        fs::write(dir.path().join("d1").join("d2").join("c.txt"), b"c").unwrap();
+        fs::write(deepest.join("d.txt"), b"d").unwrap();
+        dir
+    }
+
+    /// `walk_dir` で見つかったファイルの名前を、ソートして返します。
+    fn walked_file_names(root: &Path, opts: &CompressOptions) -> Vec<String> {
+        let mut names: Vec<String> = walk_dir(root, opts)
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(is_file_entry)
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn walk_dir_without_depth_limits_finds_all_files() {
+        let dir = nested_tree();
+        assert_eq!(walked_file_names(dir.path(), &CompressOptions::default()), ["a.txt", "b.txt", "c.txt", "d.txt"]);
+    }
+
+    #[test]
+    fn walk_dir_skips_files_deeper_than_max_depth() {
+        let dir = nested_tree();
+        let opts = CompressOptions { max_depth: Some(2), ..CompressOptions::default() };
+        assert_eq!(walked_file_names(dir.path(), &opts), ["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn walk_dir_skips_files_shallower_than_min_depth() {
+        let dir = nested_tree();
+        let opts = CompressOptions { min_depth: Some(3), ..CompressOptions::default() };
+        assert_eq!(walked_file_names(dir.path(), &opts), ["c.txt", "d.txt"]);
+        let opts = CompressOptions { min_depth: Some(2), max_depth: Some(3), ..CompressOptions::default() };
+        assert_eq!(walked_file_names(dir.path(), &opts), ["b.txt", "c.txt"]);
+    }
+
+    /// `extract_bytes` で取り出したエントリを、先頭のディレクトリ名を除いた名前でソートして返します。
+    fn extracted_entries(archive: &[u8], private_key: &RsaPrivateKey) -> Vec<(String, Vec<u8>)> {
+        let mut entries: Vec<(String, Vec<u8>)> = extract_bytes(&armor::dearmor(archive).unwrap(), private_key)
+            .unwrap()
+            .into_iter()
+            .map(|(name, data)| (name.split_once('/').map_or(name.clone(), |(_, rest)| rest.to_string()), data))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    fn nested_tree_entries() -> Vec<(String, Vec<u8>)> {
+        [("a.txt", b"a"), ("d1/b.txt", b"b"), ("d1/d2/c.txt", b"c"), ("d1/d2/d3/d.txt", b"d")]
+            .into_iter()
+            .map(|(name, data)| (name.to_string(), data.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn compress_to_writer_round_trips_in_every_output_format() {
+        let dir = nested_tree();
+        let (public_key, private_key) = test_utils::generate_insecure_test_keypair();
+        for (output_format, use_temp_file) in [
+            (OutputFormat::Binary, true),
+            (OutputFormat::Binary, false),
+            (OutputFormat::Base64Armored, true),
+            (OutputFormat::Base64Armored, false),
+        ] {
+            let opts = CompressOptions { output_format, use_temp_file, ..CompressOptions::for_testing() };
+            let mut archive = Vec::new();
+            let stats = compress_to_writer(&mut archive, &public_key, &[dir.path().to_path_buf()], &opts).unwrap();
+            assert_eq!(stats.files_written, 4);
+            assert_eq!(stats.key_bits, Some(512));
+            assert_eq!(stats.key_fingerprint, Some(public_key_fingerprint(&public_key).unwrap()));
+            assert_eq!(armor::is_armored(&archive), output_format == OutputFormat::Base64Armored);
+            assert_eq!(extracted_entries(&archive, &private_key), nested_tree_entries(), "{:?}", output_format);
+        }
+    }
+
+    #[test]
+    fn streamed_zip_round_trips_with_deduplication_and_embedded_manifest() {
+        let dir = nested_tree();
+        fs::write(dir.path().join("d1").join("copy.txt"), b"a").unwrap();
+        let (public_key, private_key) = test_utils::generate_insecure_test_keypair();
+        let opts = CompressOptions {
+            use_temp_file: false,
+            deduplicate: true,
+            embed_manifest: true,
+            sort_entries: true,
+            ..CompressOptions::for_testing()
+        };
+        let mut archive = Vec::new();
+        compress_to_writer(&mut archive, &public_key, &[dir.path().to_path_buf()], &opts).unwrap();
+        let mut expected = nested_tree_entries();
+        expected.push(("d1/copy.txt".to_string(), b"a".to_vec()));
+        expected.sort();
+        let entries: Vec<_> = extracted_entries(&archive, &private_key)
+            .into_iter()
+            .filter(|(name, _)| name != EMBEDDED_MANIFEST_NAME)
+            .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn compress_to_writer_embeds_input_checksum_in_header() {
+        let dir = nested_tree();
+        let (public_key, private_key) = test_utils::generate_insecure_test_keypair();
+        let opts = CompressOptions { embed_input_checksum: true, ..CompressOptions::for_testing() };
+        let mut archive = Vec::new();
+        compress_to_writer(&mut archive, &public_key, &[dir.path().to_path_buf()], &opts).unwrap();
+        let (header, _) = ArchiveHeader::parse(&archive).unwrap();
+        let zip_data = decrypt_with_private_key(&archive, &private_key).unwrap();
+        assert_eq!(header.input_checksum, Some(tree_checksum::zip_tree_checksum(io::Cursor::new(zip_data)).unwrap()));
+    }
+
+    #[test]
+    fn compress_files_names_output_after_content_hash() {
+        let dir = nested_tree();
+        let out = tempfile::tempdir().unwrap();
+        let (public_key, private_key) = test_utils::generate_insecure_test_keypair();
+        let public_key_path = out.path().join("public.pem");
+        save_public_key(&public_key, &public_key_path).unwrap();
+        let opts = CompressOptions { output_naming: OutputNaming::ContentHash, ..CompressOptions::for_testing() };
+        let stats = compress_files(out.path().join("ignored.acrp"), &public_key_path, &[dir.path()], &opts).unwrap();
+
+        let output_path = stats.output_path.unwrap();
+        let archive = fs::read(&output_path).unwrap();
+        let expected_name = format!("{}.{}", &manifest::to_hex(&Sha256::digest(&archive))[..8], EXTENTION);
+        assert_eq!(output_path.file_name().unwrap().to_string_lossy(), expected_name);
+        assert!(!out.path().join("ignored.acrp").exists());
+        assert_eq!(extracted_entries(&archive, &private_key), nested_tree_entries());
+        // 一時ファイルは出力ファイルの名前に変更されているため、出力先には公開鍵とアーカイブのみが残る
+        assert_eq!(fs::read_dir(out.path()).unwrap().count(), 2);
+    }
+
+    /// ZIPの一時ファイルに格納されたエントリを、先頭のディレクトリ名を除いた名前でソートして返します。
+    fn zip_entries(zip: &NamedTempFile) -> Vec<(String, Vec<u8>)> {
+        let mut archive = ZipArchive::new(zip.reopen().unwrap()).unwrap();
+        let mut entries = Vec::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).unwrap();
+            let name = file.name().split_once('/').map_or(file.name().to_string(), |(_, rest)| rest.to_string());
+            entries.push((name, data));
+        }
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn build_zip_resumes_from_journal_after_interruption() {
+        let dir = nested_tree();
+        let journal_dir = tempfile::tempdir().unwrap();
+        let output = journal_dir.path().join("out.acrp");
+        let targets = [dir.path().to_path_buf()];
+        let pb = progress::new_bytes_progress_bar(0, None, None);
+
+        // 2ファイルを格納したところで中断させる
+        let interrupted = CompressOptions { max_entries: Some(2), detect_content_type: true, ..CompressOptions::for_testing() };
+        let mut journal = Journal::open_in(journal_dir.path(), &output).unwrap();
+        assert!(build_zip(&targets, &interrupted, &pb, Some(&mut journal)).is_err());
+        drop(journal);
+
+        let opts = CompressOptions { detect_content_type: true, emit_manifest: true, ..CompressOptions::for_testing() };
+        let mut journal = Journal::open_in(journal_dir.path(), &output).unwrap();
+        assert_eq!(journal.len(), 2);
+        let (zip, stats) = build_zip(&targets, &opts, &pb, Some(&mut journal)).unwrap();
+        assert_eq!(stats.files_resumed, 2);
+        assert_eq!(stats.files_written, 4);
+        assert_eq!(zip_entries(&zip), nested_tree_entries());
+        // 再利用したエントリも、新たに格納したエントリと同じくマニフェストに記録される
+        let manifest = stats.manifest.unwrap();
+        assert_eq!(manifest.entries().len(), 4);
+        assert!(manifest.entries().iter().all(|entry| entry.sha256.len() == 64));
+
+        // 変更したファイルは記録済みでも格納し直す
+        fs::write(dir.path().join("a.txt"), b"changed").unwrap();
+        let mut journal = Journal::open_in(journal_dir.path(), &output).unwrap();
+        let (zip, stats) = build_zip(&targets, &opts, &pb, Some(&mut journal)).unwrap();
+        assert_eq!(stats.files_resumed, 3);
+        assert!(zip_entries(&zip).contains(&("a.txt".to_string(), b"changed".to_vec())));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_pathbuf_shims_round_trip() {
+        let dir = nested_tree();
+        let work = tempfile::tempdir().unwrap();
+        let (public_key, private_key) = test_utils::generate_test_keypair();
+        let public_key_path = work.path().join("public.pem");
+        let private_key_path = work.path().join("private.pem");
+        save_public_key(&public_key, &public_key_path).unwrap();
+        save_private_key(&private_key, &private_key_path).unwrap();
+        let archive = work.path().join("archive.acrp");
+        let extracted = work.path().join("extracted");
+
+        compress_files_pathbuf(&archive, &public_key_path, &[dir.path().to_path_buf()]).unwrap();
+        extract_files_pathbuf(&archive, &private_key_path, &extracted).unwrap();
+        let base_name = dir.path().file_name().unwrap();
+        assert_eq!(fs::read(extracted.join(base_name).join("d1").join("d2").join("d3").join("d.txt")).unwrap(), b"d");
+        assert_eq!(extracted_entries(&fs::read(&archive).unwrap(), &private_key), nested_tree_entries());
+    }
+
+    #[test]
+    fn pipeline_encrypt_round_trips_with_compress_options() {
+        let dir = nested_tree();
+        let work = tempfile::tempdir().unwrap();
+        let (public_key, private_key) = test_utils::generate_insecure_test_keypair();
+        let public_key_path = work.path().join("public.pem");
+        save_public_key(&public_key, &public_key_path).unwrap();
+        let archive = work.path().join("archive.acrp");
+
+        let opts = CompressOptions { retry_count: 2, embed_input_checksum: true, ..CompressOptions::for_testing() };
+        pipeline::Pipeline::builder()
+            .step(pipeline::Compress { opts })
+            .step(pipeline::Encrypt { public_key: public_key_path, output: archive.clone() })
+            .build()
+            .run(&[dir.path().to_path_buf()])
+            .unwrap();
+        let data = fs::read(&archive).unwrap();
+        let (header, _) = ArchiveHeader::parse(&data).unwrap();
+        assert!(header.input_checksum.is_some());
+        assert_eq!(extracted_entries(&data, &private_key), nested_tree_entries());
+    }
+
+    #[test]
+    fn header_round_trips_and_is_authenticated_as_aad() {
+        let dir = nested_tree();
+        let (public_key, private_key) = test_utils::generate_insecure_test_keypair();
+        let opts = CompressOptions {
+            comment: Some("weekly".to_string()),
+            creator: Some("backup.sh".to_string()),
+            ..CompressOptions::for_testing()
+        };
+        let mut archive = Vec::new();
+        compress_to_writer(&mut archive, &public_key, &[dir.path().to_path_buf()], &opts).unwrap();
+
+        let (header, header_len) = ArchiveHeader::parse(&archive).unwrap();
+        assert_eq!(header.version, header::CURRENT_VERSION);
+        assert_eq!(header.comment.as_deref(), Some("weekly"));
+        assert_eq!(header.creator.as_deref(), Some("backup.sh"));
+        assert_eq!(header.recipient_key_id, Some(public_key_fingerprint(&public_key).unwrap()));
+        assert_eq!(header.encode().len(), header_len);
+        assert_eq!(extracted_entries(&archive, &private_key), nested_tree_entries());
+
+        // ヘッダは平文だが AAD として認証されるため、1バイトでも書き換えると復号に失敗する
+        let mut tampered = archive.clone();
+        let comment_at = tampered.windows(6).position(|window| window == b"weekly").unwrap();
+        tampered[comment_at] = b'W';
+        assert_eq!(ArchiveHeader::parse(&tampered).unwrap().0.comment.as_deref(), Some("Weekly"));
+        assert!(extract_bytes(&tampered, &private_key).is_err());
+    }
+
+    /// `validate_archive_structure` を、一時ファイルに書き出した `data` に対して実行します。
+    fn validate_bytes(data: &[u8]) -> Result<()> {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), data).unwrap();
+        validate_archive_structure(file.path())
+    }
+
+    fn structure_error(data: &[u8]) -> ArchryptoError {
+        validate_bytes(data).unwrap_err().downcast::<ArchryptoError>().unwrap()
+    }
+
+    #[test]
+    fn validate_archive_structure_accepts_valid_archives() {
+        let dir = nested_tree();
+        let (public_key, _) = test_utils::generate_test_keypair();
+        for output_format in [OutputFormat::Binary, OutputFormat::Base64Armored] {
+            let opts = CompressOptions { output_format, ..CompressOptions::for_testing() };
+            let mut archive = Vec::new();
+            compress_to_writer(&mut archive, &public_key, &[dir.path().to_path_buf()], &opts).unwrap();
+            validate_bytes(&archive).unwrap();
+        }
+    }
+
+    #[test]
+    fn validate_archive_structure_detects_corruption() {
+        let dir = nested_tree();
+        let (public_key, _) = test_utils::generate_test_keypair();
+        let mut archive = Vec::new();
+        compress_to_writer(&mut archive, &public_key, &[dir.path().to_path_buf()], &CompressOptions::for_testing()).unwrap();
+        let (_, header_len) = ArchiveHeader::parse(&archive).unwrap();
+
+        // ヘッダを持たない旧形式（バージョン 0）も Nonce から始まるものとして受け付ける
+        validate_bytes(&archive[header_len..]).unwrap();
+
+        let mut newer = archive.clone();
+        newer[4..6].copy_from_slice(&(header::CURRENT_VERSION + 1).to_be_bytes());
+        assert!(matches!(structure_error(&newer), ArchryptoError::UnsupportedVersion { version } if version == header::CURRENT_VERSION + 1));
+
+        let mut corrupted_header = archive.clone();
+        corrupted_header[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(structure_error(&corrupted_header), ArchryptoError::CorruptedHeader { .. }));
+
+        let mut bad_key_size = archive.clone();
+        bad_key_size[header_len + 12..header_len + 14].copy_from_slice(&5u16.to_be_bytes());
+        assert!(matches!(structure_error(&bad_key_size), ArchryptoError::ImplausibleKeySize { key_size: 5 }));
+
+        let key_size = u16::from_be_bytes([archive[header_len + 12], archive[header_len + 13]]) as u64;
+        let truncated = &archive[..header_len + 14 + key_size as usize];
+        assert!(matches!(
+            structure_error(truncated),
+            ArchryptoError::TruncatedArchive { len, min } if len == truncated.len() as u64 && min == header_len as u64 + 14 + key_size + AES_GCM_TAG_LEN
+        ));
+        assert!(matches!(structure_error(&archive[..header_len + 5]), ArchryptoError::TruncatedArchive { .. }));
+    }
+
+    /// 鍵ペアを `work` に保存し、`nested_tree` を `emit_manifest` 付きで圧縮します。
+    /// アーカイブ、マニフェストJSON、秘密鍵のパスを返します。
+    fn compress_with_manifest_json(work: &Path) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = nested_tree();
+        let (public_key, private_key) = test_utils::generate_test_keypair();
+        let public_key_path = work.join("public.pem");
+        let private_key_path = work.join("private.pem");
+        save_public_key(&public_key, &public_key_path).unwrap();
+        save_private_key(&private_key, &private_key_path).unwrap();
+        let archive = work.join("archive.acrp");
+        let opts = CompressOptions { emit_manifest: true, ..CompressOptions::for_testing() };
+        compress_files(&archive, &public_key_path, &[dir.path()], &opts).unwrap();
+        let manifest = manifest_json_path(&archive);
+        assert_eq!(manifest::read_manifest_json(&manifest).unwrap().entries.len(), 4);
+        (archive, manifest, private_key_path)
+    }
+
+    /// マニフェストJSONを読み込み、`edit` で書き換えて保存し直します。
+    fn edit_manifest_json(manifest: &Path, edit: impl FnOnce(&mut ManifestJson)) {
+        let mut json = manifest::read_manifest_json(manifest).unwrap();
+        edit(&mut json);
+        fs::write(manifest, serde_json::to_string(&json).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn extract_files_verifies_manifest_json_before_extracting() {
+        let work = tempfile::tempdir().unwrap();
+        let (archive, manifest, private_key_path) = compress_with_manifest_json(work.path());
+        let opts = ExtractOptions { verify_manifest: Some(manifest.clone()), ..Default::default() };
+
+        let extracted = work.path().join("extracted");
+        let stats = extract_files(&archive, &private_key_path, &extracted, &opts).unwrap();
+        assert_eq!(stats.files_written, 4);
+
+        let mut tampered_entry = String::new();
+        edit_manifest_json(&manifest, |json| {
+            tampered_entry = json.entries[0].path.clone();
+            json.entries[0].sha256 = "0".repeat(64);
+        });
+        let rejected = work.path().join("rejected");
+        let err = extract_files(&archive, &private_key_path, &rejected, &opts).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ArchryptoError>(),
+            Some(ArchryptoError::ManifestVerificationFailed { entry, .. }) if *entry == tampered_entry
+        ));
+        // 展開を始める前に照合するため、何も書き出さない
+        assert!(!rejected.join(&tampered_entry).exists());
+
+        edit_manifest_json(&manifest, |json| {
+            json.entries.remove(0);
+            json.entries.push(ManifestEntry { path: "ghost.txt".to_string(), size: 0, sha256: "0".repeat(64), modified: None });
+        });
+        let err = extract_files(&archive, &private_key_path, &rejected, &opts).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ArchryptoError>(),
+            Some(ArchryptoError::ManifestVerificationFailed { entry, reason }) if entry == "ghost.txt" && reason.contains("missing")
+        ));
+    }
+
+    #[test]
+    fn verify_archive_integrity_reports_each_kind_of_tampering() {
+        let work = tempfile::tempdir().unwrap();
+        let (archive, manifest, private_key_path) = compress_with_manifest_json(work.path());
+
+        let report = verify_archive_integrity(&archive, &manifest, &private_key_path).unwrap();
+        assert_eq!(report, IntegrityReport { all_entries_present: true, ..Default::default() });
+
+        let mut mismatched = String::new();
+        let mut unrecorded = String::new();
+        edit_manifest_json(&manifest, |json| {
+            mismatched = json.entries[0].path.clone();
+            json.entries[0].sha256 = "0".repeat(64);
+            unrecorded = json.entries.remove(1).path;
+            json.entries.push(ManifestEntry { path: "ghost.txt".to_string(), size: 0, sha256: "0".repeat(64), modified: None });
+        });
+        let report = verify_archive_integrity(&archive, &manifest, &private_key_path).unwrap();
+        assert_eq!(
+            report,
+            IntegrityReport {
+                all_entries_present: false,
+                missing_entries: vec!["ghost.txt".to_string()],
+                hash_mismatches: vec![mismatched],
+                unexpected_entries: vec![unrecorded],
+                tampered: true,
+            }
+        );
+    }
+
+    #[test]
+    fn diff_archives_classifies_every_entry() {
+        let dir = nested_tree();
+        let work = tempfile::tempdir().unwrap();
+        let (public_key, private_key) = test_utils::generate_insecure_test_keypair();
+        let public_key_path = work.path().join("public.pem");
+        let private_key_path = work.path().join("private.pem");
+        save_public_key(&public_key, &public_key_path).unwrap();
+        save_private_key(&private_key, &private_key_path).unwrap();
+        let old = work.path().join("old.acrp");
+        let new = work.path().join("new.acrp");
+        compress_files(&old, &public_key_path, &[dir.path()], &CompressOptions::for_testing()).unwrap();
+
+        fs::write(dir.path().join("a.txt"), b"changed").unwrap();
+        fs::remove_file(dir.path().join("d1").join("b.txt")).unwrap();
+        fs::write(dir.path().join("e.txt"), b"e").unwrap();
+        compress_files(&new, &public_key_path, &[dir.path()], &CompressOptions::for_testing()).unwrap();
+
+        let base_name = dir.path().file_name().unwrap().to_string_lossy().into_owned();
+        let changes: Vec<(ChangeType, String)> = diff_archives(&old, &new, &private_key_path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.change_type, entry.entry_name.strip_prefix(&format!("{}/", base_name)).unwrap().to_string()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (ChangeType::Modified, "a.txt".to_string()),
+                (ChangeType::Deleted, "d1/b.txt".to_string()),
+                (ChangeType::Unchanged, "d1/d2/c.txt".to_string()),
+                (ChangeType::Unchanged, "d1/d2/d3/d.txt".to_string()),
+                (ChangeType::Added, "e.txt".to_string()),
+            ]
+        );
+        assert!(diff_archives(&old, &old, &private_key_path).unwrap().iter().all(|entry| entry.change_type == ChangeType::Unchanged));
+    }
+
+    #[test]
+    fn long_comment_is_truncated_and_reported_in_stats() {
+        let dir = nested_tree();
+        let (public_key, _) = test_utils::generate_insecure_test_keypair();
+        let mut archive = Vec::new();
+        let stats = compress_to_writer(&mut archive, &public_key, &[dir.path().to_path_buf()], &CompressOptions::for_testing()).unwrap();
+        assert!(!stats.comment_truncated);
+
+        let opts = CompressOptions { comment: Some("x".repeat(MAX_COMMENT_LEN + 1)), ..CompressOptions::for_testing() };
+        archive.clear();
+        let stats = compress_to_writer(&mut archive, &public_key, &[dir.path().to_path_buf()], &opts).unwrap();
+        assert!(stats.comment_truncated);
+        assert_eq!(ArchiveHeader::parse(&archive).unwrap().0.comment, Some("x".repeat(MAX_COMMENT_LEN)));
+    }
+
+    #[test]
+    fn post_extract_hook_failure_is_attached_to_the_extraction_error() {
+        let work = tempfile::tempdir().unwrap();
+        let (archive, manifest, private_key_path) = compress_with_manifest_json(work.path());
+        edit_manifest_json(&manifest, |json| json.entries[0].sha256 = "0".repeat(64));
+        let opts = ExtractOptions {
+            verify_manifest: Some(manifest),
+            post_extract_hook: Some("false".to_string()),
+            post_extract_hook_on_error: true,
+            ..Default::default()
+        };
+
+        let err = extract_files(&archive, &private_key_path, work.path().join("extracted"), &opts).unwrap_err();
+        assert!(matches!(err.downcast_ref::<ArchryptoError>(), Some(ArchryptoError::ManifestVerificationFailed { .. })));
+        assert!(err.to_string().starts_with("post_extract_hook also failed"), "{:#}", err);
+    }
+
+    #[test]
+    fn threaded_extraction_keeps_the_last_duplicate_like_single_threaded() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..16 {
+            let sub = dir.path().join(format!("d{:02}", i));
+            fs::create_dir_all(&sub).unwrap();
+            fs::write(sub.join("same.txt"), format!("{:02}", i).repeat(64 * 1024)).unwrap();
+            fs::write(sub.join(format!("unique{:02}.txt", i)), format!("{}", i)).unwrap();
+        }
+        let work = tempfile::tempdir().unwrap();
+        let (public_key, private_key) = test_utils::generate_test_keypair();
+        let public_key_path = work.path().join("public.pem");
+        let private_key_path = work.path().join("private.pem");
+        save_public_key(&public_key, &public_key_path).unwrap();
+        save_private_key(&private_key, &private_key_path).unwrap();
+        let archive = work.path().join("archive.acrp");
+        let opts = CompressOptions { sort_entries: true, ..CompressOptions::for_testing() };
+        compress_files(&archive, &public_key_path, &[dir.path()], &opts).unwrap();
+
+        for extract_threads in [1, 4] {
+            let extracted = work.path().join(format!("extracted{}", extract_threads));
+            let opts = ExtractOptions { flatten: true, overwrite_policy: OverwritePolicy::Overwrite, extract_threads, ..Default::default() };
+            let stats = extract_files(&archive, &private_key_path, &extracted, &opts).unwrap();
+            assert_eq!(stats.files_written, 32);
+            assert_eq!(fs::read_to_string(extracted.join("same.txt")).unwrap(), "15".repeat(64 * 1024));
+            for i in 0..16 {
+                assert_eq!(fs::read_to_string(extracted.join(format!("unique{:02}.txt", i))).unwrap(), i.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn compress_preencrypted_zip_wraps_the_zip_unchanged() {
+        let work = tempfile::tempdir().unwrap();
+        let zip_path = work.path().join("input.zip");
+        let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.add_directory("docs/", SimpleFileOptions::default()).unwrap();
+        zip.start_file("docs/readme.txt", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"readme").unwrap();
+        zip.finish().unwrap();
+        let (public_key, private_key) = test_utils::generate_test_keypair();
+        let public_key_path = work.path().join("public.pem");
+        save_public_key(&public_key, &public_key_path).unwrap();
+        let output = work.path().join("wrapped.acrp");
+
+        let stats = compress_preencrypted_zip(&zip_path, &public_key_path, &output).unwrap();
+        assert_eq!((stats.files_written, stats.total_bytes), (1, 6));
+        assert_eq!(stats.output_path, Some(output.clone()));
+        assert_eq!(decrypt_with_private_key(&fs::read(&output).unwrap(), &private_key).unwrap(), fs::read(&zip_path).unwrap());
+    }
+}
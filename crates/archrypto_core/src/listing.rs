@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use anyhow::Result;
+use zip::ZipArchive;
+
+use crate::{content_type, decrypt_with_private_key, dedup, load_private_key};
+
+/// `list_archive_entries` で返す、アーカイブ内の1つのファイルの情報.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// ZIP内のエントリ名
+    pub name: String,
+    /// 非圧縮時のバイト数（重複排除したエントリは参照先のバイト数）
+    pub size: u64,
+    /// `CompressOptions::detect_content_type` で記録した MIME タイプ。記録されていない場合は None。
+    pub content_type: Option<String>,
+}
+
+/// `.acrp` ファイルを復号し、格納されているファイルの一覧を返します.
+///
+/// ファイルの内容は読み出さず、ZIPの中央ディレクトリの情報のみを使用します。
+///
+/// # Arguments
+///
+/// * `archive` - `.acrp` ファイルのパス。
+/// * `private_key` - 復号に使用する秘密鍵ファイルのパス。
+///
+/// # Returns
+///
+/// ファイルの情報を、ZIP内の順序で返します（ディレクトリを除く）。
+///
+/// # Errors
+///
+/// ファイルの読み込み、秘密鍵のパース、復号、またはZIPの解析に失敗した場合にエラーを返します。
+pub fn list_archive_entries(archive: impl AsRef<Path>, private_key: impl AsRef<Path>) -> Result<Vec<ArchiveEntry>> {
+    let private_key = load_private_key(private_key.as_ref())?;
+    let zip_data = decrypt_with_private_key(&fs::read(archive.as_ref())?, &private_key)?;
+    let mut zip = ZipArchive::new(io::Cursor::new(zip_data))?;
+    let mut entries: Vec<ArchiveEntry> = Vec::new();
+    // 重複排除したエントリは参照先のエントリのサイズと MIME タイプを使用する
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for i in 0..zip.len() {
+        let file = zip.by_index_raw(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let source = dedup::source_entry(file.extra_data()).and_then(|source| positions.get(&source).copied());
+        let entry = match source {
+            Some(position) => ArchiveEntry { name: file.name().to_string(), ..entries[position].clone() },
+            None => ArchiveEntry {
+                name: file.name().to_string(),
+                size: file.size(),
+                content_type: content_type::from_extra_data(file.extra_data()),
+            },
+        };
+        positions.insert(entry.name.clone(), entries.len());
+        entries.push(entry);
+    }
+    Ok(entries)
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::timefmt::format_iso8601;
+use crate::{dedup, ArchryptoError};
+
+/// ZIP内に埋め込むマニフェストのエントリ名.
+pub const EMBEDDED_MANIFEST_NAME: &str = "MANIFEST.sha256";
+
+/// 書き込んだデータの SHA-256 を計算しながら、内側の Writer へ書き込みます.
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    /// 内側の Writer をラップします.
+    pub fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// これまでに書き込んだデータの SHA-256 を16進数文字列で返します.
+    pub fn finalize_hex(self) -> String {
+        to_hex(&self.hasher.finalize())
+    }
+
+    /// 内側の Writer と、これまでに書き込んだデータの SHA-256（16進数文字列）を返します.
+    pub fn into_parts(self) -> (W, String) {
+        (self.inner, to_hex(&self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// バイト列を小文字の16進数文字列に変換します.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `sha256sum` 互換の1行（`<sha256>  <エントリ名>`）を作成します.
+pub fn format_line(sha256_hex: &str, entry_name: &str) -> String {
+    format!("{}  {}\n", sha256_hex, entry_name)
+}
+
+/// `sha256sum` 互換のマニフェストをパースし、エントリ名から SHA-256 へのマップを返します.
+///
+/// # Errors
+///
+/// 行の形式が不正な場合にエラーを返します。
+pub fn parse(content: &str) -> Result<HashMap<String, String>> {
+    let mut entries = HashMap::new();
+    for line in content.lines().filter(|line| !line.is_empty()) {
+        let (hash, name) = line
+            .split_once("  ")
+            .ok_or_else(|| anyhow!("Invalid manifest line: {}", line))?;
+        entries.insert(name.to_string(), hash.to_ascii_lowercase());
+    }
+    Ok(entries)
+}
+
+/// アーカイブとは別に書き出すマニフェストファイル（`<出力>.manifest.json`）の形式のバージョン.
+pub const MANIFEST_JSON_VERSION: u32 = 1;
+
+/// `<出力>.manifest.json` に記録する1つのエントリ.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// ZIP内のエントリ名
+    pub path: String,
+    /// 非圧縮時のバイト数
+    pub size: u64,
+    /// 内容の SHA-256（小文字の16進数文字列）
+    pub sha256: String,
+    /// 元のファイルの更新日時（ISO 8601、UTC）。取得できない場合は None。
+    pub modified: Option<String>,
+}
+
+/// `<出力>.manifest.json` の内容.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestJson {
+    /// 形式のバージョン（`MANIFEST_JSON_VERSION`）
+    pub version: u32,
+    /// マニフェストを作成した日時（ISO 8601、UTC）
+    pub created_at: String,
+    /// 格納したエントリ（格納順）
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// 圧縮中に格納したエントリの情報を集め、埋め込み用の `sha256sum` 形式や JSON 形式のマニフェストを作成します.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestWriter {
+    entries: Vec<ManifestEntry>,
+}
+
+impl ManifestWriter {
+    /// 空のマニフェストを作成します.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 格納したエントリを追加します.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - ZIP内のエントリ名。
+    /// * `size` - 非圧縮時のバイト数。
+    /// * `sha256_hex` - 内容の SHA-256（16進数文字列）。
+    /// * `modified` - 元のファイルの更新日時。
+    pub fn add(&mut self, path: &str, size: u64, sha256_hex: String, modified: Option<SystemTime>) {
+        self.entries.push(ManifestEntry {
+            path: path.to_string(),
+            size,
+            sha256: sha256_hex,
+            modified: modified.map(format_iso8601),
+        });
+    }
+
+    /// これまでに追加したエントリ.
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// ZIP内に埋め込む `sha256sum` 互換のマニフェストを作成します.
+    pub fn to_sha256sum(&self) -> String {
+        self.entries.iter().map(|entry| format_line(&entry.sha256, &entry.path)).collect()
+    }
+
+    /// 現在時刻を作成日時として、JSON 形式のマニフェストを作成します.
+    ///
+    /// # Errors
+    ///
+    /// JSON へのシリアライズに失敗した場合にエラーを返します。
+    pub fn to_json(&self) -> Result<String> {
+        let manifest = ManifestJson {
+            version: MANIFEST_JSON_VERSION,
+            created_at: format_iso8601(SystemTime::now()),
+            entries: self.entries.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&manifest)?)
+    }
+
+    /// JSON 形式のマニフェストを `path` に書き出します.
+    ///
+    /// # Errors
+    ///
+    /// シリアライズまたはファイルの書き込みに失敗した場合にエラーを返します。
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+/// アーカイブのパスに `.manifest.json` を付けた、マニフェストファイルのパスを返します.
+pub fn manifest_json_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
+
+/// `ManifestWriter::write_json` で書き出したマニフェストJSONを読み込みます.
+///
+/// # Errors
+///
+/// ファイルの読み込みまたはパースに失敗した場合にエラーを返します。
+pub(crate) fn read_manifest_json(manifest_path: &Path) -> Result<ManifestJson> {
+    let content = fs::read_to_string(manifest_path)?;
+    serde_json::from_str(&content).map_err(|e| anyhow!("Failed to parse manifest {}: {}", manifest_path.display(), e))
+}
+
+/// マニフェストJSONの全てのエントリが、復号したZIPに存在し SHA-256 が一致するかを確認します.
+///
+/// ZIPにのみ存在するエントリはエラーにしません。
+///
+/// # Arguments
+///
+/// * `archive` - 復号したZIP。
+/// * `manifest_path` - `ManifestWriter::write_json` で書き出したマニフェストJSONのパス。
+///
+/// # Errors
+///
+/// * マニフェストの読み込みまたはパースに失敗した場合。
+/// * エントリが存在しない、または SHA-256 が一致しない場合（`ArchryptoError::ManifestVerificationFailed`）。
+pub(crate) fn verify_manifest_json<R: Read + Seek>(archive: &mut ZipArchive<R>, manifest_path: &Path) -> Result<()> {
+    let manifest = read_manifest_json(manifest_path)?;
+    for entry in &manifest.entries {
+        let fail = |reason: String| ArchryptoError::ManifestVerificationFailed { entry: entry.path.clone(), reason };
+        let source = match archive.by_name(&entry.path) {
+            Ok(file) => dedup::source_entry(file.extra_data()),
+            Err(ZipError::FileNotFound) => return Err(fail("entry is missing from the archive".to_string()).into()),
+            Err(e) => return Err(e.into()),
+        };
+        // 重複排除したエントリは内容を持たないため、参照先のエントリの内容を照合する
+        let mut file = archive.by_name(source.as_deref().unwrap_or(&entry.path))?;
+        let mut writer = HashingWriter::new(io::sink());
+        io::copy(&mut file, &mut writer)?;
+        let actual = writer.finalize_hex();
+        if !actual.eq_ignore_ascii_case(&entry.sha256) {
+            return Err(fail(format!("SHA-256 mismatch: expected {}, found {}", entry.sha256, actual)).into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use log::debug;
+use sha2::{Digest, Sha256};
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+use crate::manifest::{to_hex, ManifestWriter, EMBEDDED_MANIFEST_NAME};
+use crate::{
+    check_key_size, check_output_writable, decrypt_archive_entries, encrypt_with_public_key, load_public_key,
+    validate_extension, ArchiveHeader, ArchiveStats, ArchryptoError, KeySizeOverride, EXTENTION,
+};
+
+/// `merge_archives` で、複数の入力アーカイブに同じ名前のエントリがある場合の挙動を表します.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// 先に指定したアーカイブのエントリを残します。
+    #[default]
+    FirstWins,
+    /// 後に指定したアーカイブのエントリで置き換えます（格納順は最初に現れた位置のままです）。
+    LastWins,
+    /// エラーを返して結合を中断します（`ArchryptoError::EntryConflict`）。
+    ErrorOnConflict,
+}
+
+/// 複数の `.acrp` ファイルを復号してエントリを1つのZIPにまとめ、公開鍵で暗号化し直して書き出します.
+///
+/// 全ての入力アーカイブは同じ秘密鍵で復号できる必要があります。エントリは入力の順に、各アーカイブ内の順で格納します。
+/// 入力アーカイブのヘッダ（コメントなど）は引き継ぎません。
+/// 入力アーカイブの埋め込みマニフェスト（`MANIFEST.sha256`）は通常のエントリとして結合せず、
+/// いずれかの入力が持っていた場合は、結合後のエントリから作り直して格納します。
+///
+/// # Arguments
+///
+/// * `inputs` - 結合する `.acrp` ファイルのパスのリスト。
+/// * `private_key` - 入力アーカイブの復号に使用する秘密鍵ファイルのパス。
+/// * `output` - 結合したアーカイブの出力先パス。拡張子は ".acrp" である必要があります。
+/// * `public_key` - 暗号化に使用する公開鍵ファイルのパス。
+/// * `conflict_policy` - 同じ名前のエントリが複数のアーカイブにある場合の挙動。
+///
+/// # Returns
+///
+/// 結合したアーカイブに格納したファイル数などの統計情報を返します。
+///
+/// # Errors
+///
+/// * 出力先の拡張子が ".acrp" でない場合、または出力先に書き込めない場合。
+/// * 公開鍵のビット数が `MIN_RSA_KEY_BITS` 未満の場合（`ArchryptoError::KeyTooSmall`）。
+/// * `ConflictPolicy::ErrorOnConflict` で同じ名前のエントリがあった場合（`ArchryptoError::EntryConflict`）。
+/// * いずれかの入力アーカイブの読み込みや復号、ZIP圧縮、暗号化、書き出しに失敗した場合にエラーを返します。
+pub fn merge_archives(
+    inputs: &[impl AsRef<Path>],
+    private_key: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    public_key: impl AsRef<Path>,
+    conflict_policy: ConflictPolicy,
+) -> Result<ArchiveStats> {
+    let output = output.as_ref();
+    if !validate_extension(output)? {
+        return Err(anyhow!("outputpath extention does not \".{}\"", EXTENTION));
+    }
+    check_output_writable(output)?;
+    let public_key = load_public_key(public_key.as_ref())?;
+    check_key_size(&public_key, KeySizeOverride::Enforce)?;
+
+    // 格納するエントリと、エントリ名から entries 内の位置への対応
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut embed_manifest = false;
+    for input in inputs {
+        let input = input.as_ref();
+        let input_entries = decrypt_archive_entries(input, private_key.as_ref())?;
+        debug!("Merging {} entries from {}", input_entries.len(), input.display());
+        for (name, data) in input_entries {
+            // 各入力のマニフェストは自身のエントリしか含まないため、結合後に作り直す
+            if name == EMBEDDED_MANIFEST_NAME {
+                embed_manifest = true;
+                continue;
+            }
+            match positions.get(&name) {
+                None => {
+                    positions.insert(name.clone(), entries.len());
+                    entries.push((name, data));
+                }
+                Some(&position) => match conflict_policy {
+                    ConflictPolicy::FirstWins => {}
+                    ConflictPolicy::LastWins => entries[position].1 = data,
+                    ConflictPolicy::ErrorOnConflict => {
+                        return Err(ArchryptoError::EntryConflict { entry: name }.into());
+                    }
+                },
+            }
+        }
+    }
+
+    let mut stats = ArchiveStats::default();
+    let mut manifest = ManifestWriter::new();
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in &entries {
+        zip.start_file(name.as_str(), SimpleFileOptions::default())?;
+        zip.write_all(data)?;
+        if embed_manifest {
+            manifest.add(name, data.len() as u64, to_hex(&Sha256::digest(data)), None);
+        }
+        stats.files += 1;
+        stats.files_written += 1;
+        stats.total_bytes += data.len() as u64;
+    }
+    if embed_manifest {
+        zip.start_file(EMBEDDED_MANIFEST_NAME, SimpleFileOptions::default())?;
+        zip.write_all(manifest.to_sha256sum().as_bytes())?;
+    }
+    let zip_data = zip.finish()?.into_inner();
+
+    let encrypted = encrypt_with_public_key(&zip_data, &public_key, &ArchiveHeader::new())?;
+    File::create(output)?.write_all(&encrypted)?;
+    stats.output_path = Some(output.to_path_buf());
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{compress_files, extract_files, save_private_key, save_public_key, test_utils, CompressOptions, ExtractOptions};
+
+    /// テスト用の鍵ペアを `work` に保存し、公開鍵と秘密鍵のパスを返します。
+    fn save_test_keys(work: &Path) -> (PathBuf, PathBuf) {
+        let (public_key, private_key) = test_utils::generate_test_keypair();
+        let public_key_path = work.join("public.pem");
+        let private_key_path = work.join("private.pem");
+        save_public_key(&public_key, &public_key_path).unwrap();
+        save_private_key(&private_key, &private_key_path).unwrap();
+        (public_key_path, private_key_path)
+    }
+
+    /// `work/<name>/root` に `files` を作成して圧縮し、アーカイブのパスを返します。エントリ名は `root/...` になります。
+    fn shard(work: &Path, name: &str, files: &[(&str, &[u8])], embed_manifest: bool) -> PathBuf {
+        let root = work.join(name).join("root");
+        fs::create_dir_all(&root).unwrap();
+        for (file, data) in files {
+            fs::write(root.join(file), data).unwrap();
+        }
+        let archive = work.join(format!("{}.acrp", name));
+        let opts = CompressOptions { embed_manifest, sort_entries: true, ..CompressOptions::for_testing() };
+        compress_files(&archive, work.join("public.pem"), &[root], &opts).unwrap();
+        archive
+    }
+
+    /// 2つのシャードを `conflict_policy` で結合し、結合したアーカイブのエントリを返します。
+    fn merge_overlapping_shards(conflict_policy: ConflictPolicy) -> Result<Vec<(String, Vec<u8>)>> {
+        let work = tempfile::tempdir().unwrap();
+        let (public_key_path, private_key_path) = save_test_keys(work.path());
+        let first = shard(work.path(), "first", &[("a.txt", b"a"), ("shared.txt", b"first")], false);
+        let second = shard(work.path(), "second", &[("b.txt", b"b"), ("shared.txt", b"second")], false);
+        let merged = work.path().join("merged.acrp");
+        let stats = merge_archives(&[first, second], &private_key_path, &merged, &public_key_path, conflict_policy)?;
+        assert_eq!(stats.files_written, 3);
+        assert_eq!(stats.output_path, Some(merged.clone()));
+        decrypt_archive_entries(&merged, &private_key_path)
+    }
+
+    fn entries(items: &[(&str, &[u8])]) -> Vec<(String, Vec<u8>)> {
+        items.iter().map(|(name, data)| (name.to_string(), data.to_vec())).collect()
+    }
+
+    #[test]
+    fn first_wins_keeps_the_earlier_entry() {
+        assert_eq!(
+            merge_overlapping_shards(ConflictPolicy::FirstWins).unwrap(),
+            entries(&[("root/a.txt", b"a"), ("root/shared.txt", b"first"), ("root/b.txt", b"b")])
+        );
+    }
+
+    #[test]
+    fn last_wins_replaces_the_entry_in_its_original_position() {
+        assert_eq!(
+            merge_overlapping_shards(ConflictPolicy::LastWins).unwrap(),
+            entries(&[("root/a.txt", b"a"), ("root/shared.txt", b"second"), ("root/b.txt", b"b")])
+        );
+    }
+
+    #[test]
+    fn error_on_conflict_reports_the_conflicting_entry() {
+        let err = merge_overlapping_shards(ConflictPolicy::ErrorOnConflict).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ArchryptoError>(),
+            Some(ArchryptoError::EntryConflict { entry }) if entry == "root/shared.txt"
+        ));
+    }
+
+    #[test]
+    fn embedded_manifests_are_rebuilt_for_the_merged_entries() {
+        let work = tempfile::tempdir().unwrap();
+        let (public_key_path, private_key_path) = save_test_keys(work.path());
+        let first = shard(work.path(), "first", &[("a.txt", b"a"), ("shared.txt", b"first")], true);
+        let second = shard(work.path(), "second", &[("b.txt", b"b"), ("shared.txt", b"second")], true);
+        let disjoint = shard(work.path(), "disjoint", &[("c.txt", b"c")], true);
+
+        for (conflict_policy, inputs, shared) in [
+            (ConflictPolicy::FirstWins, vec![first.clone(), second.clone()], Some(&b"first"[..])),
+            (ConflictPolicy::LastWins, vec![first.clone(), second.clone()], Some(&b"second"[..])),
+            (ConflictPolicy::ErrorOnConflict, vec![first.clone(), disjoint.clone()], None),
+        ] {
+            let merged = work.path().join("merged.acrp");
+            merge_archives(&inputs, &private_key_path, &merged, &public_key_path, conflict_policy).unwrap();
+            let merged_entries = decrypt_archive_entries(&merged, &private_key_path).unwrap();
+            let manifests = merged_entries.iter().filter(|(name, _)| name == EMBEDDED_MANIFEST_NAME).count();
+            assert_eq!(manifests, 1, "{:?}", conflict_policy);
+
+            // 作り直したマニフェストが結合後の全エントリと一致するため、展開時の照合に成功する
+            let extracted = tempfile::tempdir().unwrap();
+            let stats = extract_files(&merged, &private_key_path, extracted.path(), &ExtractOptions::default()).unwrap();
+            assert_eq!(stats.files_written, merged_entries.len() - 1);
+            if let Some(shared) = shared {
+                assert_eq!(fs::read(extracted.path().join("root").join("shared.txt")).unwrap(), shared);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+
+use crate::timefmt;
+
+/// 出力ファイル名のテンプレートを展開します.
+///
+/// 使用できるプレースホルダは次の通りです。
+///
+/// * `{date}` - 今日の日付（UTC、`YYYY-MM-DD` 形式）
+/// * `{target}` - 最初の対象パスのベース名
+/// * `{hash}` - 全ての対象パスから計算した SHA-256 の先頭8桁（16進数）
+///
+/// # Arguments
+///
+/// * `template` - 展開するテンプレート（例: `"{date}_{target}_{hash}.acrp"`）。
+/// * `targets` - 圧縮対象となるファイルまたはディレクトリのパスのリスト。
+///
+/// # Returns
+///
+/// 展開した出力ファイルのパスを返します。
+///
+/// # Errors
+///
+/// * 対象パスが空の場合。
+/// * 未知のプレースホルダや閉じられていない `{` が含まれている場合。
+/// * 最初の対象パスのベース名を取得できない場合にエラーを返します。
+pub fn expand_output_template(template: &str, targets: &[PathBuf]) -> Result<PathBuf> {
+    let first = targets
+        .first()
+        .ok_or_else(|| anyhow!("No target paths to name the archive after"))?;
+
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed placeholder in output name template: {}", template))?;
+        match &rest[start + 1..start + end] {
+            "date" => expanded.push_str(&timefmt::today()),
+            "target" => expanded.push_str(&target_name(first)?),
+            "hash" => expanded.push_str(&targets_hash(targets)),
+            other => return Err(anyhow!("Unknown placeholder in output name template: {{{}}}", other)),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+/// 対象パスのベース名を返します. `.` のようにベース名を持たないパスは正規化してから取得します。
+fn target_name(target: &Path) -> Result<String> {
+    let name = match target.file_name() {
+        Some(name) => name.to_owned(),
+        None => target
+            .canonicalize()?
+            .file_name()
+            .ok_or_else(|| anyhow!("Failed to get target name: {}", target.display()))?
+            .to_owned(),
+    };
+    Ok(name.to_string_lossy().into_owned())
+}
+
+/// 全ての対象パスを改行区切りで連結した文字列の SHA-256 の先頭8桁を返します.
+fn targets_hash(targets: &[PathBuf]) -> String {
+    let mut hasher = Sha256::new();
+    for target in targets {
+        hasher.update(target.to_string_lossy().as_bytes());
+        hasher.update(b"\n");
+    }
+    crate::manifest::to_hex(&hasher.finalize()[..4])
+}
+
+/// 暗号化したアーカイブの内容から、`dir` 配下の出力ファイルのパスを求めます.
+///
+/// ファイル名はアーカイブの SHA-256 の先頭8桁（16進数）に拡張子 `.acrp` を付けたものです（`git hash-object` と同様）。
+///
+/// # Arguments
+///
+/// * `sha256_hex` - 出力するアーカイブの SHA-256（16進数文字列）。
+/// * `dir` - 出力先のディレクトリ。
+pub(crate) fn content_hash_path(sha256_hex: &str, dir: &Path) -> PathBuf {
+    dir.join(format!("{}.{}", &sha256_hex[..8], crate::EXTENTION))
+}
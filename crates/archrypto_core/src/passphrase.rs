@@ -0,0 +1,66 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use anyhow::{anyhow, Context, Result};
+
+/// パスフレーズの読み取り元.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassphraseSource {
+    /// 標準エラー出力にプロンプトを表示し、標準入力から1行読み取ります。
+    Interactive,
+    /// ファイルから読み取ります（Docker secrets の `/run/secrets/...` など）。
+    File(PathBuf),
+    /// 指定した名前の環境変数から読み取ります。
+    Env(String),
+    /// 指定した文字列をそのまま使用します。プロセス一覧から見えるため、他の方法を推奨します。
+    Inline(String),
+}
+
+/// パスフレーズを読み取ります.
+///
+/// `Interactive` と `File` では末尾の改行（`\n` または `\r\n`）を1つだけ取り除きます。
+///
+/// # Arguments
+///
+/// * `source` - パスフレーズの読み取り元。
+///
+/// # Errors
+///
+/// * ファイルや標準入力の読み込みに失敗した場合。
+/// * 環境変数が設定されていない、または UTF-8 として解釈できない場合。
+/// * 読み取ったパスフレーズが空の場合にエラーを返します。
+pub fn read_passphrase(source: PassphraseSource) -> Result<Vec<u8>> {
+    let passphrase = match source {
+        PassphraseSource::Interactive => {
+            eprint!("Passphrase: ");
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            trim_newline(line.into_bytes())
+        }
+        PassphraseSource::File(path) => {
+            let bytes = fs::read(&path)
+                .with_context(|| format!("Failed to read passphrase file: {}", path.display()))?;
+            trim_newline(bytes)
+        }
+        PassphraseSource::Env(name) => std::env::var(&name)
+            .map_err(|e| anyhow!("Failed to read passphrase from ${}: {}", name, e))?
+            .into_bytes(),
+        PassphraseSource::Inline(passphrase) => passphrase.into_bytes(),
+    };
+    if passphrase.is_empty() {
+        return Err(anyhow!("Passphrase is empty"));
+    }
+    Ok(passphrase)
+}
+
+/// 末尾の改行を1つだけ取り除きます.
+fn trim_newline(mut bytes: Vec<u8>) -> Vec<u8> {
+    if bytes.last() == Some(&b'\n') {
+        bytes.pop();
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+    }
+    bytes
+}
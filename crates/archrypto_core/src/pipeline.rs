@@ -0,0 +1,226 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Result};
+use tempfile::NamedTempFile;
+
+use crate::{
+    build_header, build_zip, expected_total_bytes, encrypt_file_with_public_key, progress, tree_checksum,
+    validate_extension, validate_target_paths, ArchiveHeader, ArchiveStats, CompressOptions, PublicKeySource, EXTENTION,
+};
+
+/// パイプラインの各ステップ間で受け渡す状態.
+#[derive(Debug, Default)]
+pub struct PipelineContext {
+    /// `Pipeline::run` に渡された圧縮対象のパス
+    pub targets: Vec<PathBuf>,
+    /// 直前のステップが出力した成果物（暗号化済みアーカイブなど）のパス
+    pub artifact: Option<PathBuf>,
+    /// 圧縮ステップの統計情報
+    pub stats: ArchiveStats,
+    /// 圧縮ステップが作成した未暗号化のZIP
+    zip: Option<NamedTempFile>,
+    /// 圧縮ステップが作成した平文ヘッダ
+    header: ArchiveHeader,
+    /// 圧縮ステップの圧縮オプション。暗号化ステップで出力ファイル作成の再試行などに使用します
+    opts: CompressOptions,
+}
+
+/// パイプラインを構成する1つの処理.
+///
+/// ライブラリの利用者は独自のステップを実装して `PipelineBuilder::step` で追加できます。
+pub trait PipelineStep {
+    /// エラーメッセージなどに使用するステップ名を返します.
+    fn name(&self) -> &str;
+
+    /// ステップを実行します. 前のステップの結果は `ctx` から読み取り、自身の結果を `ctx` に書き込みます。
+    ///
+    /// # Errors
+    ///
+    /// 処理に失敗した場合にエラーを返します。以降のステップは実行されません。
+    fn run(&self, ctx: &mut PipelineContext) -> Result<()>;
+}
+
+/// 対象パスを未暗号化のZIPに圧縮するステップ.
+pub struct Compress {
+    pub opts: CompressOptions,
+}
+
+/// 直前の `Compress` ステップのZIPを公開鍵で暗号化し、`output` に書き出すステップ.
+pub struct Encrypt {
+    pub public_key: PathBuf,
+    /// 出力先のパス。拡張子は ".acrp" である必要があります。
+    pub output: PathBuf,
+}
+
+/// 外部コマンドを実行するステップ.
+///
+/// コマンドはシェル経由で実行し、直前の成果物のパスを環境変数 `ARCHRYPT_ARTIFACT` で渡します。
+pub struct Hook {
+    pub cmd: String,
+}
+
+/// 処理結果を1行のログとしてファイルに追記するステップ.
+pub struct Log {
+    pub path: PathBuf,
+}
+
+/// 複数のステップを順に実行するパイプライン.
+///
+/// ```no_run
+/// use archrypto_core::pipeline::{Compress, Encrypt, Log, Pipeline};
+/// # fn main() -> anyhow::Result<()> {
+/// Pipeline::builder()
+///     .step(Compress { opts: Default::default() })
+///     .step(Encrypt { public_key: "pub.pem".into(), output: "out.acrp".into() })
+///     .step(Log { path: "archrypt.log".into() })
+///     .build()
+///     .run(&["docs".into()])?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Pipeline {
+    steps: Vec<Box<dyn PipelineStep>>,
+}
+
+/// `Pipeline` のビルダー.
+#[derive(Default)]
+pub struct PipelineBuilder {
+    steps: Vec<Box<dyn PipelineStep>>,
+}
+
+impl Pipeline {
+    /// 空のパイプラインのビルダーを作成します.
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder::default()
+    }
+
+    /// 各ステップを追加した順に実行します.
+    ///
+    /// # Arguments
+    ///
+    /// * `targets` - 圧縮対象となるファイルまたはディレクトリのパスのリスト。
+    ///
+    /// # Errors
+    ///
+    /// いずれかのステップが失敗した場合、ステップ名を付けたエラーを返します。
+    pub fn run(&self, targets: &[PathBuf]) -> Result<()> {
+        let mut ctx = PipelineContext {
+            targets: targets.to_vec(),
+            ..Default::default()
+        };
+        for step in &self.steps {
+            step.run(&mut ctx)
+                .map_err(|e| anyhow!("Pipeline step '{}' failed: {}", step.name(), e))?;
+        }
+        Ok(())
+    }
+}
+
+impl PipelineBuilder {
+    /// ステップを末尾に追加します.
+    pub fn step(mut self, step: impl PipelineStep + 'static) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// パイプラインを作成します.
+    pub fn build(self) -> Pipeline {
+        Pipeline { steps: self.steps }
+    }
+}
+
+impl PipelineStep for Compress {
+    fn name(&self) -> &str {
+        "compress"
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<()> {
+        validate_target_paths(&ctx.targets, &self.opts)?;
+        let pb = progress::new_bytes_progress_bar(
+            expected_total_bytes(&ctx.targets, &self.opts)?,
+            self.opts.progress_template.as_deref(),
+            self.opts.progress_chars.as_deref(),
+        );
+        let (zip, mut stats) = build_zip(&ctx.targets, &self.opts, &pb, None)?;
+        (ctx.header, stats.comment_truncated) = build_header(&self.opts);
+        if self.opts.embed_input_checksum {
+            ctx.header.input_checksum = Some(tree_checksum::zip_tree_checksum(zip.reopen()?)?);
+        }
+        pb.finish();
+        ctx.artifact = Some(zip.path().to_path_buf());
+        ctx.zip = Some(zip);
+        ctx.stats = stats;
+        ctx.opts = self.opts.clone();
+        Ok(())
+    }
+}
+
+impl PipelineStep for Encrypt {
+    fn name(&self) -> &str {
+        "encrypt"
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<()> {
+        if !validate_extension(&self.output)? {
+            return Err(anyhow!("outputpath extention does not \".{}\"", EXTENTION));
+        }
+        let zip = ctx
+            .zip
+            .take()
+            .ok_or_else(|| anyhow!("No archive to encrypt; add a Compress step first"))?;
+        encrypt_file_with_public_key(zip.path(), &PublicKeySource::FromPath(self.public_key.clone()), &self.output, &ctx.header, &ctx.opts)?;
+        ctx.artifact = Some(self.output.clone());
+        Ok(())
+    }
+}
+
+impl PipelineStep for Hook {
+    fn name(&self) -> &str {
+        "hook"
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<()> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        command.arg(&self.cmd);
+        if let Some(artifact) = &ctx.artifact {
+            command.env("ARCHRYPT_ARTIFACT", artifact);
+        }
+        let status = command.status()?;
+        if !status.success() {
+            return Err(anyhow!("Command `{}` exited with {}", self.cmd, status));
+        }
+        Ok(())
+    }
+}
+
+impl PipelineStep for Log {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let artifact = ctx
+            .artifact
+            .as_ref()
+            .map_or_else(|| "-".to_string(), |path| path.display().to_string());
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(
+            file,
+            "{}\t{}\t{} files\t{} bytes",
+            timestamp, artifact, ctx.stats.files_written, ctx.stats.total_bytes
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+//! 進捗バーの表示.
+//!
+//! `progress-bar` フィーチャーが有効な場合は `indicatif` の進捗バーを使用し、
+//! 無効な場合は何も表示しない同じインターフェースの実装に置き換えます。
+
+use std::io::Read;
+
+#[cfg(feature = "progress-bar")]
+pub use indicatif::ProgressBar;
+
+#[cfg(feature = "progress-bar")]
+const PROGRESS_SETTING: &str = "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})";
+#[cfg(feature = "progress-bar")]
+const BYTES_PROGRESS_SETTING: &str =
+    "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})";
+#[cfg(feature = "progress-bar")]
+const PROGRESS_BAR_CHAR: &str = "#>-";
+
+/// 総数 `len` の進捗バーを作成します.
+///
+/// `template` / `chars` を指定した場合はデフォルトの表示形式の代わりに使用します。
+#[cfg(feature = "progress-bar")]
+pub fn new_progress_bar(len: u64, template: Option<&str>, chars: Option<&str>) -> ProgressBar {
+    let pb = ProgressBar::new(len);
+    pb.set_style(progress_style(PROGRESS_SETTING, template, chars));
+    pb
+}
+
+/// 総バイト数 `len` の、バイト数と転送速度を表示する進捗バーを作成します.
+///
+/// `template` / `chars` を指定した場合はデフォルトの表示形式の代わりに使用します。
+#[cfg(feature = "progress-bar")]
+pub fn new_bytes_progress_bar(len: u64, template: Option<&str>, chars: Option<&str>) -> ProgressBar {
+    let pb = ProgressBar::new(len);
+    pb.set_style(progress_style(BYTES_PROGRESS_SETTING, template, chars));
+    pb
+}
+
+/// 進捗バーの表示形式を作成します.
+///
+/// `template` が不正な場合や、`chars` が2文字未満の場合は警告をログに記録してデフォルトを使用します。
+#[cfg(feature = "progress-bar")]
+fn progress_style(default_template: &str, template: Option<&str>, chars: Option<&str>) -> indicatif::ProgressStyle {
+    let default_style = || indicatif::ProgressStyle::with_template(default_template).unwrap();
+    let style = match template.map(indicatif::ProgressStyle::with_template) {
+        Some(Ok(style)) => style,
+        Some(Err(e)) => {
+            log::warn!("Invalid progress bar template ({}); using the default", e);
+            default_style()
+        }
+        None => default_style(),
+    };
+    let chars = match chars {
+        Some(chars) if chars.chars().count() >= 2 => chars,
+        Some(chars) => {
+            log::warn!("Progress bar characters {:?} must have at least 2 characters; using the default", chars);
+            PROGRESS_BAR_CHAR
+        }
+        None => PROGRESS_BAR_CHAR,
+    };
+    style.progress_chars(chars)
+}
+
+/// 読み込んだバイト数だけ `pb` を進めるリーダーでラップします.
+#[cfg(feature = "progress-bar")]
+pub fn wrap_read<R: Read>(pb: &ProgressBar, reader: R) -> impl Read {
+    pb.wrap_read(reader)
+}
+
+/// 総数 `len` の進捗バーを作成します（`progress-bar` フィーチャー無効時は何も表示しません）.
+#[cfg(not(feature = "progress-bar"))]
+pub fn new_progress_bar(len: u64, _template: Option<&str>, _chars: Option<&str>) -> ProgressBar {
+    ProgressBar::new(len)
+}
+
+/// 総バイト数 `len` の進捗バーを作成します（`progress-bar` フィーチャー無効時は何も表示しません）.
+#[cfg(not(feature = "progress-bar"))]
+pub fn new_bytes_progress_bar(len: u64, _template: Option<&str>, _chars: Option<&str>) -> ProgressBar {
+    ProgressBar::new(len)
+}
+
+/// 進捗バーを表示しないため、リーダーをそのまま返します.
+#[cfg(not(feature = "progress-bar"))]
+pub fn wrap_read<R: Read>(_pb: &ProgressBar, reader: R) -> impl Read {
+    reader
+}
+
+/// `progress-bar` フィーチャー無効時に使用する、何も表示しない進捗バー.
+#[cfg(not(feature = "progress-bar"))]
+#[derive(Debug, Clone, Default)]
+pub struct ProgressBar;
+
+#[cfg(not(feature = "progress-bar"))]
+impl ProgressBar {
+    pub fn new(_len: u64) -> Self {
+        ProgressBar
+    }
+
+    pub fn inc(&self, _delta: u64) {}
+
+    pub fn inc_length(&self, _delta: u64) {}
+
+    pub fn enable_steady_tick(&self, _interval: std::time::Duration) {}
+
+    pub fn finish(&self) {}
+
+    pub fn finish_and_clear(&self) {}
+
+    /// 進捗バーを表示していないため、そのまま `f` を実行します.
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        f()
+    }
+}
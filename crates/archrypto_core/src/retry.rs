@@ -0,0 +1,46 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+use log::debug;
+
+/// 一時的な失敗の可能性があるI/O処理を、失敗時に待機してから再試行します.
+///
+/// NFS や SMB などのネットワークファイルシステムでは、ファイルの作成が一時的に `NotFound` などで失敗することがあります。
+/// `io::ErrorKind::NotFound` と `io::ErrorKind::WouldBlock` の場合のみ再試行し、それ以外のエラーはすぐに返します。
+/// 待機時間は `retry_delay` から始め、再試行のたびに2倍にします。
+///
+/// # Arguments
+///
+/// * `retry_count` - 最初の試行に加えて再試行する最大回数（0 の場合は再試行しません）。
+/// * `retry_delay` - 最初の再試行までの待機時間。
+/// * `what` - ログに表示する処理の説明。
+/// * `op` - 実行するI/O処理。
+///
+/// # Errors
+///
+/// 再試行しないエラーが発生した場合、または全ての試行が失敗した場合に、最後のエラーを返します。
+pub(crate) fn retry_io<T>(
+    retry_count: u32,
+    retry_delay: Duration,
+    what: &str,
+    mut op: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = retry_delay;
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(e) if attempt < retry_count && is_transient(&e) => {
+                attempt += 1;
+                debug!("{} failed ({}); retrying in {:?} ({}/{})", what, e, delay, attempt, retry_count);
+                thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// 再試行の対象とする一時的なエラーかどうかを返します.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::NotFound | io::ErrorKind::WouldBlock)
+}
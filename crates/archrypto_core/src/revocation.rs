@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use rsa::{pkcs8::DecodePublicKey, RsaPublicKey};
+use x509_cert::crl::CertificateList;
+use x509_cert::der::{pem, Decode, Encode};
+use x509_cert::Certificate;
+
+use crate::ArchryptoError;
+
+/// 証明書が失効しているかを確認するトレイト.
+///
+/// 現在の実装は CRL ファイルを使用する `CrlRevocationChecker` のみですが、
+/// OCSP など他の確認方法もこのトレイトを実装することで扱えるようにします。
+pub trait RevocationChecker {
+    /// 指定されたシリアル番号の証明書が失効しているかを確認します.
+    ///
+    /// # Arguments
+    ///
+    /// * `serial` - 証明書のシリアル番号（DER の INTEGER の内容のバイト列）。
+    ///
+    /// # Errors
+    ///
+    /// 失効している場合に `ArchryptoError::KeyRevoked` を返します。
+    fn check_serial(&self, serial: &[u8]) -> Result<()>;
+}
+
+/// X.509 の証明書失効リスト（CRL）に記載されたシリアル番号で失効を確認する `RevocationChecker`.
+///
+/// CRL の署名や有効期限は検証しません。信頼できる経路で取得した CRL を使用してください。
+#[derive(Debug, Clone)]
+pub struct CrlRevocationChecker {
+    /// CRL に記載されている失効した証明書のシリアル番号
+    revoked_serials: Vec<Vec<u8>>,
+}
+
+impl CrlRevocationChecker {
+    /// PEM（`-----BEGIN X509 CRL-----`）または DER 形式の CRL ファイルを読み込みます.
+    ///
+    /// # Arguments
+    ///
+    /// * `crl_path` - CRL ファイルのパス。
+    ///
+    /// # Errors
+    ///
+    /// ファイルの読み込み、または CRL のパースに失敗した場合にエラーを返します。
+    pub fn from_file(crl_path: &Path) -> Result<Self> {
+        let der = read_der(crl_path)?;
+        let crl = CertificateList::from_der(&der).map_err(|e| anyhow!("Failed to parse CRL {:?}: {}", crl_path, e))?;
+        let revoked_serials = crl
+            .tbs_cert_list
+            .revoked_certificates
+            .unwrap_or_default()
+            .iter()
+            .map(|revoked| revoked.serial_number.as_bytes().to_vec())
+            .collect();
+        Ok(CrlRevocationChecker { revoked_serials })
+    }
+}
+
+impl RevocationChecker for CrlRevocationChecker {
+    fn check_serial(&self, serial: &[u8]) -> Result<()> {
+        if self.revoked_serials.iter().any(|revoked| revoked == serial) {
+            return Err(ArchryptoError::KeyRevoked { serial: format_serial(serial) }.into());
+        }
+        Ok(())
+    }
+}
+
+/// 公開鍵として登録された証明書ファイルが、CRL で失効していないかを確認します.
+///
+/// # Arguments
+///
+/// * `certificate_path` - 確認する証明書ファイル（PEM または DER）のパス。
+/// * `crl_path` - CRL ファイルのパス。
+///
+/// # Returns
+///
+/// 証明書のシリアル番号（`01:23:...` 形式）を返します。
+///
+/// # Errors
+///
+/// ファイルが証明書でない場合や読み込みに失敗した場合、または証明書が失効している場合
+/// （`ArchryptoError::KeyRevoked`）にエラーを返します。
+pub fn check_revocation(certificate_path: &Path, crl_path: &Path) -> Result<String> {
+    let certificate = load_certificate(certificate_path)?;
+    let serial = certificate.tbs_certificate.serial_number.as_bytes();
+    CrlRevocationChecker::from_file(crl_path)?.check_serial(serial)?;
+    Ok(format_serial(serial))
+}
+
+/// PEM 形式の X.509 証明書から RSA 公開鍵を取り出します.
+///
+/// # Errors
+///
+/// 証明書のパースに失敗した場合、または RSA 公開鍵を含まない場合にエラーを返します。
+pub(crate) fn public_key_from_certificate_pem(pem: &str) -> Result<RsaPublicKey> {
+    let (_, der) = pem::decode_vec(pem.as_bytes()).map_err(|e| anyhow!("Failed to decode certificate PEM: {}", e))?;
+    let certificate = Certificate::from_der(&der).map_err(|e| anyhow!("Failed to parse certificate: {}", e))?;
+    let spki = certificate.tbs_certificate.subject_public_key_info.to_der()?;
+    Ok(RsaPublicKey::from_public_key_der(&spki)?)
+}
+
+/// 証明書ファイル（PEM または DER）を読み込みます.
+fn load_certificate(path: &Path) -> Result<Certificate> {
+    let der = read_der(path)?;
+    Certificate::from_der(&der).map_err(|_| anyhow!("{:?} is not an X.509 certificate; revocation can only be checked for CA-issued certificates", path))
+}
+
+/// PEM 形式であればデコードし、それ以外は DER としてファイルの内容を返します.
+fn read_der(path: &Path) -> Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    if data.starts_with(b"-----BEGIN") {
+        let (_, der) = pem::decode_vec(&data).map_err(|e| anyhow!("Failed to decode PEM file {:?}: {}", path, e))?;
+        return Ok(der);
+    }
+    Ok(data)
+}
+
+/// シリアル番号を `01:23:ab` のようなコロン区切りの16進数にします.
+fn format_serial(serial: &[u8]) -> String {
+    serial.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")
+}
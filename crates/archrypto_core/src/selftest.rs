@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use rsa::{pkcs8::EncodePrivateKey, pkcs8::LineEnding, rand_core::OsRng, RsaPrivateKey, RsaPublicKey};
+
+use crate::{compress_files, extract_files, save_public_key, CompressOptions, ExtractOptions};
+
+/// セルフテストに使用する RSA 鍵のビット数.
+const SELFTEST_KEY_BITS: usize = 2048;
+/// セルフテストで使用する暗号方式の名前.
+const SELFTEST_CIPHER: &str = "RSA PKCS#1 v1.5 + AES-256-GCM";
+/// セルフテストで圧縮するファイルの名前.
+const SELFTEST_FILE_NAME: &str = "selftest.txt";
+/// セルフテストで圧縮するファイルの内容.
+const SELFTEST_CONTENT: &[u8] = b"archrypt self-test\n";
+
+/// セルフテストの1つの工程の結果.
+#[derive(Debug, Clone)]
+pub struct SelfTestStep {
+    /// 工程の名前
+    pub name: &'static str,
+    /// 工程にかかった時間
+    pub elapsed: Duration,
+    /// 失敗した場合のエラーメッセージ。成功した場合は None です。
+    pub error: Option<String>,
+}
+
+/// セルフテスト全体の結果.
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    /// 使用した RSA 鍵のビット数
+    pub key_bits: usize,
+    /// 使用した暗号方式
+    pub cipher: &'static str,
+    /// 実行した工程の結果。失敗した工程以降は実行しないため含まれません。
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    /// 全ての工程が成功した場合に true を返します.
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|step| step.error.is_none())
+    }
+}
+
+/// 鍵の生成から圧縮・展開までを一時ディレクトリで実行し、インストールが正しく動作するかを確認します.
+///
+/// メモリ上で生成した鍵ペアを一時ディレクトリに書き出し、既知の内容のファイルを `compress_files` で圧縮した後、
+/// `extract_files` で展開して内容が一致するかを確認します。いずれかの工程が失敗した場合、以降の工程は実行しません。
+pub fn run_selftest() -> SelfTestReport {
+    let mut report = SelfTestReport {
+        key_bits: SELFTEST_KEY_BITS,
+        cipher: SELFTEST_CIPHER,
+        steps: Vec::new(),
+    };
+    let workdir = match tempfile::tempdir() {
+        Ok(workdir) => workdir,
+        Err(e) => {
+            report.steps.push(SelfTestStep {
+                name: "create temporary directory",
+                elapsed: Duration::ZERO,
+                error: Some(e.to_string()),
+            });
+            return report;
+        }
+    };
+    let dir = workdir.path();
+    let public_key_path = dir.join("selftest_pub.pem");
+    let private_key_path = dir.join("selftest_priv.pem");
+    let source = dir.join(SELFTEST_FILE_NAME);
+    let archive = dir.join("selftest.acrp");
+    let output_dir = dir.join("extracted");
+
+    let steps: [(&'static str, &dyn Fn() -> Result<()>); 4] = [
+        ("generate key pair", &|| generate_keypair(&public_key_path, &private_key_path)),
+        ("write test file", &|| Ok(fs::write(&source, SELFTEST_CONTENT)?)),
+        ("compress and encrypt", &|| {
+            compress_files(&archive, &public_key_path, std::slice::from_ref(&source), &CompressOptions::default()).map(|_| ())
+        }),
+        ("decrypt and extract", &|| {
+            extract_files(&archive, &private_key_path, &output_dir, &ExtractOptions::default())?;
+            verify_extracted(&output_dir.join(SELFTEST_FILE_NAME))
+        }),
+    ];
+    for (name, step) in steps {
+        let started = Instant::now();
+        let error = step().err().map(|e| e.to_string());
+        let failed = error.is_some();
+        report.steps.push(SelfTestStep { name, elapsed: started.elapsed(), error });
+        if failed {
+            break;
+        }
+    }
+    report
+}
+
+/// 鍵ペアを生成し、PEM 形式でファイルに書き出します.
+fn generate_keypair(public_key_path: &Path, private_key_path: &Path) -> Result<()> {
+    let private_key = RsaPrivateKey::new(&mut OsRng, SELFTEST_KEY_BITS)?;
+    save_public_key(&RsaPublicKey::from(&private_key), public_key_path)?;
+    private_key.write_pkcs8_pem_file(private_key_path, LineEnding::LF)?;
+    Ok(())
+}
+
+/// 展開したファイルの内容が、圧縮前の内容と一致するかを確認します.
+fn verify_extracted(path: &Path) -> Result<()> {
+    if fs::read(path)? != SELFTEST_CONTENT {
+        return Err(anyhow!("Extracted content of {:?} does not match the original", path));
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rsa::{BigUint, RsaPublicKey};
+
+const SSH_RSA: &str = "ssh-rsa";
+
+/// OpenSSH 形式（`authorized_keys` や `id_rsa.pub`）の `ssh-rsa` 公開鍵の1行を `RsaPublicKey` に変換します.
+///
+/// `authorized_keys` の行頭のオプション（`from="..."` など）や末尾のコメントは無視します。
+///
+/// # Arguments
+///
+/// * `ssh_pubkey_line` - `ssh-rsa AAAA... comment` 形式の1行。
+///
+/// # Errors
+///
+/// `ssh-rsa` の鍵を含まない行の場合や、Base64 または鍵データの形式が不正な場合にエラーを返します。
+pub fn import_ssh_pubkey(ssh_pubkey_line: &str) -> Result<RsaPublicKey> {
+    let mut fields = ssh_pubkey_line.split_whitespace();
+    fields
+        .by_ref()
+        .find(|field| *field == SSH_RSA)
+        .ok_or_else(|| anyhow!("Not an ssh-rsa public key"))?;
+    let encoded = fields
+        .next()
+        .ok_or_else(|| anyhow!("ssh-rsa public key data is missing"))?;
+    let blob = STANDARD.decode(encoded)?;
+
+    // 鍵データは SSH のワイヤ形式で string "ssh-rsa", mpint e, mpint n の順に並ぶ
+    let mut rest = blob.as_slice();
+    let key_type = read_ssh_string(&mut rest)?;
+    if key_type != SSH_RSA.as_bytes() {
+        return Err(anyhow!("Key data type does not match ssh-rsa"));
+    }
+    let e = BigUint::from_bytes_be(read_ssh_string(&mut rest)?);
+    let n = BigUint::from_bytes_be(read_ssh_string(&mut rest)?);
+    Ok(RsaPublicKey::new(n, e)?)
+}
+
+/// SSH のワイヤ形式の string（長さ u32 BE + データ）を1つ読み取ります.
+fn read_ssh_string<'a>(data: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len_bytes = data
+        .get(..4)
+        .ok_or_else(|| anyhow!("ssh-rsa key data is truncated"))?;
+    let len = u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+    let value = data
+        .get(4..4 + len)
+        .ok_or_else(|| anyhow!("ssh-rsa key data is truncated"))?;
+    *data = &data[4 + len..];
+    Ok(value)
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// 圧縮対象とその対象ごとの設定を記述した TOML ファイル.
+///
+/// ```toml
+/// [[targets]]
+/// path = "src"
+/// exclude_patterns = ["*.log", "target/"]
+/// max_depth = 3
+///
+/// [[targets]]
+/// path = "README.md"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// 圧縮対象のリスト
+    #[serde(default)]
+    pub targets: Vec<ManifestTarget>,
+}
+
+/// `Manifest` に記述した1つの圧縮対象.
+///
+/// `CompressOptions::target_overrides` に指定すると、この対象のディレクトリ走査時に
+/// 全体の `CompressOptions` の設定を上書きします。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestTarget {
+    /// 圧縮対象のパス。`Manifest::load` で読み込んだ場合、相対パスはマニフェストファイルのディレクトリを基準に解決済みです。
+    pub path: PathBuf,
+    /// 走査時に除外するファイルの `.gitignore` 書式のパターン
+    #[serde(default)]
+    pub exclude_patterns: Vec<String>,
+    /// ディレクトリ走査の最大の深さ。指定した場合は `CompressOptions::max_depth` を上書きします。
+    pub max_depth: Option<usize>,
+}
+
+impl Manifest {
+    /// TOML 形式のマニフェストファイルを読み込みます.
+    ///
+    /// 各対象の相対パスは、カレントディレクトリではなくマニフェストファイルのあるディレクトリを基準に解決します。
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - マニフェストファイルのパス。
+    ///
+    /// # Errors
+    ///
+    /// ファイルの読み込みに失敗した場合や、TOML の形式が不正な場合にエラーを返します。
+    pub fn load(path: &Path) -> Result<Manifest> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+        let mut manifest: Manifest = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse manifest: {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for target in &mut manifest.targets {
+            if target.path.is_relative() {
+                target.path = base_dir.join(&target.path);
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// 全ての圧縮対象のパスを返します.
+    pub fn target_paths(&self) -> Vec<PathBuf> {
+        self.targets.iter().map(|target| target.path.clone()).collect()
+    }
+}
@@ -0,0 +1,46 @@
+//! テスト用のユーティリティ.
+//!
+//! `test-utils` フィーチャーが有効な場合のみ利用できます。
+
+use std::sync::OnceLock;
+use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+
+/// テスト用の鍵ペアの生成に使用するシード.
+const TEST_KEY_SEED: u64 = 0x6172_6368_7279_7074;
+/// テスト用の鍵ペアのビット数.
+const TEST_KEY_BITS: usize = 2048;
+/// `generate_insecure_test_keypair` の鍵ペアのビット数.
+const INSECURE_TEST_KEY_BITS: usize = 512;
+
+static TEST_KEYPAIR: OnceLock<(RsaPublicKey, RsaPrivateKey)> = OnceLock::new();
+static INSECURE_TEST_KEYPAIR: OnceLock<(RsaPublicKey, RsaPrivateKey)> = OnceLock::new();
+
+/// 固定シードから生成したテスト用の RSA 鍵ペアを返します.
+///
+/// 鍵の生成は時間がかかるため、プロセス内で最初の呼び出し時に一度だけ生成し、以降はその複製を返します。
+/// 鍵は常に同じ値になるため、テスト以外の用途には使用しないでください。
+pub fn generate_test_keypair() -> (RsaPublicKey, RsaPrivateKey) {
+    TEST_KEYPAIR
+        .get_or_init(|| {
+            let mut rng = ChaCha8Rng::seed_from_u64(TEST_KEY_SEED);
+            let private_key = RsaPrivateKey::new(&mut rng, TEST_KEY_BITS).expect("failed to generate test key pair");
+            (RsaPublicKey::from(&private_key), private_key)
+        })
+        .clone()
+}
+
+/// 固定シードから生成した 512 ビットのテスト用 RSA 鍵ペアを返します.
+///
+/// `generate_test_keypair` より高速に生成できますが、`MIN_RSA_KEY_BITS` を満たさないため、
+/// `CompressOptions::for_testing()`（`KeySizeOverride::InsecureTestMode`）と組み合わせて使用します。
+pub fn generate_insecure_test_keypair() -> (RsaPublicKey, RsaPrivateKey) {
+    INSECURE_TEST_KEYPAIR
+        .get_or_init(|| {
+            let mut rng = ChaCha8Rng::seed_from_u64(TEST_KEY_SEED);
+            let private_key =
+                RsaPrivateKey::new(&mut rng, INSECURE_TEST_KEY_BITS).expect("failed to generate test key pair");
+            (RsaPublicKey::from(&private_key), private_key)
+        })
+        .clone()
+}
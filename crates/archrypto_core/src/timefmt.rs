@@ -0,0 +1,45 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 今日の日付（UTC）を `YYYY-MM-DD` 形式で返します.
+pub fn today() -> String {
+    let (year, month, day) = civil_from_days(unix_days(SystemTime::now()));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// 時刻を ISO 8601 形式（UTC、`YYYY-MM-DDTHH:MM:SSZ`）の文字列に変換します.
+///
+/// 1970-01-01 より前の時刻は 1970-01-01T00:00:00Z として扱います。
+pub fn format_iso8601(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// 1970-01-01 からの経過日数を返します.
+fn unix_days(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86_400) as i64
+}
+
+/// 1970-01-01 からの経過日数を、グレゴリオ暦の年月日に変換します.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
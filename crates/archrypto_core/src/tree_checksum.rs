@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+use crate::dedup;
+use crate::manifest::EMBEDDED_MANIFEST_NAME;
+use crate::{is_file_entry, mark_visited, target_entry_name, walk_dir, CompressOptions, PathStyle};
+
+/// ツリーチェックサムの計算に使用する1つのファイルの情報（エントリ名, サイズ, 内容の SHA-256）.
+type TreeEntry = (String, u64, [u8; 32]);
+
+/// ファイル群からアーカイブの内容を表す SHA-256 を計算します.
+///
+/// エントリ名でソートした各ファイルについて `エントリ名 | 0x00 | サイズ(u64 BE) | 内容の SHA-256` を連結したものの SHA-256 です。
+fn checksum_entries(mut entries: Vec<TreeEntry>) -> [u8; 32] {
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut hasher = Sha256::new();
+    for (name, size, digest) in &entries {
+        hasher.update(name.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(size.to_be_bytes());
+        hasher.update(digest);
+    }
+    hasher.finalize().into()
+}
+
+/// 作成したZIPデータに格納されたファイルのツリーチェックサムを計算します.
+///
+/// ディレクトリと埋め込みマニフェスト（`MANIFEST.sha256`）は含めません。
+///
+/// # Errors
+///
+/// ZIPデータの読み込みに失敗した場合にエラーを返します。
+pub(crate) fn zip_tree_checksum(zip_data: impl Read + Seek) -> Result<[u8; 32]> {
+    let mut archive = ZipArchive::new(zip_data)?;
+    let mut entries: Vec<TreeEntry> = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() || entry.name() == EMBEDDED_MANIFEST_NAME {
+            continue;
+        }
+        let name = entry.name().to_string();
+        // 重複排除したエントリは、先に格納されている参照先のエントリと同じ内容として扱う
+        if let Some(source) = dedup::source_entry(entry.extra_data()) {
+            let (_, size, digest) = entries
+                .iter()
+                .find(|(entry_name, _, _)| *entry_name == source)
+                .ok_or_else(|| anyhow!("Entry {} refers to a missing entry: {}", name, source))?;
+            entries.push((name, *size, *digest));
+            continue;
+        }
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut entry, &mut hasher)?;
+        entries.push((name, size, hasher.finalize().into()));
+    }
+    Ok(checksum_entries(entries))
+}
+
+/// 圧縮対象のパス群から、アーカイブに埋め込むものと同じツリーチェックサムを計算します.
+///
+/// デフォルトの `CompressOptions`（エントリ名はファイル名・ディレクトリ名から始まる相対パス）で
+/// `compress_files` が格納するファイルを対象とします。`CompressOptions::embed_input_checksum` で
+/// 埋め込んだ値と比較することで、アーカイブが元のディレクトリと同じファイルを含むかを復号せずに確認できます。
+///
+/// # Arguments
+///
+/// * `paths` - 圧縮対象となるファイルまたはディレクトリのパスのリスト。
+///
+/// # Errors
+///
+/// ファイルシステムの読み込みに失敗した場合、または対象パスがファイルでもディレクトリでもない場合にエラーを返します。
+pub fn compute_tree_checksum(paths: &[PathBuf]) -> Result<[u8; 32]> {
+    let opts = CompressOptions::default();
+    let mut visited = HashSet::new();
+    let mut entries = Vec::new();
+    for target in paths {
+        if target.is_file() {
+            if mark_visited(&mut visited, target)? {
+                let name = target_entry_name(target, PathStyle::BaseName, None)?;
+                entries.push(hash_file(target, name.to_string_lossy().into_owned())?);
+            }
+        } else if target.is_dir() {
+            let base_name = target_entry_name(target, PathStyle::BaseName, None)?;
+            for entry in walk_dir(target, &opts)? {
+                let entry = entry?;
+                if is_file_entry(&entry) && mark_visited(&mut visited, entry.path())? {
+                    let relative_path = entry
+                        .path()
+                        .strip_prefix(target)
+                        .map_err(|_| anyhow!("Failed to strip prefix"))?;
+                    let name = Path::new(&base_name).join(relative_path);
+                    entries.push(hash_file(entry.path(), name.to_string_lossy().into_owned())?);
+                }
+            }
+        } else {
+            return Err(anyhow!("Target path is neither file nor directory: {:?}", target.display()));
+        }
+    }
+    Ok(checksum_entries(entries))
+}
+
+/// ファイルのサイズと内容の SHA-256 を計算します.
+fn hash_file(path: &Path, name: String) -> Result<TreeEntry> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((name, size, hasher.finalize().into()))
+}